@@ -0,0 +1,8 @@
+CREATE TABLE issue_delivery_failures (
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issue(newsletter_issue_id) ON DELETE CASCADE,
+    subscriber_email TEXT NOT NULL,
+    n_retries SMALLINT NOT NULL,
+    last_error TEXT NOT NULL,
+    failed_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, subscriber_email)
+);
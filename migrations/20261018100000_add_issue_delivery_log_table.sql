@@ -0,0 +1,12 @@
+CREATE TABLE issue_delivery_log (
+    id BIGSERIAL PRIMARY KEY,
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issue(newsletter_issue_id) ON DELETE CASCADE,
+    subscriber_email TEXT NOT NULL,
+    -- one of 'sent', 'failed' or 'skipped_invalid_address'
+    outcome TEXT NOT NULL,
+    provider_message_id TEXT NULL,
+    error TEXT NULL,
+    attempted_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX issue_delivery_log_newsletter_issue_id_idx ON issue_delivery_log (newsletter_issue_id);
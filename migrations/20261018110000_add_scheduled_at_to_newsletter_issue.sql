@@ -0,0 +1,6 @@
+-- A scheduled issue is published, and its deliveries enqueued,
+-- by the scheduler once `scheduled_at` is reached
+ALTER TABLE newsletter_issue ADD COLUMN scheduled_at TIMESTAMPTZ NULL;
+ALTER TABLE newsletter_issue ALTER COLUMN published_at DROP NOT NULL;
+CREATE INDEX newsletter_issue_scheduled_at_idx ON newsletter_issue (scheduled_at)
+    WHERE published_at IS NULL;
@@ -0,0 +1,15 @@
+-- An issue that is neither published nor scheduled is a draft
+ALTER TABLE newsletter_issue ADD COLUMN created_at TIMESTAMPTZ NULL;
+ALTER TABLE newsletter_issue ADD COLUMN updated_at TIMESTAMPTZ NULL;
+UPDATE newsletter_issue
+SET
+    created_at = COALESCE(published_at, now()),
+    updated_at = COALESCE(published_at, now());
+ALTER TABLE newsletter_issue ALTER COLUMN created_at SET NOT NULL;
+ALTER TABLE newsletter_issue ALTER COLUMN created_at SET DEFAULT now();
+ALTER TABLE newsletter_issue ALTER COLUMN updated_at SET NOT NULL;
+ALTER TABLE newsletter_issue ALTER COLUMN updated_at SET DEFAULT now();
+
+-- Author of the issue, unknown for the issues published before
+ALTER TABLE newsletter_issue ADD COLUMN user_id uuid NULL
+    REFERENCES users (user_id) ON DELETE SET NULL;
@@ -0,0 +1,5 @@
+-- Issues are only listed in the public archive once an admin allows it
+ALTER TABLE newsletter_issue ADD COLUMN show_in_archive BOOLEAN NOT NULL DEFAULT false;
+
+-- Human readable id used by `/issues/{slug}`, set when the issue is published
+ALTER TABLE newsletter_issue ADD COLUMN slug TEXT NULL UNIQUE;
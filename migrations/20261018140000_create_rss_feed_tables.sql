@@ -0,0 +1,17 @@
+-- Feeds polled by the RSS-to-email task. The items found by the first poll
+-- of a feed are only recorded, not turned into issues.
+CREATE TABLE rss_feeds (
+    feed_url TEXT NOT NULL,
+    first_polled_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (feed_url)
+);
+
+CREATE TABLE rss_feed_items (
+    feed_url TEXT NOT NULL REFERENCES rss_feeds (feed_url) ON DELETE CASCADE,
+    guid TEXT NOT NULL,
+    -- NULL for the items found by the first poll
+    newsletter_issue_id uuid NULL
+        REFERENCES newsletter_issue (newsletter_issue_id) ON DELETE SET NULL,
+    seen_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (feed_url, guid)
+);
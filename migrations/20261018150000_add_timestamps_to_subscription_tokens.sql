@@ -0,0 +1,4 @@
+-- Existing tokens get a full TTL from now on
+ALTER TABLE subscription_tokens ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now();
+-- Set once the token has confirmed the subscription, it can't be used again
+ALTER TABLE subscription_tokens ADD COLUMN consumed_at TIMESTAMPTZ NULL;
@@ -0,0 +1,12 @@
+CREATE TABLE subscriber_imports (
+    subscriber_import_id uuid PRIMARY KEY,
+    user_id uuid NULL REFERENCES users (user_id) ON DELETE SET NULL,
+    mode TEXT NOT NULL,
+    n_rows INT NOT NULL,
+    n_imported INT NOT NULL,
+    n_failed INT NOT NULL,
+    n_errors INT NOT NULL,
+    -- CSV with one line per row that needs attention
+    error_report TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
@@ -0,0 +1,29 @@
+-- Mailing lists sharing the same subscribers. The first list holds every
+-- subscriber and issue from before lists existed, and is used whenever
+-- no list is given.
+CREATE TABLE lists (
+    list_id uuid PRIMARY KEY,
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+INSERT INTO lists (list_id, slug, name)
+VALUES ('00000000-0000-0000-0000-000000000001', 'newsletter', 'Newsletter');
+
+-- `status` follows the one of `subscriptions`: issues of a list are only
+-- delivered to confirmed members whose address is confirmed too
+CREATE TABLE list_memberships (
+    list_id uuid NOT NULL REFERENCES lists (list_id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    status TEXT NOT NULL,
+    subscribed_at timestamptz NOT NULL,
+    PRIMARY KEY (list_id, subscriber_id)
+);
+CREATE INDEX list_memberships_subscriber_id_idx ON list_memberships (subscriber_id);
+INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at)
+SELECT '00000000-0000-0000-0000-000000000001', id, status, subscription_at
+FROM subscriptions;
+
+ALTER TABLE newsletter_issue ADD COLUMN list_id uuid NOT NULL
+    DEFAULT '00000000-0000-0000-0000-000000000001'
+    REFERENCES lists (list_id);
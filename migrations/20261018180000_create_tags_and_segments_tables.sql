@@ -0,0 +1,59 @@
+-- Labels such as `rust`, set by the subscribe form or by an admin
+CREATE TABLE subscriber_tags (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (subscriber_id, tag)
+);
+CREATE INDEX subscriber_tags_tag_idx ON subscriber_tags (tag);
+
+-- Subsets of the subscribers that an issue can target within its list.
+-- Empty tag arrays and NULL columns do not filter anything.
+CREATE TABLE segments (
+    segment_id uuid PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    include_tags TEXT[] NOT NULL,
+    exclude_tags TEXT[] NOT NULL,
+    status TEXT NULL,
+    subscribed_from timestamptz NULL,
+    -- Exclusive upper bound
+    subscribed_before timestamptz NULL,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+-- A subscriber belongs to a segment when they have at least one of its
+-- `include_tags`, none of its `exclude_tags`, its status and subscribed
+-- within its range
+CREATE VIEW segment_members AS
+SELECT segments.segment_id, subscriptions.id AS subscriber_id
+FROM segments
+CROSS JOIN subscriptions
+WHERE
+    (
+        cardinality(segments.include_tags) = 0 OR
+        EXISTS (
+            SELECT 1 FROM subscriber_tags
+            WHERE
+                subscriber_tags.subscriber_id = subscriptions.id AND
+                subscriber_tags.tag = ANY (segments.include_tags)
+        )
+    ) AND
+    NOT EXISTS (
+        SELECT 1 FROM subscriber_tags
+        WHERE
+            subscriber_tags.subscriber_id = subscriptions.id AND
+            subscriber_tags.tag = ANY (segments.exclude_tags)
+    ) AND
+    (segments.status IS NULL OR subscriptions.status = segments.status) AND
+    (
+        segments.subscribed_from IS NULL OR
+        subscriptions.subscription_at >= segments.subscribed_from
+    ) AND
+    (
+        segments.subscribed_before IS NULL OR
+        subscriptions.subscription_at < segments.subscribed_before
+    );
+
+-- NULL when the issue goes to the whole list. Segments cannot be deleted
+-- while an issue uses them, it would silently widen the audience.
+ALTER TABLE newsletter_issue ADD COLUMN segment_id uuid NULL
+    REFERENCES segments (segment_id);
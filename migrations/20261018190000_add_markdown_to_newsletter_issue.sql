@@ -0,0 +1,3 @@
+-- The source of issues written in Markdown, NULL for the ones written as
+-- text and HTML. The `text` and `html` columns hold what is sent either way.
+ALTER TABLE newsletter_issue ADD COLUMN markdown TEXT NULL;
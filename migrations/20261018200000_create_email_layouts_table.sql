@@ -0,0 +1,18 @@
+-- What every email is wrapped in: a header, a footer with the postal
+-- address anti-spam laws ask for and the brand colors. The default layout
+-- is used for issues and transactional emails, none being the default
+-- means emails are sent as written.
+CREATE TABLE email_layouts (
+    layout_id uuid PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    header TEXT NOT NULL,
+    footer TEXT NOT NULL,
+    postal_address TEXT NOT NULL,
+    brand_color TEXT NOT NULL,
+    background_color TEXT NOT NULL,
+    is_default BOOLEAN NOT NULL DEFAULT false,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+-- At most one default layout
+CREATE UNIQUE INDEX email_layouts_default_idx ON email_layouts (is_default)
+    WHERE is_default;
@@ -0,0 +1,20 @@
+-- Opens and clicks are only tracked for the issues that opt in
+ALTER TABLE newsletter_issue ADD COLUMN track_engagement BOOLEAN NOT NULL DEFAULT false;
+
+-- One row per event, unique rates count the distinct subscribers
+CREATE TABLE issue_opens (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issue (newsletter_issue_id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    opened_at timestamptz NOT NULL
+);
+CREATE INDEX issue_opens_issue_idx ON issue_opens (newsletter_issue_id);
+
+CREATE TABLE issue_clicks (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issue (newsletter_issue_id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    clicked_at timestamptz NOT NULL
+);
+CREATE INDEX issue_clicks_issue_idx ON issue_clicks (newsletter_issue_id);
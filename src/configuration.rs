@@ -2,15 +2,23 @@ use secrecy::{ExposeSecret, Secret};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 use sqlx::ConnectOptions;
+use std::num::NonZeroU16;
 
 use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
+use std::sync::Arc;
+
+use crate::email_client::{EmailTransport, FileEmailClient, PostmarkEmailClient, SmtpEmailClient};
 
 #[derive(serde::Deserialize, Clone)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub issue_delivery: IssueDeliverySettings,
+    pub subscriptions: SubscriptionSettings,
+    // Turning blog posts into issues is disabled when missing
+    #[serde(default)]
+    pub rss_to_email: Option<RssToEmailSettings>,
     pub redis_uri: Secret<String>,
 }
 
@@ -36,10 +44,101 @@ pub struct DatabaseSettings {
 
 #[derive(serde::Deserialize, Clone)]
 pub struct EmailClientSettings {
+    #[serde(default)]
+    pub provider: EmailProvider,
     pub base_url: String,
     pub sender_email: String,
     pub authorization_token: Secret<String>,
     pub timeout_millisecond: u64,
+    // Only used by the `smtp` provider
+    pub smtp: Option<SmtpSettings>,
+    // Only used by the `file` provider, every email is written there as an `.eml` file
+    pub file_sink_directory: Option<String>,
+    // Prepended to the subject of the test emails sent from a draft
+    #[serde(default = "default_test_subject_prefix")]
+    pub test_subject_prefix: String,
+}
+
+fn default_test_subject_prefix() -> String {
+    "[TEST]".into()
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailProvider {
+    #[default]
+    Postmark,
+    Smtp,
+    File,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
+    #[serde(default)]
+    pub tls: SmtpTls,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    // Plain text, only meant for a relay running on the same host
+    None,
+    #[default]
+    Starttls,
+    Tls,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct IssueDeliverySettings {
+    // Number of attempts after which a delivery is moved to `issue_delivery_failures`
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_retries: i16,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub retry_base_delay_millisecond: u64,
+    // Number of queued deliveries a worker sends with a single request,
+    // 0 is rejected as a worker would never send anything
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub batch_size: NonZeroU16,
+    // Opens and clicks of the issues that opt in are only tracked when enabled
+    #[serde(default)]
+    pub track_engagement: bool,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SubscriptionSettings {
+    // Confirmation links older than that show a page offering to resend one
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub confirmation_token_ttl_hour: i64,
+    // Pending subscribers without any newer confirmation link are deleted
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub pending_subscriber_retention_day: i64,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct RssToEmailSettings {
+    // RSS 2.0 or Atom
+    pub feed_url: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub poll_interval_second: u64,
+    #[serde(default)]
+    pub mode: RssToEmailMode,
+    #[serde(default)]
+    pub show_in_archive: bool,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RssToEmailMode {
+    // An admin reviews and publishes the issue
+    #[default]
+    Draft,
+    // The issue is sent to the subscribers straight away
+    Publish,
 }
 
 impl DatabaseSettings {
@@ -74,16 +173,59 @@ impl EmailClientSettings {
         std::time::Duration::from_millis(self.timeout_millisecond)
     }
 
-    pub fn client(&self) -> EmailClient {
-        let sender_email = self.sender().unwrap();
+    pub fn client(&self) -> Result<Arc<dyn EmailTransport>, anyhow::Error> {
+        let sender_email = self.sender().map_err(anyhow::Error::msg)?;
         let timeout = self.timeout();
-        EmailClient::new(
-            self.base_url.clone(),
-            sender_email,
-            self.authorization_token.clone(),
-            timeout,
-        )
-        .unwrap()
+
+        let client: Arc<dyn EmailTransport> = match self.provider {
+            EmailProvider::Postmark => Arc::new(
+                PostmarkEmailClient::new(
+                    self.base_url.clone(),
+                    sender_email,
+                    self.authorization_token.clone(),
+                    timeout,
+                )
+                .map_err(anyhow::Error::msg)?,
+            ),
+            EmailProvider::Smtp => {
+                let smtp = self.smtp.as_ref().ok_or_else(|| {
+                    anyhow::anyhow!("`email_client.smtp` is required by the smtp provider")
+                })?;
+                Arc::new(SmtpEmailClient::new(smtp, sender_email, timeout)?)
+            }
+            EmailProvider::File => {
+                let directory = self.file_sink_directory.as_ref().ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "`email_client.file_sink_directory` is required by the file provider"
+                    )
+                })?;
+                Arc::new(FileEmailClient::new(directory, sender_email)?)
+            }
+        };
+
+        Ok(client)
+    }
+}
+
+impl SubscriptionSettings {
+    pub fn confirmation_token_ttl(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.confirmation_token_ttl_hour)
+    }
+
+    pub fn pending_subscriber_retention(&self) -> chrono::Duration {
+        chrono::Duration::days(self.pending_subscriber_retention_day)
+    }
+}
+
+impl RssToEmailSettings {
+    pub fn poll_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.poll_interval_second)
+    }
+}
+
+impl IssueDeliverySettings {
+    pub fn retry_base_delay(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.retry_base_delay_millisecond)
     }
 }
 
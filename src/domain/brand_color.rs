@@ -0,0 +1,58 @@
+// A color of an email layout, as a six digits hex code, e.g. `#1a5fb4`.
+// Mail clients do not all understand named colors or the short form.
+#[derive(Debug)]
+pub struct BrandColor(String);
+
+impl BrandColor {
+    pub fn parse(input: String) -> Result<BrandColor, String> {
+        let input = input.trim().to_ascii_lowercase();
+        let is_valid = input.len() == 7
+            && input.starts_with('#')
+            && input[1..].chars().all(|c| c.is_ascii_hexdigit());
+
+        if !is_valid {
+            return Err(format!(
+                "{} is not a valid color, use a hex code like #1a5fb4",
+                input
+            ));
+        }
+
+        Ok(Self(input))
+    }
+}
+
+impl AsRef<str> for BrandColor {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BrandColor;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn six_digits_hex_codes_are_valid() {
+        assert_ok!(BrandColor::parse("#1a5fb4".into()));
+        assert_eq!(
+            BrandColor::parse(" #FFFFFF".into()).unwrap().as_ref(),
+            "#ffffff"
+        );
+    }
+
+    #[test]
+    fn other_colors_are_rejected() {
+        for color in [
+            "red",
+            "#fff",
+            "1a5fb4",
+            "#1a5fb4ff",
+            "#1a5fbg",
+            "",
+            "#1a5fb4;x",
+        ] {
+            assert_err!(BrandColor::parse(color.into()));
+        }
+    }
+}
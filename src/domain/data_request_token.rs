@@ -0,0 +1,116 @@
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Signed like `UnsubscribeToken`, but it grants access to the data of the
+// subscriber so it expires at `expires_at` (a unix timestamp). The purpose
+// is part of the signed message, an unsubscribe token is not accepted here.
+#[derive(Debug)]
+pub struct DataRequestToken(String);
+
+impl DataRequestToken {
+    pub fn generate(subscriber_id: uuid::Uuid, expires_at: i64, secret: &Secret<String>) -> Self {
+        let mac = Self::mac(subscriber_id, expires_at, secret);
+        Self(hex::encode(mac.finalize().into_bytes()))
+    }
+
+    pub fn verify(
+        token: &str,
+        subscriber_id: uuid::Uuid,
+        expires_at: i64,
+        secret: &Secret<String>,
+    ) -> Result<(), anyhow::Error> {
+        let tag = hex::decode(token)?;
+        // `verify_slice` does the comparison in constant time
+        Self::mac(subscriber_id, expires_at, secret).verify_slice(&tag)?;
+        if expires_at < Utc::now().timestamp() {
+            anyhow::bail!("The link has expired");
+        }
+        Ok(())
+    }
+
+    fn mac(subscriber_id: uuid::Uuid, expires_at: i64, secret: &Secret<String>) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(secret.expose_secret().as_bytes())
+            .expect("HMAC can take key of any size");
+        mac.update(b"data-request");
+        mac.update(subscriber_id.as_bytes());
+        mac.update(&expires_at.to_be_bytes());
+        mac
+    }
+}
+
+impl AsRef<str> for DataRequestToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DataRequestToken;
+    use crate::domain::UnsubscribeToken;
+    use chrono::Utc;
+    use claim::{assert_err, assert_ok};
+    use secrecy::Secret;
+
+    fn secret() -> Secret<String> {
+        Secret::new("super-long-and-secret-random-key-needed-to-sign".to_string())
+    }
+
+    fn tomorrow() -> i64 {
+        Utc::now().timestamp() + 24 * 60 * 60
+    }
+
+    #[test]
+    fn a_generated_token_is_valid_until_it_expires() {
+        let id = uuid::Uuid::new_v4();
+        let token = DataRequestToken::generate(id, tomorrow(), &secret());
+        assert_ok!(DataRequestToken::verify(
+            token.as_ref(),
+            id,
+            tomorrow(),
+            &secret()
+        ));
+    }
+
+    #[test]
+    fn an_expired_token_is_rejected() {
+        let id = uuid::Uuid::new_v4();
+        let yesterday = Utc::now().timestamp() - 24 * 60 * 60;
+        let token = DataRequestToken::generate(id, yesterday, &secret());
+        assert_err!(DataRequestToken::verify(
+            token.as_ref(),
+            id,
+            yesterday,
+            &secret()
+        ));
+    }
+
+    #[test]
+    fn the_expiry_cannot_be_extended() {
+        let id = uuid::Uuid::new_v4();
+        let expires_at = Utc::now().timestamp() + 60;
+        let token = DataRequestToken::generate(id, expires_at, &secret());
+        assert_err!(DataRequestToken::verify(
+            token.as_ref(),
+            id,
+            tomorrow(),
+            &secret()
+        ));
+    }
+
+    #[test]
+    fn an_unsubscribe_token_is_rejected() {
+        let id = uuid::Uuid::new_v4();
+        let token = UnsubscribeToken::generate(id, &secret());
+        assert_err!(DataRequestToken::verify(
+            token.as_ref(),
+            id,
+            tomorrow(),
+            &secret()
+        ));
+    }
+}
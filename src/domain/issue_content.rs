@@ -0,0 +1,90 @@
+use crate::markdown::{markdown_to_html, markdown_to_text};
+
+// The parts of an issue. When it is written in Markdown, the parts left
+// empty are generated from it, the others override what would be generated.
+#[derive(Debug)]
+pub struct IssueContent {
+    pub markdown: Option<String>,
+    pub text: String,
+    pub html: String,
+}
+
+impl IssueContent {
+    // Drafts may be saved with missing parts
+    pub fn new(markdown: &str, text: &str, html: &str) -> Self {
+        let markdown = Some(markdown).filter(|m| !m.trim().is_empty());
+        let text = match markdown {
+            Some(markdown) if text.trim().is_empty() => markdown_to_text(markdown),
+            _ => text.to_owned(),
+        };
+        let html = match markdown {
+            Some(markdown) if html.trim().is_empty() => markdown_to_html(markdown),
+            _ => html.to_owned(),
+        };
+
+        Self {
+            markdown: markdown.map(str::to_owned),
+            text,
+            html,
+        }
+    }
+
+    // Issues are sent with both parts
+    pub fn parse(markdown: &str, text: &str, html: &str) -> Result<Self, String> {
+        let content = Self::new(markdown, text, html);
+        if content.text.trim().is_empty() || content.html.trim().is_empty() {
+            return Err(
+                "Write the issue in Markdown, or fill both its text and HTML parts.".into(),
+            );
+        }
+
+        Ok(content)
+    }
+
+    // What the text field of the form holds, empty when the part is generated
+    pub fn text_override(&self) -> &str {
+        match &self.markdown {
+            Some(markdown) if markdown_to_text(markdown) == self.text => "",
+            _ => &self.text,
+        }
+    }
+
+    pub fn html_override(&self) -> &str {
+        match &self.markdown {
+            Some(markdown) if markdown_to_html(markdown) == self.html => "",
+            _ => &self.html,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IssueContent;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn empty_parts_are_generated_from_the_markdown() {
+        let content = IssueContent::new("Hello *you*", "", " ");
+        assert_eq!(content.markdown.as_deref(), Some("Hello *you*"));
+        assert_eq!(content.text, "Hello you");
+        assert!(content.html.contains("<p>Hello <em>you</em></p>"));
+        assert_eq!(content.text_override(), "");
+        assert_eq!(content.html_override(), "");
+    }
+
+    #[test]
+    fn parts_can_be_overridden() {
+        let content = IssueContent::new("Hello *you*", "Hi!", "");
+        assert_eq!(content.text, "Hi!");
+        assert_eq!(content.text_override(), "Hi!");
+        assert_eq!(content.html_override(), "");
+    }
+
+    #[test]
+    fn issues_need_markdown_or_both_parts() {
+        assert_err!(IssueContent::parse("", "Hello", ""));
+        assert_err!(IssueContent::parse(" ", "", "<p>Hello</p>"));
+        assert_ok!(IssueContent::parse("", "Hello", "<p>Hello</p>"));
+        assert_ok!(IssueContent::parse("Hello", "", ""));
+    }
+}
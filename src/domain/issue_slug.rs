@@ -0,0 +1,64 @@
+const MAX_LENGTH: usize = 60;
+
+// The URL friendly version of an issue title, e.g. `Hello, World!` becomes
+// `hello-world`. It is not guaranteed to be unique, issues with the same
+// title get a suffix when the slug is stored.
+#[derive(Debug)]
+pub struct IssueSlug(String);
+
+impl IssueSlug {
+    pub fn from_title(title: &str) -> Self {
+        let mut slug = String::new();
+        for c in title.chars() {
+            if c.is_ascii_alphanumeric() {
+                slug.push(c.to_ascii_lowercase());
+            } else if !slug.is_empty() && !slug.ends_with('-') {
+                slug.push('-');
+            }
+            if slug.len() >= MAX_LENGTH {
+                break;
+            }
+        }
+
+        let slug = slug.trim_end_matches('-');
+        if slug.is_empty() {
+            Self("issue".into())
+        } else {
+            Self(slug.into())
+        }
+    }
+}
+
+impl AsRef<str> for IssueSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IssueSlug;
+
+    #[test]
+    fn punctuation_and_spaces_become_single_dashes() {
+        let slug = IssueSlug::from_title("  Hello, World! -- Issue #3 ");
+        assert_eq!(slug.as_ref(), "hello-world-issue-3");
+    }
+
+    #[test]
+    fn non_ascii_characters_are_dropped() {
+        let slug = IssueSlug::from_title("Café über alles");
+        assert_eq!(slug.as_ref(), "caf-ber-alles");
+    }
+
+    #[test]
+    fn a_title_without_any_letter_gets_a_default_slug() {
+        assert_eq!(IssueSlug::from_title("?!").as_ref(), "issue");
+    }
+
+    #[test]
+    fn long_titles_are_truncated() {
+        let slug = IssueSlug::from_title(&"a".repeat(200));
+        assert_eq!(slug.as_ref().len(), 60);
+    }
+}
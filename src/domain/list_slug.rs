@@ -0,0 +1,63 @@
+const MAX_LENGTH: usize = 60;
+
+// How a list is named in URLs and in the `list` field of the subscribe
+// form: lowercase ASCII letters, digits and single dashes, e.g. `weekly-digest`
+#[derive(Debug)]
+pub struct ListSlug(String);
+
+impl ListSlug {
+    pub fn parse(input: String) -> Result<ListSlug, String> {
+        let is_valid = !input.is_empty()
+            && input.len() <= MAX_LENGTH
+            && input
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+            && !input.starts_with('-')
+            && !input.ends_with('-')
+            && !input.contains("--");
+
+        if !is_valid {
+            return Err(format!("{} is not a valid list slug", input));
+        }
+
+        Ok(Self(input))
+    }
+}
+
+impl AsRef<str> for ListSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ListSlug;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn lowercase_words_separated_by_dashes_are_valid() {
+        assert_ok!(ListSlug::parse("weekly-digest-2".into()));
+        assert_ok!(ListSlug::parse("releases".into()));
+    }
+
+    #[test]
+    fn uppercase_spaces_and_other_characters_are_rejected() {
+        for slug in ["Releases", "weekly digest", "weekly_digest", "café", ""] {
+            assert_err!(ListSlug::parse(slug.into()));
+        }
+    }
+
+    #[test]
+    fn dangling_or_repeated_dashes_are_rejected() {
+        for slug in ["-releases", "releases-", "weekly--digest"] {
+            assert_err!(ListSlug::parse(slug.into()));
+        }
+    }
+
+    #[test]
+    fn long_slugs_are_rejected() {
+        assert_ok!(ListSlug::parse("a".repeat(60)));
+        assert_err!(ListSlug::parse("a".repeat(61)));
+    }
+}
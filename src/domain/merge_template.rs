@@ -0,0 +1,231 @@
+use htmlescape::encode_minimal;
+
+// The content of an issue with merge tags replaced for each recipient, e.g.
+// `Hi {{ name }}!` or `Hi {{ name | there }}!`. The text after `|` is used
+// when the recipient has no value for the tag. Tags are only substituted,
+// there is no logic nor any access to other data.
+#[derive(Debug)]
+pub struct MergeTemplate(Vec<Part>);
+
+#[derive(Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Tag {
+        field: MergeField,
+        fallback: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MergeField {
+    Name,
+    Email,
+    UnsubscribeUrl,
+    ArchiveUrl,
+}
+
+impl MergeField {
+    const ALL: [MergeField; 4] = [
+        MergeField::Name,
+        MergeField::Email,
+        MergeField::UnsubscribeUrl,
+        MergeField::ArchiveUrl,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MergeField::Name => "name",
+            MergeField::Email => "email",
+            MergeField::UnsubscribeUrl => "unsubscribe_url",
+            MergeField::ArchiveUrl => "archive_url",
+        }
+    }
+}
+
+// What a recipient is known by, `None` when there is nothing to show
+#[derive(Default)]
+pub struct MergeValues<'a> {
+    pub name: Option<&'a str>,
+    pub email: Option<&'a str>,
+    pub unsubscribe_url: Option<&'a str>,
+    pub archive_url: Option<&'a str>,
+}
+
+impl MergeValues<'_> {
+    fn get(&self, field: MergeField) -> Option<&str> {
+        let value = match field {
+            MergeField::Name => self.name,
+            MergeField::Email => self.email,
+            MergeField::UnsubscribeUrl => self.unsubscribe_url,
+            MergeField::ArchiveUrl => self.archive_url,
+        };
+        value.filter(|v| !v.trim().is_empty())
+    }
+}
+
+impl MergeTemplate {
+    // Unknown tags and unclosed `{{` are rejected
+    pub fn parse(input: &str) -> Result<MergeTemplate, String> {
+        let mut parts = vec![];
+        let mut rest = input;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_owned()));
+            }
+            let after = &rest[start + 2..];
+            let end = after
+                .find("}}")
+                .ok_or_else(|| format!("{} is not closed by }}}}", excerpt(&rest[start..])))?;
+            parts.push(parse_tag(&after[..end])?);
+            rest = &after[end + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_owned()));
+        }
+
+        Ok(Self(parts))
+    }
+
+    // For content that was never validated, e.g. an issue created from a
+    // blog post, sent as is when it does not parse
+    pub fn parse_or_verbatim(input: &str) -> MergeTemplate {
+        Self::parse(input).unwrap_or_else(|_| Self(vec![Part::Text(input.to_owned())]))
+    }
+
+    // Values are HTML escaped, the template itself is trusted
+    pub fn render_html(&self, values: &MergeValues) -> String {
+        self.render(values, escape_html)
+    }
+
+    pub fn render_text(&self, values: &MergeValues) -> String {
+        self.render(values, str::to_owned)
+    }
+
+    fn render(&self, values: &MergeValues, escape: fn(&str) -> String) -> String {
+        let mut output = String::new();
+        for part in &self.0 {
+            match part {
+                Part::Text(text) => output.push_str(text),
+                Part::Tag { field, fallback } => {
+                    if let Some(value) = values.get(*field).or(fallback.as_deref()) {
+                        output.push_str(&escape(value));
+                    }
+                }
+            }
+        }
+
+        output
+    }
+}
+
+// `name` or `name | fallback`
+fn parse_tag(tag: &str) -> Result<Part, String> {
+    let (name, fallback) = match tag.split_once('|') {
+        Some((name, fallback)) => (name.trim(), Some(fallback.trim().to_owned())),
+        None => (tag.trim(), None),
+    };
+    let field = MergeField::ALL
+        .into_iter()
+        .find(|f| f.as_str() == name)
+        .ok_or_else(|| {
+            let known: Vec<_> = MergeField::ALL.iter().map(|f| f.as_str()).collect();
+            format!(
+                "{{{{ {} }}}} is not a merge tag, use one of {}",
+                name,
+                known.join(", ")
+            )
+        })?;
+
+    Ok(Part::Tag { field, fallback })
+}
+
+// Quotes are escaped as well, tags are also used inside attributes,
+// e.g. `href="{{ unsubscribe_url }}"` or `alt="{{ name }}"`
+fn escape_html(value: &str) -> String {
+    encode_minimal(value)
+        .replace('"', "&quot;")
+        .replace('\'', "&#x27;")
+}
+
+fn excerpt(input: &str) -> String {
+    input.chars().take(20).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MergeTemplate, MergeValues};
+    use claim::assert_err;
+
+    fn values() -> MergeValues<'static> {
+        MergeValues {
+            name: Some("Ursula"),
+            email: Some("ursula@example.com"),
+            unsubscribe_url: Some("https://example.com/unsubscribe?a=1&b=2"),
+            archive_url: None,
+        }
+    }
+
+    #[test]
+    fn tags_are_replaced_with_the_values_of_the_recipient() {
+        let template =
+            MergeTemplate::parse("Hi {{ name }} ({{email}}), {{ unsubscribe_url }}").unwrap();
+        assert_eq!(
+            template.render_text(&values()),
+            "Hi Ursula (ursula@example.com), https://example.com/unsubscribe?a=1&b=2"
+        );
+    }
+
+    #[test]
+    fn values_are_escaped_in_the_html_part() {
+        let template =
+            MergeTemplate::parse(r#"<a href="{{ unsubscribe_url }}">{{ name }}</a>"#).unwrap();
+        let values = MergeValues {
+            name: Some("<b>Ursula</b>"),
+            ..values()
+        };
+        assert_eq!(
+            template.render_html(&values),
+            r#"<a href="https://example.com/unsubscribe?a=1&amp;b=2">&lt;b&gt;Ursula&lt;/b&gt;</a>"#
+        );
+    }
+
+    #[test]
+    fn values_cannot_leave_an_attribute() {
+        let template =
+            MergeTemplate::parse(r#"<img alt="{{ name }}" title='{{ name }}'>"#).unwrap();
+        let values = MergeValues {
+            name: Some(r#"" onerror="alert(1)" x='"#),
+            ..values()
+        };
+        assert_eq!(
+            template.render_html(&values),
+            r#"<img alt="&quot; onerror=&quot;alert(1)&quot; x=&#x27;" title='&quot; onerror=&quot;alert(1)&quot; x=&#x27;'>"#
+        );
+    }
+
+    #[test]
+    fn missing_values_use_the_fallback() {
+        let template = MergeTemplate::parse(
+            "Hi {{ name | dear reader }}, {{ archive_url | }}{{ archive_url }}.",
+        )
+        .unwrap();
+        let values = MergeValues {
+            name: Some("  "),
+            ..values()
+        };
+        assert_eq!(template.render_text(&values), "Hi dear reader, .");
+    }
+
+    #[test]
+    fn unknown_and_unclosed_tags_are_rejected() {
+        assert_err!(MergeTemplate::parse("Hi {{ first_name }}"));
+        assert_err!(MergeTemplate::parse("Hi {{ name"));
+        assert_err!(MergeTemplate::parse("Hi {{ }}"));
+    }
+
+    #[test]
+    fn invalid_content_can_be_sent_verbatim() {
+        let template = MergeTemplate::parse_or_verbatim("fn main() {{ name }");
+        assert_eq!(template.render_text(&values()), "fn main() {{ name }");
+    }
+}
@@ -1,7 +1,25 @@
+mod brand_color;
+mod data_request_token;
+mod issue_content;
+mod issue_slug;
+mod list_slug;
+mod merge_template;
 mod new_subscriber;
 mod subscriber_email;
 mod subscriber_name;
+mod subscriber_tag;
+mod tracking_token;
+mod unsubscribe_token;
 
+pub use brand_color::BrandColor;
+pub use data_request_token::DataRequestToken;
+pub use issue_content::IssueContent;
+pub use issue_slug::IssueSlug;
+pub use list_slug::ListSlug;
+pub use merge_template::{MergeTemplate, MergeValues};
 pub use new_subscriber::NewSubscriber;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use subscriber_tag::SubscriberTag;
+pub use tracking_token::{TrackedEvent, TrackingToken};
+pub use unsubscribe_token::UnsubscribeToken;
@@ -0,0 +1,78 @@
+const MAX_LENGTH: usize = 40;
+
+// A label used to target issues, lowercase ASCII letters, digits, `-` and `_`.
+// Tags are compared in lowercase, `Rust` is the `rust` tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubscriberTag(String);
+
+impl SubscriberTag {
+    pub fn parse(input: &str) -> Result<SubscriberTag, String> {
+        let tag = input.trim().to_ascii_lowercase();
+        let is_valid = !tag.is_empty()
+            && tag.len() <= MAX_LENGTH
+            && tag
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+
+        if !is_valid {
+            return Err(format!("{} is not a valid tag", input.trim()));
+        }
+
+        Ok(Self(tag))
+    }
+
+    // Comma separated tags, as typed in the forms. Empty entries and
+    // duplicates are skipped.
+    pub fn parse_list(input: &str) -> Result<Vec<SubscriberTag>, String> {
+        let mut tags: Vec<SubscriberTag> = vec![];
+        for tag in input.split(',').filter(|t| !t.trim().is_empty()) {
+            let tag = Self::parse(tag)?;
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+
+        Ok(tags)
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriberTag;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn tags_are_trimmed_and_lowercased() {
+        let tag = SubscriberTag::parse(" Rust-Lang_2 ").unwrap();
+        assert_eq!(tag.as_ref(), "rust-lang_2");
+    }
+
+    #[test]
+    fn spaces_and_other_characters_are_rejected() {
+        for tag in ["two words", "c++", "café", "", "   "] {
+            assert_err!(SubscriberTag::parse(tag));
+        }
+    }
+
+    #[test]
+    fn long_tags_are_rejected() {
+        assert_ok!(SubscriberTag::parse(&"a".repeat(40)));
+        assert_err!(SubscriberTag::parse(&"a".repeat(41)));
+    }
+
+    #[test]
+    fn lists_skip_empty_entries_and_duplicates() {
+        let tags = SubscriberTag::parse_list("rust, ,Beta,rust,").unwrap();
+        let tags: Vec<&str> = tags.iter().map(|t| t.as_ref()).collect();
+        assert_eq!(tags, vec!["rust", "beta"]);
+
+        assert!(SubscriberTag::parse_list("").unwrap().is_empty());
+        assert_err!(SubscriberTag::parse_list("rust,not valid"));
+    }
+}
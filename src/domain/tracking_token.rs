@@ -0,0 +1,153 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TrackedEvent {
+    Open,
+    // The link the subscriber is redirected to
+    Click(String),
+}
+
+// Identifies who opened an issue or clicked one of its links. Unlike
+// `UnsubscribeToken` it carries what it signs, base64url encoded and
+// followed by the hex encoded tag, so that the tracking routes need nothing
+// else. Clicks only redirect to the links signed here.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TrackingToken {
+    pub newsletter_issue_id: uuid::Uuid,
+    pub subscriber_id: uuid::Uuid,
+    pub event: TrackedEvent,
+}
+
+impl TrackingToken {
+    pub fn sign(&self, secret: &Secret<String>) -> String {
+        let payload = self.payload();
+        let mut mac = Self::mac(secret);
+        mac.update(&payload);
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(&payload),
+            hex::encode(mac.finalize().into_bytes())
+        )
+    }
+
+    pub fn verify(token: &str, secret: &Secret<String>) -> Result<Self, anyhow::Error> {
+        let (payload, tag) = token
+            .split_once('.')
+            .ok_or_else(|| anyhow::anyhow!("The token has no tag"))?;
+        let payload = URL_SAFE_NO_PAD.decode(payload)?;
+        let tag = hex::decode(tag)?;
+        let mut mac = Self::mac(secret);
+        mac.update(&payload);
+        // `verify_slice` does the comparison in constant time
+        mac.verify_slice(&tag)?;
+
+        if payload.len() < 33 {
+            anyhow::bail!("The token is too short");
+        }
+        let event = match payload[32] {
+            b'o' if payload.len() == 33 => TrackedEvent::Open,
+            b'c' => TrackedEvent::Click(String::from_utf8(payload[33..].to_vec())?),
+            _ => anyhow::bail!("Unknown tracked event"),
+        };
+
+        Ok(Self {
+            newsletter_issue_id: uuid::Uuid::from_slice(&payload[..16])?,
+            subscriber_id: uuid::Uuid::from_slice(&payload[16..32])?,
+            event,
+        })
+    }
+
+    fn payload(&self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(33);
+        payload.extend_from_slice(self.newsletter_issue_id.as_bytes());
+        payload.extend_from_slice(self.subscriber_id.as_bytes());
+        match &self.event {
+            TrackedEvent::Open => payload.push(b'o'),
+            TrackedEvent::Click(url) => {
+                payload.push(b'c');
+                payload.extend_from_slice(url.as_bytes());
+            }
+        }
+        payload
+    }
+
+    // The purpose is part of the signed message, no other token is accepted
+    fn mac(secret: &Secret<String>) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(secret.expose_secret().as_bytes())
+            .expect("HMAC can take key of any size");
+        mac.update(b"tracking");
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TrackedEvent, TrackingToken};
+    use claim::assert_err;
+    use secrecy::Secret;
+
+    fn secret() -> Secret<String> {
+        Secret::new("super-long-and-secret-random-key-needed-to-sign".to_string())
+    }
+
+    fn click(url: &str) -> TrackingToken {
+        TrackingToken {
+            newsletter_issue_id: uuid::Uuid::new_v4(),
+            subscriber_id: uuid::Uuid::new_v4(),
+            event: TrackedEvent::Click(url.into()),
+        }
+    }
+
+    #[test]
+    fn a_signed_token_is_verified_with_what_it_carries() {
+        let token = click("https://example.com/post?a=1&b=2");
+        let verified = TrackingToken::verify(&token.sign(&secret()), &secret()).unwrap();
+        assert_eq!(verified, token);
+
+        let token = TrackingToken {
+            event: TrackedEvent::Open,
+            ..token
+        };
+        let verified = TrackingToken::verify(&token.sign(&secret()), &secret()).unwrap();
+        assert_eq!(verified, token);
+    }
+
+    #[test]
+    fn tokens_only_contain_url_safe_characters() {
+        let signed = click("https://example.com/é?q=a b").sign(&secret());
+        assert!(signed
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)));
+    }
+
+    #[test]
+    fn a_token_with_another_link_is_rejected() {
+        let signed = click("https://example.com").sign(&secret());
+        let forged = click("https://evil.com").sign(&secret());
+        let (_, tag) = signed.split_once('.').unwrap();
+        let (payload, _) = forged.split_once('.').unwrap();
+        assert_err!(TrackingToken::verify(
+            &format!("{}.{}", payload, tag),
+            &secret()
+        ));
+    }
+
+    #[test]
+    fn a_token_signed_with_another_secret_is_rejected() {
+        let signed = click("https://example.com").sign(&Secret::new("another-secret".into()));
+        assert_err!(TrackingToken::verify(&signed, &secret()));
+    }
+
+    #[test]
+    fn a_malformed_token_is_rejected() {
+        for token in ["", "not-a-token", "AAAA.00", "!!!.zz"] {
+            assert_err!(TrackingToken::verify(token, &secret()));
+        }
+    }
+}
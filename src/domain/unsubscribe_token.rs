@@ -0,0 +1,84 @@
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// A token that proves that the holder received an email sent to `subscriber_id`.
+// It is the hex encoded HMAC-SHA256 tag of the subscriber id, so it never
+// expires and does not need to be stored in the database.
+#[derive(Debug)]
+pub struct UnsubscribeToken(String);
+
+impl UnsubscribeToken {
+    pub fn generate(subscriber_id: uuid::Uuid, secret: &Secret<String>) -> Self {
+        let mut mac = Self::mac(secret);
+        mac.update(subscriber_id.as_bytes());
+        Self(hex::encode(mac.finalize().into_bytes()))
+    }
+
+    pub fn verify(
+        token: &str,
+        subscriber_id: uuid::Uuid,
+        secret: &Secret<String>,
+    ) -> Result<(), anyhow::Error> {
+        let tag = hex::decode(token)?;
+        let mut mac = Self::mac(secret);
+        mac.update(subscriber_id.as_bytes());
+        // `verify_slice` does the comparison in constant time
+        mac.verify_slice(&tag)?;
+        Ok(())
+    }
+
+    fn mac(secret: &Secret<String>) -> HmacSha256 {
+        HmacSha256::new_from_slice(secret.expose_secret().as_bytes())
+            .expect("HMAC can take key of any size")
+    }
+}
+
+impl AsRef<str> for UnsubscribeToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UnsubscribeToken;
+    use claim::{assert_err, assert_ok};
+    use secrecy::Secret;
+
+    fn secret() -> Secret<String> {
+        Secret::new("super-long-and-secret-random-key-needed-to-sign".to_string())
+    }
+
+    #[test]
+    fn a_generated_token_is_valid_for_the_same_subscriber() {
+        let id = uuid::Uuid::new_v4();
+        let token = UnsubscribeToken::generate(id, &secret());
+        assert_ok!(UnsubscribeToken::verify(token.as_ref(), id, &secret()));
+    }
+
+    #[test]
+    fn a_token_is_rejected_for_another_subscriber() {
+        let token = UnsubscribeToken::generate(uuid::Uuid::new_v4(), &secret());
+        assert_err!(UnsubscribeToken::verify(
+            token.as_ref(),
+            uuid::Uuid::new_v4(),
+            &secret()
+        ));
+    }
+
+    #[test]
+    fn a_token_signed_with_another_secret_is_rejected() {
+        let id = uuid::Uuid::new_v4();
+        let token = UnsubscribeToken::generate(id, &Secret::new("another-secret".to_string()));
+        assert_err!(UnsubscribeToken::verify(token.as_ref(), id, &secret()));
+    }
+
+    #[test]
+    fn a_malformed_token_is_rejected() {
+        let id = uuid::Uuid::new_v4();
+        assert_err!(UnsubscribeToken::verify("not-hex", id, &secret()));
+    }
+}
@@ -1,193 +0,0 @@
-use reqwest::Client;
-use secrecy::{ExposeSecret, Secret};
-
-use crate::domain::SubscriberEmail;
-
-#[derive(Clone)]
-pub struct EmailClient {
-    http_client: Client,
-    base_url: reqwest::Url,
-    sender: SubscriberEmail,
-    authorization_token: Secret<String>,
-}
-
-#[derive(serde::Serialize)]
-#[serde(rename_all = "PascalCase")]
-struct SendEmailRequest<'a> {
-    from: &'a str,
-    to: &'a str,
-    subject: &'a str,
-    html_body: &'a str,
-    text_body: &'a str,
-}
-
-impl EmailClient {
-    pub fn new(
-        base_url: String,
-        sender: SubscriberEmail,
-        authorization_token: Secret<String>,
-        timeout: std::time::Duration,
-    ) -> Result<Self, String> {
-        let base_url = match reqwest::Url::parse(&base_url) {
-            Ok(url) => url,
-            Err(err) => return Err(err.to_string()),
-        };
-
-        let http_client = Client::builder().timeout(timeout).build().unwrap();
-
-        Ok(Self {
-            http_client,
-            base_url,
-            sender,
-            authorization_token,
-        })
-    }
-
-    pub async fn send_email(
-        &self,
-        recipient: &SubscriberEmail,
-        subject: &str,
-        html_content: &str,
-        text_content: &str,
-    ) -> Result<(), reqwest::Error> {
-        let url = self.base_url.join("email").unwrap();
-
-        let payload = SendEmailRequest {
-            from: self.sender.as_ref(),
-            to: recipient.as_ref(),
-            subject,
-            html_body: html_content,
-            text_body: text_content,
-        };
-
-        self.http_client
-            .post(url.as_ref())
-            .header(
-                "X-Postmark-Server-Token",
-                self.authorization_token.expose_secret(),
-            )
-            .json(&payload)
-            .send()
-            .await? // ?
-            .error_for_status()?; // ?
-
-        Ok(())
-    }
-}
-
-#[cfg(test)]
-
-mod tests {
-
-    use super::EmailClient;
-    use super::SubscriberEmail;
-    use claim::{assert_err, assert_ok};
-    use fake::faker::internet::en::SafeEmail;
-    use fake::faker::lorem::en::{Paragraph, Sentence};
-    use fake::{Fake, Faker};
-    use secrecy::Secret;
-    use wiremock::matchers::{any, header, header_exists, method, path};
-    use wiremock::Request;
-    use wiremock::{Mock, MockServer, ResponseTemplate};
-
-    struct SendEmailBodyMatcher;
-
-    impl wiremock::Match for SendEmailBodyMatcher {
-        fn matches(&self, request: &Request) -> bool {
-            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
-
-            if let Ok(body) = result {
-                body.get("From").is_some()
-                    && body.get("To").is_some()
-                    && body.get("Subject").is_some()
-                    && body.get("HtmlBody").is_some()
-                    && body.get("TextBody").is_some()
-            } else {
-                false
-            }
-        }
-    }
-
-    fn subject() -> String {
-        Sentence(1..2).fake()
-    }
-
-    fn content() -> String {
-        Paragraph(1..10).fake()
-    }
-
-    fn email() -> SubscriberEmail {
-        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
-    }
-
-    fn email_client(base_url: String) -> EmailClient {
-        EmailClient::new(
-            base_url,
-            email(),
-            Secret::new(Faker.fake()),
-            std::time::Duration::from_millis(200),
-        )
-        .unwrap()
-    }
-
-    #[tokio::test]
-    async fn send_email_sends_the_expected_request() {
-        let mock_server = MockServer::start().await;
-
-        Mock::given(header_exists("X-Postmark-Server-Token"))
-            .and(header("Content-Type", "application/json"))
-            .and(path("/email"))
-            .and(method("POST"))
-            .and(SendEmailBodyMatcher)
-            .respond_with(ResponseTemplate::new(200))
-            .expect(1) // number of requests
-            .mount(&mock_server)
-            .await;
-
-        let response = email_client(mock_server.uri())
-            .send_email(&email(), &subject(), &content(), &content())
-            .await;
-
-        assert_ok!(response);
-    }
-
-    /// Mocking if server return 500
-    /// What if the remote server, to which i am sending REST API request
-    /// return 500 ?
-    #[tokio::test]
-    async fn send_email_fails_if_server_return_500() {
-        let mock_server = MockServer::start().await;
-
-        Mock::given(any())
-            .respond_with(ResponseTemplate::new(500))
-            .expect(1)
-            .mount(&mock_server)
-            .await;
-
-        let response = email_client(mock_server.uri())
-            .send_email(&email(), &subject(), &content(), &content())
-            .await;
-
-        assert_err!(response);
-    }
-
-    /// What is server takes too long time to respond
-    /// if connection start to hang for long time, another request have to
-    /// create new connection
-    #[tokio::test]
-    async fn send_email_fails_if_server_takes_too_long_to_respond() {
-        let mock_server = MockServer::start().await;
-
-        Mock::given(any())
-            .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180)))
-            .expect(1)
-            .mount(&mock_server)
-            .await;
-
-        let response = email_client(mock_server.uri())
-            .send_email(&email(), &subject(), &content(), &content())
-            .await;
-
-        assert_err!(response);
-    }
-}
@@ -0,0 +1,89 @@
+use lettre::{AsyncFileTransport, AsyncTransport, Tokio1Executor};
+
+use super::{build_message, EmailHeader, EmailTransport};
+use crate::domain::SubscriberEmail;
+
+// Writes every email as an `.eml` file inside a directory instead of sending it,
+// handy during development when no email provider is available
+pub struct FileEmailClient {
+    transport: AsyncFileTransport<Tokio1Executor>,
+    sender: SubscriberEmail,
+}
+
+impl FileEmailClient {
+    pub fn new(
+        directory: impl AsRef<std::path::Path>,
+        sender: SubscriberEmail,
+    ) -> Result<Self, anyhow::Error> {
+        std::fs::create_dir_all(&directory)?;
+
+        Ok(Self {
+            transport: AsyncFileTransport::new(directory),
+            sender,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for FileEmailClient {
+    fn sender(&self) -> &SubscriberEmail {
+        &self.sender
+    }
+
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<Option<String>, anyhow::Error> {
+        let message = build_message(
+            &self.sender,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            headers,
+        )?;
+
+        // The id is the name of the written file, without the `.eml` extension
+        let id = self.transport.send(message).await?;
+        Ok(Some(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileEmailClient;
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::EmailTransport;
+
+    fn email(s: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(s.to_string()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_email_writes_an_eml_file_in_the_directory() {
+        let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let client = FileEmailClient::new(&directory, email("newsletter@example.com")).unwrap();
+
+        let id = client
+            .send_email(
+                &email("reader@example.com"),
+                "Issue #1",
+                "<p>Hello</p>",
+                "Hello",
+                &[],
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        let content = std::fs::read_to_string(directory.join(format!("{}.eml", id))).unwrap();
+        assert!(content.contains("Subject: Issue #1"));
+        assert!(content.contains("To: reader@example.com"));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
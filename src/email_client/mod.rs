@@ -0,0 +1,106 @@
+mod file;
+mod postmark;
+mod smtp;
+
+pub use file::FileEmailClient;
+pub use postmark::PostmarkEmailClient;
+pub use smtp::SmtpEmailClient;
+
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::Message;
+
+use crate::domain::SubscriberEmail;
+
+// A custom header attached to an outgoing email, e.g. `List-Unsubscribe`
+#[derive(Debug, Clone)]
+pub struct EmailHeader {
+    pub name: String,
+    pub value: String,
+}
+
+impl EmailHeader {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+}
+
+// A rendered email, ready to be handed over to a transport
+#[derive(Debug, Clone)]
+pub struct EmailMessage {
+    pub recipient: SubscriberEmail,
+    pub subject: String,
+    pub html_content: String,
+    pub text_content: String,
+    pub headers: Vec<EmailHeader>,
+}
+
+// Anything that is able to deliver an email on our behalf.
+// The provider is picked with `email_client.provider` in the configuration.
+#[async_trait::async_trait]
+pub trait EmailTransport: Send + Sync {
+    fn sender(&self) -> &SubscriberEmail;
+
+    // On success returns the id the provider assigned to the message, if any
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<Option<String>, anyhow::Error>;
+
+    // Sends several emails at once and returns one result per message, in order.
+    // Transports without a batch API send them one by one.
+    async fn send_email_batch(
+        &self,
+        messages: &[EmailMessage],
+    ) -> Vec<Result<Option<String>, anyhow::Error>> {
+        let mut results = Vec::with_capacity(messages.len());
+        for message in messages {
+            let result = self
+                .send_email(
+                    &message.recipient,
+                    &message.subject,
+                    &message.html_content,
+                    &message.text_content,
+                    &message.headers,
+                )
+                .await;
+            results.push(result);
+        }
+        results
+    }
+}
+
+// Builds a `multipart/alternative` MIME message, used by the transports
+// that speak SMTP or write raw `.eml` files
+fn build_message(
+    sender: &SubscriberEmail,
+    recipient: &SubscriberEmail,
+    subject: &str,
+    html_content: &str,
+    text_content: &str,
+    headers: &[EmailHeader],
+) -> Result<Message, anyhow::Error> {
+    let mut builder = Message::builder()
+        .from(sender.as_ref().parse::<Mailbox>()?)
+        .to(recipient.as_ref().parse::<Mailbox>()?)
+        .subject(subject);
+
+    for header in headers {
+        let name = HeaderName::new_from_ascii(header.name.clone())?;
+        builder = builder.raw_header(HeaderValue::new(name, header.value.clone()));
+    }
+
+    let message = builder.multipart(MultiPart::alternative_plain_html(
+        text_content.to_string(),
+        html_content.to_string(),
+    ))?;
+
+    Ok(message)
+}
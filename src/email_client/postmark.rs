@@ -0,0 +1,495 @@
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+
+use super::{EmailHeader, EmailMessage, EmailTransport};
+use crate::domain::SubscriberEmail;
+
+// Sends emails through Postmark's JSON API
+// https://postmarkapp.com/developer/api/email-api
+#[derive(Clone)]
+pub struct PostmarkEmailClient {
+    http_client: Client,
+    base_url: reqwest::Url,
+    sender: SubscriberEmail,
+    authorization_token: Secret<String>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<PostmarkHeader<'a>>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkHeader<'a> {
+    name: &'a str,
+    value: &'a str,
+}
+
+#[derive(serde::Deserialize)]
+struct SendEmailResponse {
+    #[serde(rename = "MessageID")]
+    message_id: String,
+}
+
+// One entry per message of a batch, `ErrorCode` is 0 when the message was accepted
+// https://postmarkapp.com/developer/api/overview#error-codes
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BatchMessageResponse {
+    error_code: i64,
+    message: String,
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+}
+
+// Postmark does not accept more messages in a single batch request
+const MAX_BATCH_SIZE: usize = 500;
+
+impl PostmarkEmailClient {
+    pub fn new(
+        base_url: String,
+        sender: SubscriberEmail,
+        authorization_token: Secret<String>,
+        timeout: std::time::Duration,
+    ) -> Result<Self, String> {
+        let base_url = match reqwest::Url::parse(&base_url) {
+            Ok(url) => url,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+
+        Ok(Self {
+            http_client,
+            base_url,
+            sender,
+            authorization_token,
+        })
+    }
+
+    fn request<'a>(
+        &'a self,
+        recipient: &'a SubscriberEmail,
+        subject: &'a str,
+        html_content: &'a str,
+        text_content: &'a str,
+        headers: &'a [EmailHeader],
+    ) -> SendEmailRequest<'a> {
+        SendEmailRequest {
+            from: self.sender.as_ref(),
+            to: recipient.as_ref(),
+            subject,
+            html_body: html_content,
+            text_body: text_content,
+            headers: headers
+                .iter()
+                .map(|h| PostmarkHeader {
+                    name: &h.name,
+                    value: &h.value,
+                })
+                .collect(),
+        }
+    }
+
+    async fn send_batch_request(
+        &self,
+        messages: &[EmailMessage],
+    ) -> Result<Vec<BatchMessageResponse>, anyhow::Error> {
+        let url = self.base_url.join("email/batch").unwrap();
+
+        let payload: Vec<_> = messages
+            .iter()
+            .map(|m| {
+                self.request(
+                    &m.recipient,
+                    &m.subject,
+                    &m.html_content,
+                    &m.text_content,
+                    &m.headers,
+                )
+            })
+            .collect();
+
+        let response = self
+            .http_client
+            .post(url.as_ref())
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(&payload)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Vec<BatchMessageResponse>>()
+            .await?;
+
+        if response.len() != messages.len() {
+            anyhow::bail!(
+                "Postmark returned {} results for a batch of {} messages",
+                response.len(),
+                messages.len()
+            );
+        }
+
+        Ok(response)
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for PostmarkEmailClient {
+    fn sender(&self) -> &SubscriberEmail {
+        &self.sender
+    }
+
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<Option<String>, anyhow::Error> {
+        let url = self.base_url.join("email").unwrap();
+
+        let payload = self.request(recipient, subject, html_content, text_content, headers);
+
+        let response = self
+            .http_client
+            .post(url.as_ref())
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(&payload)
+            .send()
+            .await? // ?
+            .error_for_status()?; // ?
+
+        // The email has been accepted at this point, a body we cannot
+        // parse only means that we do not know the provider's message id
+        let message_id = response
+            .json::<SendEmailResponse>()
+            .await
+            .ok()
+            .map(|r| r.message_id);
+
+        Ok(message_id)
+    }
+
+    async fn send_email_batch(
+        &self,
+        messages: &[EmailMessage],
+    ) -> Vec<Result<Option<String>, anyhow::Error>> {
+        let mut results = Vec::with_capacity(messages.len());
+
+        for chunk in messages.chunks(MAX_BATCH_SIZE) {
+            match self.send_batch_request(chunk).await {
+                Ok(responses) => {
+                    results.extend(responses.into_iter().map(|r| {
+                        if r.error_code == 0 {
+                            Ok(r.message_id)
+                        } else {
+                            Err(anyhow::anyhow!(
+                                "Postmark rejected the message ({}): {}",
+                                r.error_code,
+                                r.message
+                            ))
+                        }
+                    }));
+                }
+                // None of the messages of the chunk has been accepted
+                Err(e) => {
+                    results.extend(chunk.iter().map(|_| Err(anyhow::anyhow!("{:#}", e))));
+                }
+            }
+        }
+
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::PostmarkEmailClient;
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailHeader, EmailMessage, EmailTransport};
+    use claim::{assert_err, assert_ok};
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use fake::{Fake, Faker};
+    use secrecy::Secret;
+    use wiremock::matchers::{any, header, header_exists, method, path};
+    use wiremock::Request;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+
+            if let Ok(body) = result {
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn email_client(base_url: String) -> PostmarkEmailClient {
+        PostmarkEmailClient::new(
+            base_url,
+            email(),
+            Secret::new(Faker.fake()),
+            std::time::Duration::from_millis(200),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_the_expected_request() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1) // number of requests
+            .mount(&mock_server)
+            .await;
+
+        let response = email_client(mock_server.uri())
+            .send_email(&email(), &subject(), &content(), &content(), &[])
+            .await;
+
+        assert_ok!(response);
+    }
+
+    struct SendEmailHeadersMatcher;
+
+    impl wiremock::Match for SendEmailHeadersMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+
+            if let Ok(body) = result {
+                body["Headers"][0]["Name"] == "List-Unsubscribe-Post"
+                    && body["Headers"][0]["Value"] == "List-Unsubscribe=One-Click"
+            } else {
+                false
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_custom_headers() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailHeadersMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let headers = [EmailHeader::new(
+            "List-Unsubscribe-Post",
+            "List-Unsubscribe=One-Click",
+        )];
+        let response = email_client(mock_server.uri())
+            .send_email(&email(), &subject(), &content(), &content(), &headers)
+            .await;
+
+        assert_ok!(response);
+    }
+
+    #[tokio::test]
+    async fn send_email_returns_the_provider_message_id() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "To": "receiver@example.com",
+                "SubmittedAt": "2014-02-17T07:25:01.4178645-05:00",
+                "MessageID": "0a129aee-e1cd-480d-b08d-4f48548ff48d",
+                "ErrorCode": 0,
+                "Message": "OK"
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let message_id = email_client(mock_server.uri())
+            .send_email(&email(), &subject(), &content(), &content(), &[])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            message_id.as_deref(),
+            Some("0a129aee-e1cd-480d-b08d-4f48548ff48d")
+        );
+    }
+
+    fn message() -> EmailMessage {
+        EmailMessage {
+            recipient: email(),
+            subject: subject(),
+            html_content: content(),
+            text_content: content(),
+            headers: vec![],
+        }
+    }
+
+    struct SendEmailBatchBodyMatcher(usize);
+
+    impl wiremock::Match for SendEmailBatchBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<Vec<serde_json::Value>, _> = serde_json::from_slice(&request.body);
+
+            if let Ok(messages) = result {
+                messages.len() == self.0
+                    && messages
+                        .iter()
+                        .all(|m| m.get("To").is_some() && m.get("TextBody").is_some())
+            } else {
+                false
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_sends_all_messages_in_one_request() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(path("/email/batch"))
+            .and(method("POST"))
+            .and(SendEmailBatchBodyMatcher(3))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "ErrorCode": 0, "Message": "OK", "MessageID": "a" },
+                { "ErrorCode": 0, "Message": "OK", "MessageID": "b" },
+                { "ErrorCode": 0, "Message": "OK", "MessageID": "c" },
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let results = email_client(mock_server.uri())
+            .send_email_batch(&[message(), message(), message()])
+            .await;
+
+        let ids: Vec<_> = results.into_iter().map(|r| r.unwrap().unwrap()).collect();
+        assert_eq!(ids, vec!["a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_reports_rejected_messages_individually() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(path("/email/batch"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "ErrorCode": 0, "Message": "OK", "MessageID": "a" },
+                {
+                    "ErrorCode": 406,
+                    "Message": "You tried to send to a recipient that has been marked as inactive."
+                },
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let results = email_client(mock_server.uri())
+            .send_email_batch(&[message(), message()])
+            .await;
+
+        assert_ok!(&results[0]);
+        let error = results[1].as_ref().unwrap_err().to_string();
+        assert!(error.contains("406"));
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_fails_every_message_if_server_return_500() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let results = email_client(mock_server.uri())
+            .send_email_batch(&[message(), message()])
+            .await;
+
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.is_err()));
+    }
+
+    /// Mocking if server return 500
+    /// What if the remote server, to which i am sending REST API request
+    /// return 500 ?
+    #[tokio::test]
+    async fn send_email_fails_if_server_return_500() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let response = email_client(mock_server.uri())
+            .send_email(&email(), &subject(), &content(), &content(), &[])
+            .await;
+
+        assert_err!(response);
+    }
+
+    /// What is server takes too long time to respond
+    /// if connection start to hang for long time, another request have to
+    /// create new connection
+    #[tokio::test]
+    async fn send_email_fails_if_server_takes_too_long_to_respond() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180)))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let response = email_client(mock_server.uri())
+            .send_email(&email(), &subject(), &content(), &content(), &[])
+            .await;
+
+        assert_err!(response);
+    }
+}
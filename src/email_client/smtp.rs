@@ -0,0 +1,192 @@
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use secrecy::ExposeSecret;
+
+use super::{build_message, EmailHeader, EmailTransport};
+use crate::configuration::{SmtpSettings, SmtpTls};
+use crate::domain::SubscriberEmail;
+
+// Sends emails to an SMTP relay, e.g. our own Postfix
+pub struct SmtpEmailClient {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    sender: SubscriberEmail,
+}
+
+impl SmtpEmailClient {
+    pub fn new(
+        settings: &SmtpSettings,
+        sender: SubscriberEmail,
+        timeout: std::time::Duration,
+    ) -> Result<Self, anyhow::Error> {
+        let mut builder = match settings.tls {
+            SmtpTls::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.host)
+            }
+            SmtpTls::Starttls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)?
+            }
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.host)?,
+        }
+        .port(settings.port)
+        .timeout(Some(timeout));
+
+        if let (Some(username), Some(password)) = (&settings.username, &settings.password) {
+            builder = builder.credentials(Credentials::new(
+                username.clone(),
+                password.expose_secret().clone(),
+            ));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            sender,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for SmtpEmailClient {
+    fn sender(&self) -> &SubscriberEmail {
+        &self.sender
+    }
+
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<Option<String>, anyhow::Error> {
+        let message = build_message(
+            &self.sender,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            headers,
+        )?;
+
+        self.transport.send(message).await?;
+
+        // SMTP has no standard message id in its reply
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SmtpEmailClient;
+    use crate::configuration::{SmtpSettings, SmtpTls};
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailHeader, EmailTransport};
+    use claim::{assert_err, assert_ok};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    // A minimal SMTP sink, accepts one message and returns what was
+    // sent after the DATA command
+    async fn smtp_sink() -> (u16, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read, mut write) = stream.into_split();
+            let mut lines = BufReader::new(read).lines();
+            let mut data = String::new();
+            let mut in_data = false;
+
+            write.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                if in_data {
+                    if line == "." {
+                        in_data = false;
+                        write.write_all(b"250 2.0.0 Ok: queued\r\n").await.unwrap();
+                    } else {
+                        data.push_str(&line);
+                        data.push('\n');
+                    }
+                    continue;
+                }
+
+                let command = line.to_uppercase();
+                let reply: &[u8] = if command.starts_with("EHLO") {
+                    b"250-localhost\r\n250 8BITMIME\r\n"
+                } else if command.starts_with("DATA") {
+                    in_data = true;
+                    b"354 End data with <CR><LF>.<CR><LF>\r\n"
+                } else if command.starts_with("QUIT") {
+                    write.write_all(b"221 Bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    b"250 Ok\r\n"
+                };
+                write.write_all(reply).await.unwrap();
+            }
+            data
+        });
+
+        (port, handle)
+    }
+
+    fn email(s: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(s.to_string()).unwrap()
+    }
+
+    fn smtp_client(port: u16) -> SmtpEmailClient {
+        let settings = SmtpSettings {
+            host: "127.0.0.1".into(),
+            port,
+            username: None,
+            password: None,
+            tls: SmtpTls::None,
+        };
+        SmtpEmailClient::new(
+            &settings,
+            email("newsletter@example.com"),
+            std::time::Duration::from_secs(2),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_email_delivers_the_message_to_the_smtp_server() {
+        let (port, sink) = smtp_sink().await;
+
+        let headers = [EmailHeader::new(
+            "List-Unsubscribe-Post",
+            "List-Unsubscribe=One-Click",
+        )];
+        let response = smtp_client(port)
+            .send_email(
+                &email("reader@example.com"),
+                "Issue #1",
+                "<p>Hello</p>",
+                "Hello",
+                &headers,
+            )
+            .await;
+        assert_ok!(response);
+
+        let data = sink.await.unwrap();
+        assert!(data.contains("Subject: Issue #1"));
+        assert!(data.contains("To: reader@example.com"));
+        assert!(data.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+        assert!(data.contains("<p>Hello</p>"));
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_there_is_no_smtp_server() {
+        let port = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            listener.local_addr().unwrap().port()
+        };
+
+        let response = smtp_client(port)
+            .send_email(&email("reader@example.com"), "Issue #1", "", "", &[])
+            .await;
+
+        assert_err!(response);
+    }
+}
@@ -0,0 +1,157 @@
+use std::borrow::Cow;
+
+use ammonia::{Builder, UrlRelative, UrlRelativeEvaluate};
+use anyhow::Context;
+use css_inline::CSSInliner;
+use reqwest::Url;
+
+// Gmail only shows the beginning of larger messages, behind a
+// "[Message clipped]" link
+pub const GMAIL_CLIPPING_SIZE: usize = 102 * 1024;
+
+// Attributes the HTML emails of the old days are laid out with
+const LAYOUT_ATTRIBUTES: [&str; 11] = [
+    "style",
+    "class",
+    "align",
+    "valign",
+    "width",
+    "height",
+    "bgcolor",
+    "border",
+    "cellpadding",
+    "cellspacing",
+    "dir",
+];
+
+// The HTML part of an issue as mail clients expect it: the rules of its
+// `<style>` blocks are inlined into `style` attributes, scripts and other
+// tags mail clients refuse are stripped and relative URLs are made absolute
+// against `base_url`
+pub fn prepare_issue_html(html: &str, base_url: &str) -> Result<String, anyhow::Error> {
+    let base_url = Url::parse(base_url).context("The base url is not valid")?;
+    let inlined = CSSInliner::options()
+        .load_remote_stylesheets(false)
+        .build()
+        .inline(html)
+        .context("Failed to inline the CSS")?;
+
+    // The document wrapping the inliner adds is stripped as well
+    let html = sanitizer()
+        .url_relative(UrlRelative::Custom(Box::new(AbsoluteUrl(base_url))))
+        .clean(&inlined)
+        .to_string();
+
+    Ok(html)
+}
+
+// Replaces the target of the links of an HTML part prepared by
+// `prepare_issue_html`, the links `rewrite` returns `None` for are left as is
+pub fn rewrite_links<F>(html: &str, rewrite: F) -> String
+where
+    F: Fn(&str) -> Option<String> + Send + Sync + 'static,
+{
+    sanitizer()
+        .attribute_filter(move |element, attribute, value| {
+            if element == "a" && attribute == "href" {
+                if let Some(url) = rewrite(value) {
+                    return Some(Cow::Owned(url));
+                }
+            }
+            Some(Cow::Borrowed(value))
+        })
+        .clean(html)
+        .to_string()
+}
+
+fn sanitizer<'a>() -> Builder<'a> {
+    let mut builder = Builder::default();
+    builder
+        .add_generic_attributes(LAYOUT_ATTRIBUTES)
+        .add_tags(["center", "font"])
+        .add_tag_attributes("font", ["color", "face", "size"])
+        .link_rel(None);
+    builder
+}
+
+// Merge tags used as a URL are left for the delivery workers
+struct AbsoluteUrl(Url);
+
+impl<'a> UrlRelativeEvaluate<'a> for AbsoluteUrl {
+    fn evaluate<'url>(&self, url: &'url str) -> Option<Cow<'url, str>> {
+        if url.starts_with("{{") || url.starts_with('#') {
+            return Some(Cow::Borrowed(url));
+        }
+        self.0.join(url).ok().map(|url| Cow::Owned(url.into()))
+    }
+}
+
+pub fn is_clipped_by_gmail(html: &str) -> bool {
+    html.len() > GMAIL_CLIPPING_SIZE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_clipped_by_gmail, prepare_issue_html, rewrite_links, GMAIL_CLIPPING_SIZE};
+
+    fn prepare(html: &str) -> String {
+        prepare_issue_html(html, "https://example.com").unwrap()
+    }
+
+    #[test]
+    fn style_blocks_are_inlined() {
+        let html = prepare(
+            "<style>.issue p { color: red; } a { color: blue; }</style>\
+            <div class=\"issue\"><p>Hello <a href=\"https://example.com\">you</a></p></div>",
+        );
+        assert!(!html.contains("<style>"));
+        assert!(html.contains(r#"<p style="color: red;">"#));
+        assert!(html.contains(r#"style="color: blue;""#));
+    }
+
+    #[test]
+    fn scripts_and_forbidden_tags_are_stripped() {
+        let html = prepare(
+            "<p onclick=\"steal()\">Hi</p><script>steal()</script>\
+            <iframe src=\"https://evil.com\"></iframe><form><input name=\"q\"></form>",
+        );
+        assert_eq!(html, "<p>Hi</p>");
+    }
+
+    #[test]
+    fn relative_urls_are_made_absolute() {
+        let html = prepare(r#"<a href="/issues/1">Read</a><img src="cat.png" alt="cat">"#);
+        assert!(html.contains(r#"href="https://example.com/issues/1""#));
+        assert!(html.contains(r#"src="https://example.com/cat.png""#));
+    }
+
+    #[test]
+    fn merge_tags_and_anchors_are_left_as_is() {
+        let html = prepare(r##"<a href="{{ unsubscribe_url }}">Leave</a> <a href="#top">Top</a>"##);
+        assert!(html.contains(r#"href="{{ unsubscribe_url }}""#));
+        assert!(html.contains(r##"href="#top""##));
+    }
+
+    #[test]
+    fn links_can_be_rewritten() {
+        let html = prepare(
+            r#"<p style="color: red;">Read <a href="https://example.com/a?b=1&amp;c=2">this</a> \
+            or <a href="mailto:me@example.com">write</a></p>"#,
+        );
+        let html = rewrite_links(&html, |url| {
+            url.starts_with("https://")
+                .then(|| format!("https://t.example.com/?to={}", url))
+        });
+        assert_eq!(
+            html,
+            r#"<p style="color: red;">Read <a href="https://t.example.com/?to=https://example.com/a?b=1&amp;c=2">this</a> \
+            or <a href="mailto:me@example.com">write</a></p>"#
+        );
+    }
+
+    #[test]
+    fn large_issues_are_clipped_by_gmail() {
+        assert!(!is_clipped_by_gmail(&"a".repeat(GMAIL_CLIPPING_SIZE)));
+        assert!(is_clipped_by_gmail(&"a".repeat(GMAIL_CLIPPING_SIZE + 1)));
+    }
+}
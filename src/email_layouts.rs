@@ -0,0 +1,282 @@
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+
+use crate::email_client::EmailMessage;
+
+// What emails are wrapped in. Its texts are plain text, they end up in both
+// parts of the emails.
+pub struct EmailLayout {
+    pub layout_id: uuid::Uuid,
+    pub name: String,
+    pub header: String,
+    pub footer: String,
+    pub postal_address: String,
+    pub brand_color: String,
+    pub background_color: String,
+    pub is_default: bool,
+}
+
+struct EmailLayoutRow {
+    layout_id: sqlx::types::Uuid,
+    name: String,
+    header: String,
+    footer: String,
+    postal_address: String,
+    brand_color: String,
+    background_color: String,
+    is_default: bool,
+}
+
+impl From<EmailLayoutRow> for EmailLayout {
+    fn from(row: EmailLayoutRow) -> Self {
+        Self {
+            layout_id: uuid::Uuid::from_bytes(*row.layout_id.as_bytes()),
+            name: row.name,
+            header: row.header,
+            footer: row.footer,
+            postal_address: row.postal_address,
+            brand_color: row.brand_color,
+            background_color: row.background_color,
+            is_default: row.is_default,
+        }
+    }
+}
+
+impl EmailLayout {
+    // Mail clients ignore most of CSS, the layout is made of tables and
+    // inline styles
+    pub fn wrap_html(&self, content: &str, unsubscribe_url: Option<&str>) -> String {
+        let brand_color = encode_minimal(&self.brand_color);
+        let background_color = encode_minimal(&self.background_color);
+
+        let header = if self.header.trim().is_empty() {
+            String::new()
+        } else {
+            format!(
+                r#"<tr><td style="padding: 16px 24px; font-family: Helvetica, Arial, sans-serif; font-size: 20px; font-weight: bold; color: {brand_color};">{}</td></tr>"#,
+                lines_html(&self.header)
+            )
+        };
+
+        let mut footer: Vec<String> = [&self.footer, &self.postal_address]
+            .into_iter()
+            .filter(|text| !text.trim().is_empty())
+            .map(|text| lines_html(text))
+            .collect();
+        if let Some(url) = unsubscribe_url {
+            footer.push(format!(
+                r#"Don't want these emails anymore? <a href="{}" style="color: {brand_color};">Unsubscribe</a>."#,
+                encode_minimal(url)
+            ));
+        }
+
+        format!(
+            r#"<table role="presentation" width="100%" cellpadding="0" cellspacing="0" border="0" bgcolor="{background_color}" style="background-color: {background_color};">
+<tr><td align="center" style="padding: 24px 12px;">
+<table role="presentation" width="600" cellpadding="0" cellspacing="0" border="0" bgcolor="{background}" style="width: 100%; max-width: 600px; background-color: {background}; border-top: 4px solid {brand_color};">
+{header}
+<tr><td style="padding: 24px;">{content}</td></tr>
+<tr><td style="padding: 16px 24px; font-family: Helvetica, Arial, sans-serif; font-size: 12px; line-height: 1.5; color: #777777;">{footer}</td></tr>
+</table>
+</td></tr>
+</table>"#,
+            background = "#ffffff",
+            footer = footer.join("<br /><br />"),
+        )
+    }
+
+    pub fn wrap_text(&self, content: &str, unsubscribe_url: Option<&str>) -> String {
+        let mut text = String::new();
+        if !self.header.trim().is_empty() {
+            text.push_str(self.header.trim());
+            text.push_str("\n\n");
+        }
+        text.push_str(content);
+
+        let mut footer: Vec<&str> = [&self.footer, &self.postal_address]
+            .into_iter()
+            .map(|text| text.trim())
+            .filter(|text| !text.is_empty())
+            .collect();
+        let unsubscribe = unsubscribe_url.map(|url| format!("Unsubscribe: {}", url));
+        footer.extend(unsubscribe.as_deref());
+        if !footer.is_empty() {
+            text.push_str("\n\n--\n");
+            text.push_str(&footer.join("\n\n"));
+        }
+
+        text
+    }
+}
+
+// Transactional emails are wrapped without an unsubscribe link, their
+// recipients are not subscribed to anything yet or anymore
+pub fn apply_layout(layout: Option<&EmailLayout>, message: EmailMessage) -> EmailMessage {
+    match layout {
+        Some(layout) => EmailMessage {
+            html_content: layout.wrap_html(&message.html_content, None),
+            text_content: layout.wrap_text(&message.text_content, None),
+            ..message
+        },
+        None => message,
+    }
+}
+
+fn lines_html(text: &str) -> String {
+    text.trim()
+        .lines()
+        .map(encode_minimal)
+        .collect::<Vec<_>>()
+        .join("<br />")
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_layouts(pool: &PgPool) -> Result<Vec<EmailLayout>, anyhow::Error> {
+    let layouts = sqlx::query_as!(
+        EmailLayoutRow,
+        r#"
+        SELECT
+            layout_id,
+            name,
+            header,
+            footer,
+            postal_address,
+            brand_color,
+            background_color,
+            is_default
+        FROM email_layouts
+        ORDER BY name
+    "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch the email layouts")?
+    .into_iter()
+    .map(EmailLayout::from)
+    .collect();
+
+    Ok(layouts)
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_layout(
+    pool: &PgPool,
+    layout_id: uuid::Uuid,
+) -> Result<Option<EmailLayout>, anyhow::Error> {
+    let layout = sqlx::query_as!(
+        EmailLayoutRow,
+        r#"
+        SELECT
+            layout_id,
+            name,
+            header,
+            footer,
+            postal_address,
+            brand_color,
+            background_color,
+            is_default
+        FROM email_layouts
+        WHERE layout_id = $1
+    "#,
+        sqlx::types::Uuid::from_bytes(layout_id.into_bytes())
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch the email layout")?
+    .map(EmailLayout::from);
+
+    Ok(layout)
+}
+
+// `None` when emails are sent as written
+#[tracing::instrument(skip_all)]
+pub async fn get_default_layout(pool: &PgPool) -> Result<Option<EmailLayout>, anyhow::Error> {
+    let layout = sqlx::query_as!(
+        EmailLayoutRow,
+        r#"
+        SELECT
+            layout_id,
+            name,
+            header,
+            footer,
+            postal_address,
+            brand_color,
+            background_color,
+            is_default
+        FROM email_layouts
+        WHERE is_default
+    "#
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch the default email layout")?
+    .map(EmailLayout::from);
+
+    Ok(layout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EmailLayout;
+
+    fn layout() -> EmailLayout {
+        EmailLayout {
+            layout_id: uuid::Uuid::nil(),
+            name: "Default".into(),
+            header: "Rust & friends".into(),
+            footer: "You subscribed on our website.".into(),
+            postal_address: "1 Main Street\nSpringfield".into(),
+            brand_color: "#1a5fb4".into(),
+            background_color: "#f4f4f4".into(),
+            is_default: true,
+        }
+    }
+
+    #[test]
+    fn the_html_part_is_wrapped_in_the_layout() {
+        let html = layout().wrap_html(
+            "<p>Hello</p>",
+            Some("https://example.com/unsubscribe?a=1&b=2"),
+        );
+        assert!(html.contains(r##"bgcolor="#f4f4f4""##));
+        assert!(html.contains("border-top: 4px solid #1a5fb4;"));
+        assert!(html.contains(">Rust &amp; friends</td>"));
+        assert!(html.contains(r#"<td style="padding: 24px;"><p>Hello</p></td>"#));
+        assert!(html.contains("1 Main Street<br />Springfield"));
+        assert!(html.contains(r#"<a href="https://example.com/unsubscribe?a=1&amp;b=2""#));
+    }
+
+    #[test]
+    fn the_text_part_is_wrapped_in_the_layout() {
+        let text = layout().wrap_text("Hello", Some("https://example.com/unsubscribe"));
+        assert_eq!(
+            text,
+            "Rust & friends\n\
+            \n\
+            Hello\n\
+            \n\
+            --\n\
+            You subscribed on our website.\n\
+            \n\
+            1 Main Street\n\
+            Springfield\n\
+            \n\
+            Unsubscribe: https://example.com/unsubscribe"
+        );
+    }
+
+    #[test]
+    fn empty_parts_of_the_layout_are_left_out() {
+        let layout = EmailLayout {
+            header: "".into(),
+            footer: " ".into(),
+            ..layout()
+        };
+        assert_eq!(
+            layout.wrap_text("Hello", None),
+            "Hello\n\n--\n1 Main Street\nSpringfield"
+        );
+        assert!(!layout.wrap_html("Hello", None).contains("Unsubscribe"));
+    }
+}
@@ -1,22 +1,52 @@
 use crate::{
-    configuration::Settings, domain::SubscriberEmail, email_client::EmailClient,
-    startup::get_connection_pool,
+    configuration::{IssueDeliverySettings, Settings},
+    domain::{
+        MergeTemplate, MergeValues, SubscriberEmail, TrackedEvent, TrackingToken, UnsubscribeToken,
+    },
+    email_client::{EmailHeader, EmailMessage, EmailTransport},
+    email_html::rewrite_links,
+    email_layouts::get_default_layout,
+    startup::{get_connection_pool, HmacSecret},
+    utils::app_base_url,
 };
+use rand::Rng;
 use sqlx::{PgPool, Postgres, Transaction};
-use tracing::{field::display, Span};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use tracing::Span;
 
 pub async fn run_worker_until_stopped(configuration: Settings) {
     let pool = get_connection_pool(&configuration.database);
-    let email_client = configuration.email_client.client();
+    let email_client = configuration
+        .email_client
+        .client()
+        .expect("Failed to build the email client");
+    let base_url = app_base_url(
+        &configuration.application.base_url,
+        configuration.application.port,
+    );
+    let hmac_secret = HmacSecret(configuration.application.hmac_secret);
 
-    worker_loop(&pool, &email_client)
-        .await
-        .expect("Failed to start worker loop");
+    worker_loop(
+        &pool,
+        email_client.as_ref(),
+        &base_url,
+        &hmac_secret,
+        &configuration.issue_delivery,
+    )
+    .await
+    .expect("Failed to start worker loop");
 }
 
-async fn worker_loop(pool: &PgPool, email_client: &EmailClient) -> Result<(), anyhow::Error> {
+async fn worker_loop(
+    pool: &PgPool,
+    email_client: &dyn EmailTransport,
+    base_url: &str,
+    hmac_secret: &HmacSecret,
+    settings: &IssueDeliverySettings,
+) -> Result<(), anyhow::Error> {
     loop {
-        match try_execute_task(pool, email_client).await {
+        match try_execute_task(pool, email_client, base_url, hmac_secret, settings).await {
             Ok(ExecutionOutput::EmptyQueue) => {
                 tokio::time::sleep(std::time::Duration::from_secs(10)).await;
             }
@@ -29,110 +59,563 @@ async fn worker_loop(pool: &PgPool, email_client: &EmailClient) -> Result<(), an
     }
 }
 
+// Queue a delivery of the issue for every confirmed member of its list,
+// only the members of its segment when it has one
+#[tracing::instrument(skip_all)]
+pub async fn enqueue_delivery_task(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: uuid::Uuid,
+) -> Result<(), sqlx::Error> {
+    let sqlx_uuid = sqlx::types::Uuid::from_bytes(newsletter_issue_id.into_bytes());
+
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+        SELECT newsletter_issue.newsletter_issue_id, subscriptions.email
+        FROM newsletter_issue
+        JOIN list_memberships ON list_memberships.list_id = newsletter_issue.list_id
+        JOIN subscriptions ON subscriptions.id = list_memberships.subscriber_id
+        WHERE
+            newsletter_issue.newsletter_issue_id = $1 AND
+            list_memberships.status = 'confirmed' AND
+            subscriptions.status = 'confirmed' AND
+            (
+                newsletter_issue.segment_id IS NULL OR
+                EXISTS (
+                    SELECT 1 FROM segment_members
+                    WHERE
+                        segment_members.segment_id = newsletter_issue.segment_id AND
+                        segment_members.subscriber_id = subscriptions.id
+                )
+            )
+    "#,
+        sqlx_uuid
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
 pub enum ExecutionOutput {
     TaskCompleted,
     EmptyQueue,
 }
 
-#[tracing::instrument(
-    skip_all,
-    fields(
-        newsletter_issue_id=tracing::field::Empty,
-        subscriber_email=tracing::field::Empty
-    ),
-    err
-)]
+#[tracing::instrument(skip_all, fields(n_tasks = tracing::field::Empty), err)]
 pub async fn try_execute_task(
     pool: &PgPool,
-    email_client: &EmailClient,
+    email_client: &dyn EmailTransport,
+    base_url: &str,
+    hmac_secret: &HmacSecret,
+    settings: &IssueDeliverySettings,
 ) -> Result<ExecutionOutput, anyhow::Error> {
-    let task = dequeue_task(pool).await?;
-    if task.is_none() {
-        return Ok(ExecutionOutput::EmptyQueue);
-    }
-
-    let (transaction, newsletter_issue_id, subscriber_email) = task.unwrap();
+    let (mut transaction, tasks) =
+        match dequeue_tasks(pool, settings.batch_size.get().into()).await? {
+            Some(dequeued) => dequeued,
+            None => return Ok(ExecutionOutput::EmptyQueue),
+        };
+    Span::current().record("n_tasks", tasks.len());
 
-    Span::current()
-        .record("newsletter_issue_id", &display(&newsletter_issue_id))
-        .record("subscriber_email", &display(&subscriber_email));
+    let emails: Vec<String> = tasks.iter().map(|t| t.subscriber_email.clone()).collect();
+    let subscribers = get_confirmed_subscribers(pool, &emails).await?;
+    let layout = get_default_layout(pool).await?;
 
     // FUTURE TODO
     // Use cache to store newsletter_issue
     // so that we don't hit the database all the time
-    let issue_data = get_issue(pool, newsletter_issue_id).await?;
-
-    match SubscriberEmail::parse(subscriber_email.clone()) {
-        Ok(email) => {
-            // TODO: Retry
-            // We are only trying sending email only once
-            // so introduce retry
-            if let Err(e) = email_client
-                .send_email(
-                    &email,
-                    &issue_data.title,
-                    &issue_data.html,
-                    &issue_data.text,
+    let mut issues = HashMap::new();
+    let mut deliveries = Vec::with_capacity(tasks.len());
+
+    for task in tasks {
+        // The subscriber may have left after the issue was enqueued
+        let subscriber = match subscribers.get(&task.subscriber_email) {
+            Some(subscriber) => subscriber,
+            None => {
+                tracing::info!(
+                    subscriber_email = %task.subscriber_email,
+                    "Skipping a subscriber that is no longer confirmed"
+                );
+                delete_task(
+                    &mut transaction,
+                    task.newsletter_issue_id,
+                    &task.subscriber_email,
                 )
-                .await
-            {
+                .await?;
+                continue;
+            }
+        };
+
+        let recipient = match SubscriberEmail::parse(task.subscriber_email.clone()) {
+            Ok(email) => email,
+            Err(e) => {
                 tracing::error!(
                     error.cause_chain = ?e,
                     error.message = %e,
-                    "Failed to deliver issue to a confirmed subscriber. \
-                    Skipping.",
+                    subscriber_email = %task.subscriber_email,
+                    "Skipping a confirmed subscriber. \
+                    Their stored contact details are invalid",
                 );
+                log_delivery_attempt(
+                    &mut transaction,
+                    task.newsletter_issue_id,
+                    &task.subscriber_email,
+                    DeliveryOutcome::SkippedInvalidAddress,
+                    None,
+                    Some(&e),
+                )
+                .await?;
+                delete_task(
+                    &mut transaction,
+                    task.newsletter_issue_id,
+                    &task.subscriber_email,
+                )
+                .await?;
+                continue;
             }
+        };
+
+        let issue = match issues.entry(task.newsletter_issue_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(
+                get_issue(pool, task.newsletter_issue_id)
+                    .await?
+                    .into_templates(base_url),
+            ),
+        };
+
+        let unsubscribe_link = unsubscribe_link(base_url, subscriber.id, hmac_secret);
+        let values = MergeValues {
+            name: Some(&subscriber.name),
+            email: Some(recipient.as_ref()),
+            unsubscribe_url: Some(&unsubscribe_link),
+            archive_url: issue.archive_url.as_deref(),
+        };
+        let mut html = issue.html.render_html(&values);
+        let text = issue.text.render_text(&values);
+        if settings.track_engagement && issue.track_engagement {
+            let token = TrackingToken {
+                newsletter_issue_id: issue.newsletter_issue_id,
+                subscriber_id: subscriber.id,
+                event: TrackedEvent::Open,
+            };
+            html = track_engagement(&html, base_url, &token, &unsubscribe_link, hmac_secret);
         }
-        Err(e) => {
-            tracing::error!(
-                error.cause_chain = ?e,
-                error.message = %e,
-                "Skipping a confirmed subscriber. \
-                Their stored contact details are invalid",
-            );
+        let (html_content, text_content) = match &layout {
+            Some(layout) => (
+                layout.wrap_html(&html, Some(&unsubscribe_link)),
+                layout.wrap_text(&text, Some(&unsubscribe_link)),
+            ),
+            None => (
+                format!(
+                    "{}<hr /><p>Don't want these emails anymore? \
+                        <a href=\"{}\">Unsubscribe</a>.</p>",
+                    html, unsubscribe_link
+                ),
+                format!("{}\n\n--\nUnsubscribe: {}", text, unsubscribe_link),
+            ),
+        };
+        let message = EmailMessage {
+            subject: issue.title.clone(),
+            html_content,
+            text_content,
+            headers: unsubscribe_headers(email_client, &unsubscribe_link).to_vec(),
+            recipient,
+        };
+        deliveries.push((task, message));
+    }
+
+    let (tasks, messages): (Vec<_>, Vec<_>) = deliveries.into_iter().unzip();
+    let results = if messages.is_empty() {
+        vec![]
+    } else {
+        email_client.send_email_batch(&messages).await
+    };
+
+    for (task, result) in tasks.into_iter().zip(results) {
+        let Task {
+            newsletter_issue_id,
+            subscriber_email,
+            n_retries,
+        } = task;
+
+        match result {
+            Ok(message_id) => {
+                log_delivery_attempt(
+                    &mut transaction,
+                    newsletter_issue_id,
+                    &subscriber_email,
+                    DeliveryOutcome::Sent,
+                    message_id.as_deref(),
+                    None,
+                )
+                .await?;
+                delete_task(&mut transaction, newsletter_issue_id, &subscriber_email).await?;
+            }
+            Err(e) => {
+                log_delivery_attempt(
+                    &mut transaction,
+                    newsletter_issue_id,
+                    &subscriber_email,
+                    DeliveryOutcome::Failed,
+                    None,
+                    Some(&e.to_string()),
+                )
+                .await?;
+
+                let n_retries = n_retries + 1;
+                if n_retries >= settings.max_retries {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        subscriber_email = %subscriber_email,
+                        "Failed to deliver issue to a confirmed subscriber. \
+                        Giving up after {} attempts.",
+                        n_retries
+                    );
+                    move_task_to_failures(
+                        &mut transaction,
+                        newsletter_issue_id,
+                        &subscriber_email,
+                        n_retries,
+                        &e.to_string(),
+                    )
+                    .await?;
+                } else {
+                    let delay = retry_delay(n_retries, settings.retry_base_delay());
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        subscriber_email = %subscriber_email,
+                        "Failed to deliver issue to a confirmed subscriber. \
+                        Retrying in {:?}.",
+                        delay
+                    );
+                    reschedule_task(
+                        &mut transaction,
+                        newsletter_issue_id,
+                        &subscriber_email,
+                        n_retries,
+                        delay,
+                    )
+                    .await?;
+                }
+            }
         }
     }
 
-    delete_task(transaction, newsletter_issue_id, &subscriber_email).await?;
+    transaction.commit().await?;
 
     Ok(ExecutionOutput::TaskCompleted)
 }
 
-type PgTransaction = Transaction<'static, Postgres>;
+pub enum DeliveryOutcome {
+    Sent,
+    Failed,
+    SkippedInvalidAddress,
+}
 
+impl DeliveryOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryOutcome::Sent => "sent",
+            DeliveryOutcome::Failed => "failed",
+            DeliveryOutcome::SkippedInvalidAddress => "skipped_invalid_address",
+        }
+    }
+}
+
+// Every attempt is recorded, the log is what the per-issue report is built from
 #[tracing::instrument(skip_all)]
-async fn dequeue_task(
+async fn log_delivery_attempt(
+    transaction: &mut PgTransaction,
+    newsletter_issue_id: sqlx::types::Uuid,
+    email: &str,
+    outcome: DeliveryOutcome,
+    provider_message_id: Option<&str>,
+    error: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_log (
+            newsletter_issue_id,
+            subscriber_email,
+            outcome,
+            provider_message_id,
+            error,
+            attempted_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+    "#,
+        newsletter_issue_id,
+        email,
+        outcome.as_str(),
+        provider_message_id,
+        error
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+// Exponential backoff with a random jitter, so that the retries of an issue
+// sent to many subscribers do not all hit the email provider at the same time
+fn retry_delay(n_retries: i16, base: std::time::Duration) -> std::time::Duration {
+    let exponential = base.saturating_mul(2u32.saturating_pow(n_retries.saturating_sub(1) as u32));
+    let jitter = rand::thread_rng().gen_range(0..=base.as_millis() as u64);
+    exponential.saturating_add(std::time::Duration::from_millis(jitter))
+}
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+struct Task {
+    newsletter_issue_id: sqlx::types::Uuid,
+    subscriber_email: String,
+    n_retries: i16,
+}
+
+// Lock up to `batch_size` due deliveries, other workers skip them
+// until the transaction is committed
+#[tracing::instrument(skip(pool))]
+async fn dequeue_tasks(
     pool: &PgPool,
-) -> Result<Option<(PgTransaction, sqlx::types::Uuid, String)>, anyhow::Error> {
+    batch_size: i64,
+) -> Result<Option<(PgTransaction, Vec<Task>)>, anyhow::Error> {
     let mut transaction = pool.begin().await?;
 
-    let r = sqlx::query!(
+    let tasks = sqlx::query_as!(
+        Task,
         r#"
-        SELECT newsletter_issue_id, subscriber_email FROM issue_delivery_queue
+        SELECT newsletter_issue_id, subscriber_email, n_retries
+        FROM issue_delivery_queue
+        WHERE execute_after <= now()
         FOR UPDATE
         SKIP LOCKED
-        LIMIT 1
-    "#
+        LIMIT $1
+    "#,
+        batch_size
     )
-    .fetch_optional(&mut transaction)
+    .fetch_all(&mut transaction)
     .await?;
 
-    if let Some(row) = r {
-        Ok(Some((
-            transaction,
-            row.newsletter_issue_id,
-            row.subscriber_email,
-        )))
-    } else {
-        Ok(None)
+    if tasks.is_empty() {
+        return Ok(None);
     }
+
+    Ok(Some((transaction, tasks)))
+}
+
+#[tracing::instrument(skip_all)]
+async fn reschedule_task(
+    trans: &mut PgTransaction,
+    newsletter_issue_id: sqlx::types::Uuid,
+    email: &str,
+    n_retries: i16,
+    delay: std::time::Duration,
+) -> Result<(), anyhow::Error> {
+    let execute_after = chrono::Utc::now() + chrono::Duration::from_std(delay)?;
+
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            n_retries = $3,
+            execute_after = $4
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+    "#,
+        newsletter_issue_id,
+        email,
+        n_retries,
+        execute_after
+    )
+    .execute(&mut *trans)
+    .await?;
+
+    Ok(())
+}
+
+// Dead-letter the delivery, it stays around for inspection
+// but it is not going to be attempted anymore
+#[tracing::instrument(skip_all)]
+async fn move_task_to_failures(
+    trans: &mut PgTransaction,
+    newsletter_issue_id: sqlx::types::Uuid,
+    email: &str,
+    n_retries: i16,
+    last_error: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_failures
+            (newsletter_issue_id, subscriber_email, n_retries, last_error, failed_at)
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET
+            n_retries = EXCLUDED.n_retries,
+            last_error = EXCLUDED.last_error,
+            failed_at = EXCLUDED.failed_at
+    "#,
+        newsletter_issue_id,
+        email,
+        n_retries,
+        last_error
+    )
+    .execute(&mut *trans)
+    .await?;
+
+    delete_task(trans, newsletter_issue_id, email).await
+}
+
+pub fn unsubscribe_link(
+    base_url: &str,
+    subscriber_id: uuid::Uuid,
+    hmac_secret: &HmacSecret,
+) -> String {
+    let token = UnsubscribeToken::generate(subscriber_id, &hmac_secret.0);
+    format!(
+        "{}/subscription/unsubscribe?subscriber_id={}&token={}",
+        base_url,
+        subscriber_id,
+        token.as_ref()
+    )
+}
+
+// RFC 2369 & RFC 8058 headers, so that mail clients can show their own
+// unsubscribe button and POST to the link without opening a browser
+fn unsubscribe_headers(
+    email_client: &dyn EmailTransport,
+    unsubscribe_link: &str,
+) -> [EmailHeader; 2] {
+    [
+        EmailHeader::new(
+            "List-Unsubscribe",
+            format!(
+                "<mailto:{}?subject=unsubscribe>, <{}>",
+                email_client.sender(),
+                unsubscribe_link
+            ),
+        ),
+        EmailHeader::new("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"),
+    ]
+}
+
+struct Subscriber {
+    id: uuid::Uuid,
+    name: String,
+}
+
+// The subscribers among `emails` that are still confirmed, by email
+#[tracing::instrument(skip_all)]
+async fn get_confirmed_subscribers(
+    pool: &PgPool,
+    emails: &[String],
+) -> Result<HashMap<String, Subscriber>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, email, name FROM subscriptions
+        WHERE email = ANY($1) AND status = 'confirmed'
+    "#,
+        emails
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| {
+            let subscriber = Subscriber {
+                id: uuid::Uuid::from_bytes(*r.id.as_bytes()),
+                name: r.name,
+            };
+            (r.email, subscriber)
+        })
+        .collect())
 }
 
 struct NewsletterIssueData {
+    newsletter_issue_id: sqlx::types::Uuid,
     title: String,
     text: String,
     html: String,
+    slug: Option<String>,
+    show_in_archive: bool,
+    track_engagement: bool,
+}
+
+// An issue ready to be rendered for each of its recipients
+struct IssueTemplates {
+    newsletter_issue_id: uuid::Uuid,
+    title: String,
+    text: MergeTemplate,
+    html: MergeTemplate,
+    // `None` when the issue is not in the public archive
+    archive_url: Option<String>,
+    track_engagement: bool,
+}
+
+impl NewsletterIssueData {
+    // Issues published from the admin have valid merge tags, the others
+    // (e.g. created from a blog post) are sent as is when they do not parse
+    fn into_templates(self, base_url: &str) -> IssueTemplates {
+        let archive_url = self.show_in_archive.then(|| match &self.slug {
+            Some(slug) => format!("{}/issues/{}", base_url, slug),
+            None => format!("{}/issues/{}", base_url, self.newsletter_issue_id),
+        });
+
+        IssueTemplates {
+            newsletter_issue_id: uuid::Uuid::from_bytes(*self.newsletter_issue_id.as_bytes()),
+            title: self.title,
+            text: MergeTemplate::parse_or_verbatim(&self.text),
+            html: MergeTemplate::parse_or_verbatim(&self.html),
+            archive_url,
+            track_engagement: self.track_engagement,
+        }
+    }
+}
+
+// Links to web pages go through `/t/c/{token}`, and the open is recorded
+// when the pixel at `/t/o/{token}.gif` is loaded. The unsubscribe link is
+// left alone, leaving is not engagement.
+fn track_engagement(
+    html: &str,
+    base_url: &str,
+    open: &TrackingToken,
+    unsubscribe_link: &str,
+    hmac_secret: &HmacSecret,
+) -> String {
+    let click_url = {
+        let base_url = base_url.to_owned();
+        let unsubscribe_link = unsubscribe_link.to_owned();
+        let secret = hmac_secret.0.clone();
+        let (newsletter_issue_id, subscriber_id) = (open.newsletter_issue_id, open.subscriber_id);
+        move |url: &str| {
+            if url == unsubscribe_link {
+                return None;
+            }
+            // Serialized, the link is percent-encoded ASCII, a valid `Location`
+            let url = reqwest::Url::parse(url).ok()?;
+            if !matches!(url.scheme(), "https" | "http") {
+                return None;
+            }
+            let token = TrackingToken {
+                newsletter_issue_id,
+                subscriber_id,
+                event: TrackedEvent::Click(url.into()),
+            };
+            Some(format!("{}/t/c/{}", base_url, token.sign(&secret)))
+        }
+    };
+
+    format!(
+        "{}<img src=\"{}/t/o/{}.gif\" width=\"1\" height=\"1\" alt=\"\" \
+            style=\"display: block; border: 0;\">",
+        rewrite_links(html, click_url),
+        base_url,
+        open.sign(&hmac_secret.0)
+    )
 }
 
 #[tracing::instrument(skip_all)]
@@ -143,7 +626,10 @@ async fn get_issue(
     let issue_data = sqlx::query_as!(
         NewsletterIssueData,
         r#"
-            SELECT title, text, html from newsletter_issue
+            SELECT
+                newsletter_issue_id, title, text, html, slug, show_in_archive,
+                track_engagement
+            FROM newsletter_issue
             WHERE 
                 newsletter_issue_id = $1
         "#,
@@ -157,7 +643,7 @@ async fn get_issue(
 
 #[tracing::instrument(skip_all)]
 async fn delete_task(
-    mut trans: PgTransaction,
+    trans: &mut PgTransaction,
     newsletter_issue_id: sqlx::types::Uuid,
     email: &str,
 ) -> Result<(), anyhow::Error> {
@@ -171,9 +657,31 @@ async fn delete_task(
         newsletter_issue_id,
         email
     )
-    .execute(&mut trans)
+    .execute(&mut *trans)
     .await?;
 
-    trans.commit().await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::retry_delay;
+    use std::time::Duration;
+
+    #[test]
+    fn retry_delay_grows_exponentially() {
+        let base = Duration::from_millis(100);
+        for n in 1..=5 {
+            let delay = retry_delay(n, base);
+            let exponential = base * 2u32.pow(n as u32 - 1);
+            assert!(delay >= exponential);
+            assert!(delay <= exponential + base);
+        }
+    }
+
+    #[test]
+    fn retry_delay_does_not_overflow() {
+        let delay = retry_delay(i16::MAX, Duration::from_secs(1));
+        assert!(delay >= Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,63 @@
+use sqlx::PgPool;
+
+use crate::{
+    configuration::Settings, issue_delivery_workers::enqueue_delivery_task,
+    startup::get_connection_pool,
+};
+
+pub async fn run_scheduler_until_stopped(configuration: Settings) {
+    let pool = get_connection_pool(&configuration.database);
+
+    loop {
+        if let Err(e) = publish_scheduled_issues(&pool).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to publish scheduled newsletter issues"
+            );
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(30)).await;
+    }
+}
+
+// Publish every scheduled issue whose time has come and enqueue its deliveries,
+// returns the number of published issues
+#[tracing::instrument(skip_all, err)]
+pub async fn publish_scheduled_issues(pool: &PgPool) -> Result<usize, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+
+    // Locked until the transaction is committed, so that an admin can't
+    // reschedule or cancel an issue while it is being published
+    let due_issues = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id
+        FROM newsletter_issue
+        WHERE published_at IS NULL AND scheduled_at <= now()
+        FOR UPDATE
+        SKIP LOCKED
+    "#
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+
+    for issue in &due_issues {
+        sqlx::query!(
+            r#"
+            UPDATE newsletter_issue
+            SET published_at = now()
+            WHERE newsletter_issue_id = $1
+        "#,
+            issue.newsletter_issue_id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        let newsletter_issue_id = uuid::Uuid::from_bytes(*issue.newsletter_issue_id.as_bytes());
+        enqueue_delivery_task(&mut transaction, newsletter_issue_id).await?;
+
+        tracing::info!(%newsletter_issue_id, "Published a scheduled newsletter issue");
+    }
+
+    transaction.commit().await?;
+    Ok(due_issues.len())
+}
@@ -2,10 +2,19 @@ pub mod authentication;
 pub mod configuration;
 pub mod domain;
 pub mod email_client;
+pub mod email_html;
+pub mod email_layouts;
 pub mod idempotency;
 pub mod issue_delivery_workers;
+pub mod issue_scheduler;
+pub mod mailing_lists;
+pub mod markdown;
 pub mod routes;
+pub mod rss_to_email;
+pub mod segments;
 pub mod session_state;
 pub mod startup;
+pub mod subscriber_import;
+pub mod subscription_cleanup;
 pub mod telemetry;
 pub mod utils;
@@ -0,0 +1,120 @@
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+
+// The list created along with the `lists` table. Subscribers who do not
+// choose a list join it, and issues go to it unless told otherwise.
+pub const DEFAULT_LIST_ID: uuid::Uuid = uuid::uuid!("00000000-0000-0000-0000-000000000001");
+
+pub struct MailingList {
+    pub list_id: uuid::Uuid,
+    pub slug: String,
+    pub name: String,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_lists(pool: &PgPool) -> Result<Vec<MailingList>, anyhow::Error> {
+    let lists = sqlx::query!(r#"SELECT list_id, slug, name FROM lists ORDER BY created_at, name"#)
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch the lists")?
+        .into_iter()
+        .map(|r| MailingList {
+            list_id: uuid::Uuid::from_bytes(*r.list_id.as_bytes()),
+            slug: r.slug,
+            name: r.name,
+        })
+        .collect();
+
+    Ok(lists)
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_list_id(pool: &PgPool, slug: &str) -> Result<Option<uuid::Uuid>, sqlx::Error> {
+    let list_id = sqlx::query!(r#"SELECT list_id FROM lists WHERE slug = $1"#, slug)
+        .fetch_optional(pool)
+        .await?
+        .map(|r| uuid::Uuid::from_bytes(*r.list_id.as_bytes()));
+
+    Ok(list_id)
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn list_exists(pool: &PgPool, list_id: uuid::Uuid) -> Result<bool, anyhow::Error> {
+    let exists = sqlx::query!(
+        r#"SELECT EXISTS (SELECT 1 FROM lists WHERE list_id = $1) as "exists!""#,
+        sqlx::types::Uuid::from_bytes(list_id.into_bytes())
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to look up the list")?
+    .exists;
+
+    Ok(exists)
+}
+
+// Makes the subscriber a pending member of the list, unless they already
+// are a confirmed one. Returns whether the membership awaits a confirmation.
+#[tracing::instrument(skip(transaction))]
+pub async fn join_list(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: sqlx::types::Uuid,
+    list_id: uuid::Uuid,
+) -> Result<bool, sqlx::Error> {
+    let n_pending = sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at)
+        VALUES ($1, $2, 'pending_confirmation', now())
+        ON CONFLICT (list_id, subscriber_id) DO UPDATE
+        SET
+            status = 'pending_confirmation',
+            subscribed_at = CASE
+                WHEN list_memberships.status = 'unsubscribed' THEN now()
+                ELSE list_memberships.subscribed_at
+            END
+        WHERE list_memberships.status != 'confirmed'
+    "#,
+        sqlx::types::Uuid::from_bytes(list_id.into_bytes()),
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?
+    .rows_affected();
+
+    Ok(n_pending == 1)
+}
+
+// A confirmation link confirms every list joined since the previous one
+#[tracing::instrument(skip(transaction))]
+pub async fn confirm_pending_memberships(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: sqlx::types::Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships SET status = 'confirmed'
+        WHERE subscriber_id = $1 AND status = 'pending_confirmation'
+    "#,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+// Unsubscribing leaves every list, subscribing again only joins the
+// list of the form
+#[tracing::instrument(skip(transaction))]
+pub async fn leave_all_lists(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: sqlx::types::Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE list_memberships SET status = 'unsubscribed' WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
@@ -1,11 +1,25 @@
 use newsletter::configuration::get_configuration;
 use newsletter::idempotency::run_idempotency_worker;
 use newsletter::issue_delivery_workers::run_worker_until_stopped;
+use newsletter::issue_scheduler::run_scheduler_until_stopped;
+use newsletter::rss_to_email::run_rss_to_email_until_stopped;
 use newsletter::startup::Application;
+use newsletter::subscriber_import::import_subscribers_from_file;
+use newsletter::subscription_cleanup::run_subscription_cleanup_until_stopped;
 use newsletter::telemetry::{get_subscriber, init_subscriber};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    // newsletter import-subscribers <file.csv> <confirmed|pending>
+    // Runs without the JSON logs, stdout is the error report of the import
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let [command, path, mode] = args.as_slice() {
+        if command == "import-subscribers" {
+            let configuration = get_configuration().expect("Failed to read configuration.");
+            return import_subscribers_from_file(configuration, path, mode).await;
+        }
+    }
+
     let subscriber = get_subscriber("newsletter".into(), "info".into());
     init_subscriber(subscriber);
 
@@ -16,6 +30,11 @@ async fn main() -> anyhow::Result<()> {
     let worker_run = tokio::spawn(run_worker_until_stopped(configuration.clone()));
 
     let idempotency_worker = tokio::spawn(run_idempotency_worker(configuration.clone()));
+    let scheduler_run = tokio::spawn(run_scheduler_until_stopped(configuration.clone()));
+    let rss_to_email_run = tokio::spawn(run_rss_to_email_until_stopped(configuration.clone()));
+    let subscription_cleanup_run = tokio::spawn(run_subscription_cleanup_until_stopped(
+        configuration.clone(),
+    ));
 
     println!("Started server at post {}", configuration.application.port);
 
@@ -30,6 +49,15 @@ async fn main() -> anyhow::Result<()> {
         _ = idempotency_worker => {
             println!("Idempotency worker exited");
         },
+        _ = scheduler_run => {
+            println!("Scheduler exited");
+        },
+        _ = rss_to_email_run => {
+            println!("RSS to email task exited");
+        },
+        _ = subscription_cleanup_run => {
+            println!("Subscription cleanup task exited");
+        },
     };
 
     Ok(())
@@ -0,0 +1,332 @@
+use pulldown_cmark::{html, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+
+// Lines of the plain text part are wrapped at this width, which reads well
+// in any mail client
+const TEXT_WIDTH: usize = 72;
+
+// Scoped to the `issue` class so that it does not leak into the pages an
+// issue is shown in, e.g. the public archive. Only properties the main mail
+// clients understand are used.
+const STYLESHEET: &str = r#"
+.issue { max-width: 600px; margin: 0 auto; font-family: Helvetica, Arial, sans-serif; font-size: 16px; line-height: 1.5; color: #222222; }
+.issue h1, .issue h2, .issue h3 { line-height: 1.25; margin: 1.5em 0 0.5em 0; }
+.issue a { color: #1a5fb4; }
+.issue img { max-width: 100%; height: auto; border: 0; }
+.issue pre { padding: 12px; background-color: #f4f4f4; white-space: pre-wrap; }
+.issue code { font-family: Menlo, Consolas, monospace; font-size: 14px; }
+.issue blockquote { margin: 0; padding-left: 12px; border-left: 4px solid #dddddd; color: #555555; }
+"#;
+
+// The HTML part of an issue written in Markdown, with its stylesheet
+pub fn markdown_to_html(markdown: &str) -> String {
+    let mut body = String::new();
+    html::push_html(&mut body, Parser::new_ext(markdown, Options::empty()));
+    // Merge tags used as a link target, e.g. `[Leave]({{unsubscribe_url}})`,
+    // have been percent-encoded
+    let body = body.replace("%7B%7B", "{{").replace("%7D%7D", "}}");
+
+    format!(
+        "<style>{}</style>\n<div class=\"issue\">\n{}</div>",
+        STYLESHEET, body
+    )
+}
+
+// The plain text part of an issue written in Markdown: links become
+// numbered footnotes and paragraphs are wrapped
+pub fn markdown_to_text(markdown: &str) -> String {
+    let mut renderer = TextRenderer::default();
+    for event in Parser::new_ext(markdown, Options::empty()) {
+        renderer.event(event);
+    }
+
+    renderer.finish()
+}
+
+// A block the lines are nested in, e.g. a quote or a list item
+struct Container {
+    // `- ` or `1. ` for the first line of a list item, spaces for the next ones
+    first: String,
+    rest: String,
+    started: bool,
+}
+
+impl Container {
+    fn new(first: &str, rest: &str) -> Self {
+        Self {
+            first: first.to_owned(),
+            rest: rest.to_owned(),
+            started: false,
+        }
+    }
+}
+
+#[derive(Default)]
+struct TextRenderer {
+    output: String,
+    // The text of the paragraph, heading or list item being rendered
+    inline: String,
+    containers: Vec<Container>,
+    // The next number of each ordered list, `None` for bullet lists
+    lists: Vec<Option<u64>>,
+    // The target of the links being rendered, with the start of their label
+    links: Vec<(String, usize)>,
+    footnotes: Vec<String>,
+    code_block: Option<String>,
+}
+
+impl TextRenderer {
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(Tag::Paragraph) | Event::Start(Tag::Heading { .. }) => self.flush(),
+            Event::End(TagEnd::Paragraph) => {
+                self.flush();
+                self.blank_line();
+            }
+            Event::End(TagEnd::Heading(level)) => {
+                let width = self.inline.trim().chars().count().min(TEXT_WIDTH);
+                self.flush();
+                let underline = if level == HeadingLevel::H1 { "=" } else { "-" };
+                self.write_line(&underline.repeat(width));
+                self.blank_line();
+            }
+            Event::Start(Tag::List(start)) => {
+                self.flush();
+                self.lists.push(start);
+            }
+            Event::End(TagEnd::List(_)) => {
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.blank_line();
+                }
+            }
+            Event::Start(Tag::Item) => {
+                self.flush();
+                let marker = match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}. ", *n - 1)
+                    }
+                    _ => "- ".into(),
+                };
+                let indent = " ".repeat(marker.len());
+                self.containers.push(Container::new(&marker, &indent));
+            }
+            Event::Start(Tag::BlockQuote(_)) => {
+                self.flush();
+                self.containers.push(Container::new("> ", "> "));
+            }
+            Event::End(TagEnd::Item) | Event::End(TagEnd::BlockQuote(_)) => {
+                self.flush();
+                self.containers.pop();
+            }
+            Event::Start(Tag::CodeBlock(_)) => {
+                self.flush();
+                self.code_block = Some(String::new());
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                // Code is indented but never wrapped
+                let code = self.code_block.take().unwrap_or_default();
+                for line in code.lines() {
+                    self.write_line(&format!("    {}", line));
+                }
+                self.blank_line();
+            }
+            Event::Start(Tag::Link { dest_url, .. })
+            | Event::Start(Tag::Image { dest_url, .. }) => {
+                self.links.push((dest_url.to_string(), self.inline.len()));
+            }
+            Event::End(TagEnd::Link) | Event::End(TagEnd::Image) => {
+                if let Some((url, start)) = self.links.pop() {
+                    self.footnote(url, start);
+                }
+            }
+            Event::Text(text) => match &mut self.code_block {
+                Some(code) => code.push_str(&text),
+                None => self.inline.push_str(&text),
+            },
+            Event::Code(code) => self.inline.push_str(&code),
+            Event::SoftBreak => self.inline.push(' '),
+            Event::HardBreak => self.inline.push('\n'),
+            Event::Rule => {
+                self.flush();
+                self.write_line(&"-".repeat(10));
+                self.blank_line();
+            }
+            // Raw HTML only makes sense in the HTML part
+            _ => {}
+        }
+    }
+
+    // Links whose label is their target, e.g. `<https://example.com>`, are
+    // left as is
+    fn footnote(&mut self, url: String, start: usize) {
+        let label = self.inline[start..].trim();
+        if label == url || url.strip_prefix("mailto:") == Some(label) {
+            return;
+        }
+
+        let n = match self.footnotes.iter().position(|f| *f == url) {
+            Some(i) => i + 1,
+            None => {
+                self.footnotes.push(url);
+                self.footnotes.len()
+            }
+        };
+        self.inline.push_str(&format!(" [{}]", n));
+    }
+
+    // Writes the pending inline text, wrapped
+    fn flush(&mut self) {
+        let inline = std::mem::take(&mut self.inline);
+        let prefix_width = self.prefix().chars().count();
+        let width = TEXT_WIDTH.saturating_sub(prefix_width).max(20);
+
+        for hard_line in inline.split('\n') {
+            let mut line = String::new();
+            for word in words(hard_line) {
+                let line_width = line.chars().count();
+                if line_width > 0 && line_width + 1 + word.chars().count() > width {
+                    self.write_line(&line);
+                    line.clear();
+                }
+                if !line.is_empty() {
+                    line.push(' ');
+                }
+                line.push_str(&word);
+            }
+            if !line.is_empty() {
+                self.write_line(&line);
+            }
+        }
+    }
+
+    fn prefix(&self) -> String {
+        self.containers
+            .iter()
+            .map(|c| {
+                if c.started {
+                    c.rest.as_str()
+                } else {
+                    c.first.as_str()
+                }
+            })
+            .collect()
+    }
+
+    fn write_line(&mut self, line: &str) {
+        let prefix = self.prefix();
+        self.output.push_str(&prefix);
+        self.output.push_str(line);
+        self.output.push('\n');
+        for container in &mut self.containers {
+            container.started = true;
+        }
+    }
+
+    fn blank_line(&mut self) {
+        if !self.output.is_empty() && !self.output.ends_with("\n\n") {
+            self.output.push('\n');
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.flush();
+        let mut text = self.output.trim_end().to_owned();
+        if !self.footnotes.is_empty() {
+            text.push('\n');
+            for (i, url) in self.footnotes.iter().enumerate() {
+                text.push_str(&format!("\n[{}] {}", i + 1, url));
+            }
+        }
+
+        text
+    }
+}
+
+// Merge tags, e.g. `{{ name | dear reader }}`, are never split across lines
+fn words(line: &str) -> Vec<String> {
+    let mut words: Vec<String> = vec![];
+    let mut in_tag = false;
+    for word in line.split_whitespace() {
+        match words.last_mut() {
+            Some(last) if in_tag => {
+                last.push(' ');
+                last.push_str(word);
+            }
+            _ => words.push(word.to_owned()),
+        }
+        let last = words.last().unwrap();
+        in_tag = match (last.rfind("{{"), last.rfind("}}")) {
+            (Some(open), Some(close)) => open > close,
+            (Some(_), None) => true,
+            _ => false,
+        };
+    }
+
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{markdown_to_html, markdown_to_text};
+
+    #[test]
+    fn the_html_part_is_styled() {
+        let html = markdown_to_html("# Hello\n\nIt's *me*, [there]({{unsubscribe_url}}).");
+        assert!(html.starts_with("<style>"));
+        assert!(html.contains(r#"<div class="issue">"#));
+        assert!(html.contains("<h1>Hello</h1>"));
+        assert!(
+            html.contains(r#"<p>It's <em>me</em>, <a href="{{unsubscribe_url}}">there</a>.</p>"#)
+        );
+    }
+
+    #[test]
+    fn links_become_footnotes_in_the_text_part() {
+        let text = markdown_to_text(
+            "Read [the post](https://example.com/post), [again](https://example.com/post) \
+            or <https://example.com>.\n\n![A cat](https://example.com/cat.png)",
+        );
+        assert_eq!(
+            text,
+            "Read the post [1], again [1] or https://example.com.\n\
+            \n\
+            A cat [2]\n\
+            \n\
+            [1] https://example.com/post\n\
+            [2] https://example.com/cat.png"
+        );
+    }
+
+    #[test]
+    fn paragraphs_are_wrapped_but_not_merge_tags() {
+        let text = markdown_to_text(&format!(
+            "{} {{{{ name | dear reader }}}}",
+            "word ".repeat(13)
+        ));
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].chars().count() <= 72);
+        assert_eq!(lines[1], "{{ name | dear reader }}");
+    }
+
+    #[test]
+    fn blocks_keep_their_shape_in_the_text_part() {
+        let text = markdown_to_text(
+            "Title\n=====\n\n- one\n- two\n  1. nested\n\n> quoted\n\n```\nlet x  = 1;\n```",
+        );
+        assert_eq!(
+            text,
+            "Title\n\
+            =====\n\
+            \n\
+            - one\n\
+            - two\n\
+            \x20 1. nested\n\
+            \n\
+            > quoted\n\
+            \n\
+            \x20   let x  = 1;"
+        );
+    }
+}
@@ -50,6 +50,11 @@ fn get_admin_html(username: String) -> String {
             <p>Available actions:</p>
             <ol>
                 <li><a href="/admin/newsletters">Send newsletter issue</a></li>
+                <li><a href="/admin/newsletters/drafts">Drafts</a></li>
+                <li><a href="/admin/subscribers">Subscribers</a></li>
+                <li><a href="/admin/lists">Lists</a></li>
+                <li><a href="/admin/segments">Segments</a></li>
+                <li><a href="/admin/layouts">Email layouts</a></li>
                 <li><a href="/admin/password">Change password</a></li>
                 <li>
                     <form name="logoutForm" action="/admin/logout" method="post">
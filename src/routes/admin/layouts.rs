@@ -0,0 +1,493 @@
+use actix_web::{get, http::header::ContentType, post, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages, Level};
+use anyhow::Context;
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+
+use crate::domain::BrandColor;
+use crate::email_layouts::{get_layout, get_layouts, EmailLayout};
+use crate::markdown::markdown_to_html;
+use crate::utils::{e500, see_other};
+
+// What layouts are previewed around
+const SAMPLE_ISSUE: &str = "# This week in Rust\n\n\
+    Here is what happened since the last issue, with [a link](https://example.com) \
+    to read more.\n\n\
+    - A first piece of news\n\
+    - A second one";
+
+#[derive(serde::Deserialize)]
+pub struct LayoutFormData {
+    name: String,
+    #[serde(default)]
+    header: String,
+    #[serde(default)]
+    footer: String,
+    postal_address: String,
+    brand_color: String,
+    background_color: String,
+}
+
+impl TryFrom<LayoutFormData> for EmailLayout {
+    type Error = String;
+
+    fn try_from(form: LayoutFormData) -> Result<Self, Self::Error> {
+        let name = form.name.trim();
+        if name.is_empty() {
+            return Err("The layout needs a name.".into());
+        }
+        // Anti-spam laws ask for it in every bulk email
+        if form.postal_address.trim().is_empty() {
+            return Err("The layout needs a postal address.".into());
+        }
+        let brand_color = BrandColor::parse(form.brand_color)?;
+        let background_color = BrandColor::parse(form.background_color)?;
+
+        Ok(Self {
+            layout_id: uuid::Uuid::new_v4(),
+            name: name.to_owned(),
+            header: form.header.trim().to_owned(),
+            footer: form.footer.trim().to_owned(),
+            postal_address: form.postal_address.trim().to_owned(),
+            brand_color: brand_color.as_ref().to_owned(),
+            background_color: background_color.as_ref().to_owned(),
+            is_default: false,
+        })
+    }
+}
+
+// GET /admin/layouts
+#[get("/layouts")]
+pub async fn layouts_page(
+    flash_message: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let message = flash_messages_html(&flash_message);
+
+    let layouts = get_layouts(&pool).await.map_err(e500)?;
+    let mut rows = String::new();
+    for layout in layouts {
+        let default = if layout.is_default {
+            "Default".to_owned()
+        } else {
+            format!(
+                r#"<form action="/admin/layouts/{}/default" method="post">
+                    <button type="submit">Make default</button>
+                </form>"#,
+                layout.layout_id
+            )
+        };
+        rows.push_str(&format!(
+            r#"<tr>
+                <td><a href="/admin/layouts/{id}">{}</a></td>
+                <td>{default}</td>
+                <td><a href="/admin/layouts/{id}/preview" target="_blank">preview</a></td>
+            </tr>"#,
+            encode_minimal(&layout.name),
+            id = layout.layout_id,
+        ));
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(get_layouts_page_html(message, rows)))
+}
+
+// POST /admin/layouts
+#[tracing::instrument(name = "Create an email layout", skip(form, pool), fields(name = %form.name))]
+#[post("/layouts")]
+pub async fn create_layout(
+    form: web::Form<LayoutFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let layout = match EmailLayout::try_from(form.into_inner()) {
+        Ok(layout) => layout,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/layouts"));
+        }
+    };
+
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO email_layouts (
+            layout_id,
+            name,
+            header,
+            footer,
+            postal_address,
+            brand_color,
+            background_color,
+            created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, now())
+        ON CONFLICT (name) DO NOTHING
+    "#,
+        sqlx::types::Uuid::from_bytes(layout.layout_id.into_bytes()),
+        layout.name,
+        layout.header,
+        layout.footer,
+        layout.postal_address,
+        layout.brand_color,
+        layout.background_color
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the email layout")
+    .map_err(e500)?
+    .rows_affected();
+
+    if n_inserted == 0 {
+        FlashMessage::error(format!("{} is already the name of a layout.", layout.name)).send();
+    } else {
+        FlashMessage::info(format!("The {} layout has been created.", layout.name)).send();
+    }
+    Ok(see_other("/admin/layouts"))
+}
+
+// GET /admin/layouts/{layout_id}
+#[get("/layouts/{layout_id}")]
+pub async fn edit_layout_page(
+    layout_id: web::Path<uuid::Uuid>,
+    flash_message: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let message = flash_messages_html(&flash_message);
+
+    let layout = match get_layout(&pool, layout_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        Some(layout) => layout,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(get_edit_layout_page_html(message, &layout)))
+}
+
+// POST /admin/layouts/{layout_id}
+#[tracing::instrument(name = "Update an email layout", skip(form, pool))]
+#[post("/layouts/{layout_id}")]
+pub async fn update_layout(
+    layout_id: web::Path<uuid::Uuid>,
+    form: web::Form<LayoutFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let layout_id = layout_id.into_inner();
+    let layout_url = format!("/admin/layouts/{}", layout_id);
+    let layout = match EmailLayout::try_from(form.into_inner()) {
+        Ok(layout) => layout,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&layout_url));
+        }
+    };
+
+    let layout_id = sqlx::types::Uuid::from_bytes(layout_id.into_bytes());
+    let name_taken = sqlx::query!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM email_layouts WHERE name = $1 AND layout_id != $2
+        ) as "exists!"
+    "#,
+        layout.name,
+        layout_id
+    )
+    .fetch_one(pool.get_ref())
+    .await
+    .context("Failed to look up the name of the email layout")
+    .map_err(e500)?
+    .exists;
+    if name_taken {
+        FlashMessage::error(format!("{} is already the name of a layout.", layout.name)).send();
+        return Ok(see_other(&layout_url));
+    }
+
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE email_layouts
+        SET
+            name = $2,
+            header = $3,
+            footer = $4,
+            postal_address = $5,
+            brand_color = $6,
+            background_color = $7
+        WHERE layout_id = $1
+    "#,
+        layout_id,
+        layout.name,
+        layout.header,
+        layout.footer,
+        layout.postal_address,
+        layout.brand_color,
+        layout.background_color
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update the email layout")
+    .map_err(e500)?
+    .rows_affected();
+
+    if n_updated == 0 {
+        FlashMessage::error("The layout does not exist anymore.").send();
+        return Ok(see_other("/admin/layouts"));
+    }
+
+    FlashMessage::info("The layout has been saved.").send();
+    Ok(see_other(&layout_url))
+}
+
+// POST /admin/layouts/{layout_id}/default
+// Issues and transactional emails sent from now on are wrapped in the layout
+#[tracing::instrument(name = "Make an email layout the default", skip(pool))]
+#[post("/layouts/{layout_id}/default")]
+pub async fn set_default_layout(
+    layout_id: web::Path<uuid::Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let layout_id = sqlx::types::Uuid::from_bytes(layout_id.into_inner().into_bytes());
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+
+    // Unset first, the unique index allows a single default at any time
+    sqlx::query!(
+        r#"UPDATE email_layouts SET is_default = false WHERE is_default AND layout_id != $1"#,
+        layout_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to unset the default email layout")
+    .map_err(e500)?;
+    let n_updated = sqlx::query!(
+        r#"UPDATE email_layouts SET is_default = true WHERE layout_id = $1"#,
+        layout_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to set the default email layout")
+    .map_err(e500)?
+    .rows_affected();
+
+    if n_updated == 0 {
+        FlashMessage::error("The layout does not exist anymore.").send();
+        return Ok(see_other("/admin/layouts"));
+    }
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the default email layout")
+        .map_err(e500)?;
+
+    FlashMessage::info("Emails are now sent with this layout.").send();
+    Ok(see_other("/admin/layouts"))
+}
+
+// POST /admin/layouts/{layout_id}/delete
+// Emails are sent as written when the default layout is deleted
+#[tracing::instrument(name = "Delete an email layout", skip(pool))]
+#[post("/layouts/{layout_id}/delete")]
+pub async fn delete_layout(
+    layout_id: web::Path<uuid::Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    sqlx::query!(
+        r#"DELETE FROM email_layouts WHERE layout_id = $1"#,
+        sqlx::types::Uuid::from_bytes(layout_id.into_inner().into_bytes())
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to delete the email layout")
+    .map_err(e500)?;
+
+    FlashMessage::info("The layout has been deleted.").send();
+    Ok(see_other("/admin/layouts"))
+}
+
+// GET /admin/layouts/{layout_id}/preview
+#[get("/layouts/{layout_id}/preview")]
+pub async fn preview_layout(
+    layout_id: web::Path<uuid::Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match get_layout(&pool, layout_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        Some(layout) => Ok(preview_response(&layout)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+// POST /admin/layouts/preview
+// The layout being typed in, shown next to its form before it is saved
+#[post("/layouts/preview")]
+pub async fn preview_layout_form(form: web::Form<LayoutFormData>) -> HttpResponse {
+    match EmailLayout::try_from(form.into_inner()) {
+        Ok(layout) => preview_response(&layout),
+        Err(e) => HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(format!("<p>{}</p>", encode_minimal(&e))),
+    }
+}
+
+fn preview_response(layout: &EmailLayout) -> HttpResponse {
+    let html = layout.wrap_html(&markdown_to_html(SAMPLE_ISSUE), Some("#"));
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .insert_header(("Content-Security-Policy", "sandbox"))
+        .body(html)
+}
+
+fn flash_messages_html(flash_message: &IncomingFlashMessages) -> String {
+    let mut message_str = String::new();
+
+    for m in flash_message.iter() {
+        let mut class = "error";
+        if m.level() == Level::Info {
+            class = "info"
+        }
+
+        // Messages may quote the name typed in the form
+        message_str.push_str(
+            format!(
+                "<p class='{}'><i>{}</i></p>",
+                class,
+                encode_minimal(m.content())
+            )
+            .as_str(),
+        );
+    }
+
+    message_str
+}
+
+// The fields of a layout, with a button showing the layout in the
+// `preview` frame without saving it
+fn layout_form_html(action: &str, submit: &str, layout: Option<&EmailLayout>) -> String {
+    let text = |value: Option<&String>| encode_minimal(value.map(String::as_str).unwrap_or(""));
+    let name = encode_attribute(layout.map(|l| l.name.as_str()).unwrap_or(""));
+    let header = text(layout.map(|l| &l.header));
+    let footer = text(layout.map(|l| &l.footer));
+    let postal_address = text(layout.map(|l| &l.postal_address));
+    let brand_color = encode_attribute(layout.map(|l| l.brand_color.as_str()).unwrap_or("#1a5fb4"));
+    let background_color = encode_attribute(
+        layout
+            .map(|l| l.background_color.as_str())
+            .unwrap_or("#f4f4f4"),
+    );
+
+    format!(
+        r#"<form action="{action}" method="post">
+            <label>Name
+                <input type="text" name="name" placeholder="Default" value="{name}" required>
+            </label>
+            <br />
+            <label>Header<br />
+                <textarea name="header" rows="2" cols="50">{header}</textarea>
+            </label>
+            <br />
+            <label>Footer<br />
+                <textarea name="footer" rows="3" cols="50">{footer}</textarea>
+            </label>
+            <br />
+            <label>Postal address<br />
+                <textarea name="postal_address" rows="3" cols="50" required>{postal_address}</textarea>
+            </label>
+            <br />
+            <label>Brand color
+                <input type="color" name="brand_color" value="{brand_color}">
+            </label>
+            <label>Background color
+                <input type="color" name="background_color" value="{background_color}">
+            </label>
+            <br />
+            <button type="submit" formaction="/admin/layouts/preview" formtarget="preview">Preview</button>
+            <button type="submit">{submit}</button>
+        </form>
+        <iframe name="preview" title="Preview" width="700" height="500"></iframe>"#
+    )
+}
+
+fn get_layouts_page_html(message: String, rows: String) -> String {
+    let form = layout_form_html("/admin/layouts", "Create", None);
+
+    format!(
+        r#"
+        <html>
+            <head>
+                <title>Email layouts</title>
+                <style>
+                    .error {{
+                        color: red;
+                        font-weight: bold;
+                    }}
+                    .info {{
+                        color: green;
+                        font-weight: bold;
+                    }}
+                    td, th {{
+                        padding: 0 1em 0 0;
+                        text-align: left;
+                    }}
+                </style>
+            </head>
+            <body>
+                {message}
+                <table>
+                    <tr><th>Name</th><th></th><th></th></tr>
+                    {rows}
+                </table>
+                <p>Issues and transactional emails are wrapped in the default layout, and sent as written when there is none.</p>
+                <h2>New layout</h2>
+                {form}
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+        </html>
+    "#
+    )
+}
+
+fn get_edit_layout_page_html(message: String, layout: &EmailLayout) -> String {
+    let form = layout_form_html(
+        &format!("/admin/layouts/{}", layout.layout_id),
+        "Save",
+        Some(layout),
+    );
+    let id = layout.layout_id;
+
+    format!(
+        r#"
+        <html>
+            <head>
+                <title>Email layout</title>
+                <style>
+                    .error {{
+                        color: red;
+                        font-weight: bold;
+                    }}
+                    .info {{
+                        color: green;
+                        font-weight: bold;
+                    }}
+                </style>
+            </head>
+            <body>
+                {message}
+                {form}
+                <form action="/admin/layouts/{id}/delete" method="post">
+                    <button type="submit">Delete</button>
+                </form>
+                <p><a href="/admin/layouts">&lt;- Back</a></p>
+            </body>
+        </html>
+    "#
+    )
+}
@@ -0,0 +1,209 @@
+use actix_web::{get, http::header::ContentType, post, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages, Level};
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+
+use crate::domain::ListSlug;
+use crate::mailing_lists::MailingList;
+use crate::utils::{e500, see_other};
+
+struct ListSummary {
+    name: String,
+    slug: String,
+    n_confirmed: i64,
+    n_pending: i64,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ListFormData {
+    name: String,
+    slug: String,
+}
+
+// GET /admin/lists
+#[get("/lists")]
+pub async fn lists_page(
+    flash_message: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let message = flash_messages_html(&flash_message);
+
+    let lists = get_list_summaries(&pool).await.map_err(e500)?;
+    let mut rows = String::new();
+    for list in lists {
+        let slug = encode_minimal(&list.slug);
+        rows.push_str(&format!(
+            r#"<tr>
+                <td>{}</td>
+                <td><a href="/subscription?list={slug}">{slug}</a></td>
+                <td>{}</td>
+                <td>{}</td>
+            </tr>"#,
+            encode_minimal(&list.name),
+            list.n_confirmed,
+            list.n_pending,
+        ));
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(get_lists_page_html(message, rows)))
+}
+
+// POST /admin/lists
+#[tracing::instrument(name = "Create a list", skip(form, pool), fields(slug = %form.slug))]
+#[post("/lists")]
+pub async fn create_list(
+    form: web::Form<ListFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ListFormData { name, slug } = form.into_inner();
+    let name = name.trim();
+    if name.is_empty() {
+        FlashMessage::error("The list needs a name.").send();
+        return Ok(see_other("/admin/lists"));
+    }
+    let slug = match ListSlug::parse(slug) {
+        Ok(slug) => slug,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/lists"));
+        }
+    };
+
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO lists (list_id, slug, name, created_at)
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT (slug) DO NOTHING
+    "#,
+        sqlx::types::Uuid::new_v4(),
+        slug.as_ref(),
+        name
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the list")
+    .map_err(e500)?
+    .rows_affected();
+
+    if n_inserted == 0 {
+        FlashMessage::error(format!("{} is already the slug of a list.", slug.as_ref())).send();
+    } else {
+        FlashMessage::info(format!("The {} list has been created.", name)).send();
+    }
+    Ok(see_other("/admin/lists"))
+}
+
+// The options of a `list_id` select, for the issue forms
+pub(super) fn list_options_html(lists: &[MailingList], selected: uuid::Uuid) -> String {
+    let mut options = String::new();
+    for list in lists {
+        options.push_str(&format!(
+            r#"<option value="{}"{}>{}</option>"#,
+            list.list_id,
+            if list.list_id == selected {
+                " selected"
+            } else {
+                ""
+            },
+            encode_minimal(&list.name)
+        ));
+    }
+
+    options
+}
+
+fn flash_messages_html(flash_message: &IncomingFlashMessages) -> String {
+    let mut message_str = String::new();
+
+    for m in flash_message.iter() {
+        let mut class = "error";
+        if m.level() == Level::Info {
+            class = "info"
+        }
+
+        // Messages may quote the name typed in the form
+        message_str.push_str(
+            format!(
+                "<p class='{}'><i>{}</i></p>",
+                class,
+                encode_minimal(m.content())
+            )
+            .as_str(),
+        );
+    }
+
+    message_str
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_list_summaries(pool: &PgPool) -> Result<Vec<ListSummary>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        ListSummary,
+        r#"
+        SELECT
+            name,
+            slug,
+            count(*) FILTER (WHERE list_memberships.status = 'confirmed') as "n_confirmed!",
+            count(*) FILTER (
+                WHERE list_memberships.status = 'pending_confirmation'
+            ) as "n_pending!"
+        FROM lists
+        LEFT JOIN list_memberships ON list_memberships.list_id = lists.list_id
+        GROUP BY lists.list_id
+        ORDER BY lists.created_at, name
+    "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch the lists")?;
+
+    Ok(lists)
+}
+
+fn get_lists_page_html(message: String, rows: String) -> String {
+    format!(
+        r#"
+        <html>
+            <head>
+                <title>Lists</title>
+                <style>
+                    .error {{
+                        color: red;
+                        font-weight: bold;
+                    }}
+                    .info {{
+                        color: green;
+                        font-weight: bold;
+                    }}
+                    td, th {{
+                        padding: 0 1em 0 0;
+                        text-align: left;
+                    }}
+                </style>
+            </head>
+            <body>
+                {message}
+                <table>
+                    <tr><th>Name</th><th>Slug</th><th>Confirmed members</th><th>Pending members</th></tr>
+                    {rows}
+                </table>
+                <h2>New list</h2>
+                <form action="/admin/lists" method="post">
+                    <label>Name
+                        <input type="text" name="name" placeholder="Weekly digest" required>
+                    </label>
+                    <label>Slug
+                        <input type="text" name="slug" placeholder="weekly-digest" required>
+                    </label>
+                    <button type="submit">Create</button>
+                </form>
+                <p>Subscribers join a list with the <code>list</code> field of the subscribe form, set to its slug.</p>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+        </html>
+    "#
+    )
+}
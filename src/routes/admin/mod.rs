@@ -1,11 +1,32 @@
 mod dashboard;
+mod layouts;
+mod lists;
 mod logout;
 pub mod newsletters;
 pub mod password;
+mod segments;
+mod subscribers;
 
 pub use dashboard::*;
+pub use layouts::{
+    create_layout, delete_layout, edit_layout_page, layouts_page, preview_layout,
+    preview_layout_form, set_default_layout, update_layout,
+};
+pub use lists::{create_list, lists_page};
 pub use logout::*;
-pub use newsletters::issue_page;
-pub use newsletters::newsletter_issue;
+pub use newsletters::{cancel_scheduled_issue, reschedule_issue};
+pub use newsletters::{
+    create_draft, delete_draft, drafts_page, edit_draft_page, preview_draft, publish_draft,
+    send_test_email, update_draft,
+};
+pub use newsletters::{insert_newsletter_issue, newsletter_issue, IssueAudience, IssuePublication};
+pub use newsletters::{issue_page, issue_recipients};
+pub use newsletters::{issue_report, set_issue_archive_visibility};
 pub use password::change_password;
 pub use password::change_password_form;
+pub use segments::{create_segment, segments_page};
+pub use subscribers::{
+    confirm_subscriber_manually, delete_subscriber, export_subscribers, import_subscribers_upload,
+    subscriber_import_errors, subscriber_import_page, subscriber_page, subscribers_page,
+    tag_subscriber, unsubscribe_subscriber_manually,
+};
@@ -0,0 +1,352 @@
+use actix_web::{get, http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{IncomingFlashMessages, Level};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+
+use crate::domain::{IssueContent, MergeTemplate, MergeValues};
+use crate::email_html::prepare_issue_html;
+use crate::email_layouts::get_default_layout;
+use crate::mailing_lists::{get_lists, DEFAULT_LIST_ID};
+use crate::segments::{count_recipients, get_segments};
+use crate::utils::{app_base_url, e400, e500};
+
+use super::super::super::lists::list_options_html;
+use super::super::super::segments::segment_options_html;
+
+struct DraftSummary {
+    newsletter_issue_id: sqlx::types::Uuid,
+    title: String,
+    author: Option<String>,
+    updated_at: DateTime<Utc>,
+}
+
+pub(super) struct Draft {
+    pub(super) title: String,
+    pub(super) markdown: Option<String>,
+    pub(super) text: String,
+    pub(super) html: String,
+    pub(super) list_id: sqlx::types::Uuid,
+    pub(super) segment_id: Option<sqlx::types::Uuid>,
+}
+
+// GET /admin/newsletters/drafts
+#[get("/newsletters/drafts")]
+pub async fn drafts_page(
+    flash_message: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let message = flash_messages_html(&flash_message);
+
+    let lists = get_lists(&pool).await.map_err(e500)?;
+    let list_options = list_options_html(&lists, DEFAULT_LIST_ID);
+    let segments = get_segments(&pool).await.map_err(e500)?;
+    let segment_options = segment_options_html(&segments, None);
+
+    let drafts = get_drafts(&pool).await.map_err(e500)?;
+    let mut drafts_str = String::new();
+    for draft in drafts {
+        drafts_str.push_str(&format!(
+            r#"<li>
+                <a href="/admin/newsletters/drafts/{id}">{}</a>
+                by {}, last edited {}
+                (<a href="/admin/newsletters/drafts/{id}/preview" target="_blank">preview</a>)
+            </li>"#,
+            encode_minimal(&draft.title),
+            encode_minimal(draft.author.as_deref().unwrap_or("unknown")),
+            draft.updated_at.to_rfc2822(),
+            id = draft.newsletter_issue_id,
+        ));
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(get_drafts_page_html(
+            message,
+            list_options,
+            segment_options,
+            drafts_str,
+        )))
+}
+
+// GET /admin/newsletters/drafts/{newsletter_issue_id}
+#[get("/newsletters/drafts/{newsletter_issue_id}")]
+pub async fn edit_draft_page(
+    newsletter_issue_id: web::Path<uuid::Uuid>,
+    flash_message: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let message = flash_messages_html(&flash_message);
+
+    let draft = match get_draft(&pool, newsletter_issue_id).await.map_err(e500)? {
+        Some(draft) => draft,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let list_id = uuid::Uuid::from_bytes(*draft.list_id.as_bytes());
+    let segment_id = draft
+        .segment_id
+        .map(|id| uuid::Uuid::from_bytes(*id.as_bytes()));
+    let lists = get_lists(&pool).await.map_err(e500)?;
+    let list_options = list_options_html(&lists, list_id);
+    let segments = get_segments(&pool).await.map_err(e500)?;
+    let segment_options = segment_options_html(&segments, segment_id);
+    let n_recipients = count_recipients(&pool, list_id, segment_id)
+        .await
+        .map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(get_edit_draft_html(
+            message,
+            newsletter_issue_id,
+            draft,
+            list_options,
+            segment_options,
+            n_recipients,
+        )))
+}
+
+// GET /admin/newsletters/drafts/{newsletter_issue_id}/preview
+// The HTML content of the draft, as subscribers are going to see it
+#[get("/newsletters/drafts/{newsletter_issue_id}/preview")]
+pub async fn preview_draft(
+    newsletter_issue_id: web::Path<uuid::Uuid>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<String>,
+    app_port: web::Data<u16>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft = match get_draft(&pool, newsletter_issue_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        Some(draft) => draft,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    // Made safe the way publishing the draft does, the merge tags use
+    // their fallback as in the archive
+    let html =
+        prepare_issue_html(&draft.html, &app_base_url(&base_url, **app_port)).map_err(e400)?;
+    let html = MergeTemplate::parse_or_verbatim(&html).render_html(&MergeValues::default());
+    let html = match get_default_layout(&pool).await.map_err(e500)? {
+        Some(layout) => layout.wrap_html(&html, Some("#")),
+        None => html,
+    };
+
+    // The content is rendered on our own origin, the sandbox keeps any
+    // script it may contain away from the admin session
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .insert_header(("Content-Security-Policy", "sandbox"))
+        .body(html))
+}
+
+fn flash_messages_html(flash_message: &IncomingFlashMessages) -> String {
+    let mut message_str = String::new();
+
+    for m in flash_message.iter() {
+        let mut class = "error";
+        if m.level() == Level::Info {
+            class = "info"
+        }
+
+        // Messages may quote the addresses typed in the test email form
+        message_str.push_str(
+            format!(
+                "<p class='{}'><i>{}</i></p>",
+                class,
+                encode_minimal(m.content())
+            )
+            .as_str(),
+        );
+    }
+
+    message_str
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_drafts(pool: &PgPool) -> Result<Vec<DraftSummary>, anyhow::Error> {
+    let drafts = sqlx::query_as!(
+        DraftSummary,
+        r#"
+        SELECT newsletter_issue_id, title, users.username as "author?", updated_at
+        FROM newsletter_issue
+        LEFT JOIN users ON users.user_id = newsletter_issue.user_id
+        WHERE published_at IS NULL AND scheduled_at IS NULL
+        ORDER BY updated_at DESC
+    "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch the drafts")?;
+
+    Ok(drafts)
+}
+
+#[tracing::instrument(skip(pool))]
+pub(super) async fn get_draft(
+    pool: &PgPool,
+    newsletter_issue_id: uuid::Uuid,
+) -> Result<Option<Draft>, anyhow::Error> {
+    let draft = sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT title, markdown, text, html, list_id, segment_id
+        FROM newsletter_issue
+        WHERE
+            newsletter_issue_id = $1 AND
+            published_at IS NULL AND
+            scheduled_at IS NULL
+    "#,
+        sqlx::types::Uuid::from_bytes(newsletter_issue_id.into_bytes())
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch the draft")?;
+
+    Ok(draft)
+}
+
+const STYLE: &str = r#"
+    <style>
+        .error {
+            color: red;
+            font-weight: bold;
+        }
+        .info {
+            color: green;
+            font-weight: bold;
+        }
+    </style>
+"#;
+
+fn get_drafts_page_html(
+    message: String,
+    list_options: String,
+    segment_options: String,
+    drafts: String,
+) -> String {
+    format!(
+        r#"
+        <html>
+            <head>
+                <title>Drafts</title>
+                {STYLE}
+            </head>
+            <body>
+                {message}
+                <h2>New draft</h2>
+                <form action="/admin/newsletters/drafts" method="post">
+                    <label>Title
+                        <input type="text" placeholder="Title" name="title">
+                    </label>
+                    <label>Markdown
+                        <textarea placeholder="Markdown content" name="markdown"></textarea>
+                    </label>
+                    <label>Text
+                        <textarea placeholder="Text content, generated from the Markdown when empty" name="text"></textarea>
+                    </label>
+                    <label>HTML
+                        <textarea placeholder="Html content, generated from the Markdown when empty" name="html"></textarea>
+                    </label>
+                    <label>List
+                        <select name="list_id">{list_options}</select>
+                    </label>
+                    <label>Segment
+                        <select name="segment_id">{segment_options}</select>
+                    </label>
+                    <button type="submit">Save draft</button>
+                </form>
+                <h2>Drafts</h2>
+                <ul>
+                    {drafts}
+                </ul>
+                <p><a href="/admin/newsletters">&lt;- Back</a></p>
+            </body>
+        </html>
+    "#
+    )
+}
+
+fn get_edit_draft_html(
+    message: String,
+    newsletter_issue_id: uuid::Uuid,
+    draft: Draft,
+    list_options: String,
+    segment_options: String,
+    // Of the saved list and segment
+    n_recipients: i64,
+) -> String {
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let title = encode_attribute(&draft.title);
+    // The parts generated from the Markdown are left out, they follow its edits
+    let content = IssueContent {
+        markdown: draft.markdown,
+        text: draft.text,
+        html: draft.html,
+    };
+    let markdown = encode_minimal(content.markdown.as_deref().unwrap_or_default());
+    let text = encode_minimal(content.text_override());
+    let html = encode_minimal(content.html_override());
+
+    format!(
+        r#"
+        <html>
+            <head>
+                <title>Edit draft</title>
+                {STYLE}
+            </head>
+            <body>
+                {message}
+                <form action="/admin/newsletters/drafts/{newsletter_issue_id}" method="post">
+                    <label>Title
+                        <input type="text" name="title" value="{title}">
+                    </label>
+                    <label>Markdown
+                        <textarea name="markdown">{markdown}</textarea>
+                    </label>
+                    <label>Text
+                        <textarea placeholder="Generated from the Markdown when empty" name="text">{text}</textarea>
+                    </label>
+                    <label>HTML
+                        <textarea placeholder="Generated from the Markdown when empty" name="html">{html}</textarea>
+                    </label>
+                    <label>List
+                        <select name="list_id">{list_options}</select>
+                    </label>
+                    <label>Segment
+                        <select name="segment_id">{segment_options}</select>
+                    </label>
+                    <button type="submit">Save</button>
+                </form>
+                <p>
+                    <a href="/admin/newsletters/drafts/{newsletter_issue_id}/preview" target="_blank">
+                        Preview
+                    </a>
+                </p>
+                <form action="/admin/newsletters/drafts/{newsletter_issue_id}/test" method="post">
+                    <label>Send a test email to
+                        <input type="text" placeholder="Comma separated addresses" name="recipients">
+                    </label>
+                    <button type="submit">Send test</button>
+                </form>
+                <p>Publishing now would send this draft to {n_recipients} subscriber(s).</p>
+                <form action="/admin/newsletters/drafts/{newsletter_issue_id}/publish" method="post">
+                    <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
+                    <label>
+                        <input type="checkbox" name="track_engagement">
+                        Track opens and clicks
+                    </label>
+                    <button type="submit">Publish</button>
+                </form>
+                <form action="/admin/newsletters/drafts/{newsletter_issue_id}/delete" method="post">
+                    <button type="submit">Delete</button>
+                </form>
+                <p><a href="/admin/newsletters/drafts">&lt;- Back</a></p>
+            </body>
+        </html>
+    "#
+    )
+}
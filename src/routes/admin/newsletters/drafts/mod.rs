@@ -0,0 +1,7 @@
+mod get;
+mod post;
+mod test_email;
+
+pub use get::*;
+pub use post::*;
+pub use test_email::*;
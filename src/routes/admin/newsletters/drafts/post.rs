@@ -0,0 +1,321 @@
+use actix_web::{post, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::authentication::UserID;
+use crate::domain::IssueContent;
+use crate::email_html::prepare_issue_html;
+use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::issue_delivery_workers::enqueue_delivery_task;
+use crate::mailing_lists::DEFAULT_LIST_ID;
+use crate::utils::{app_base_url, e400, e500, see_other};
+
+use super::super::post::{
+    assign_slug, check_list, check_merge_tags, clipping_warning, parse_segment_id, success_message,
+};
+
+#[derive(serde::Deserialize)]
+pub struct DraftFormData {
+    title: String,
+    // Either part is generated from the Markdown when left empty
+    #[serde(default)]
+    markdown: String,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    html: String,
+    // The default list when missing, left as is by an update
+    list_id: Option<uuid::Uuid>,
+    // Empty for the whole list, left as is by an update when missing
+    segment_id: Option<String>,
+}
+
+impl DraftFormData {
+    fn content(&self) -> IssueContent {
+        IssueContent::new(&self.markdown, &self.text, &self.html)
+    }
+
+    async fn list_id(&self, pool: &PgPool) -> Result<Option<uuid::Uuid>, actix_web::Error> {
+        if let Some(list_id) = self.list_id {
+            check_list(pool, list_id).await?;
+        }
+        Ok(self.list_id)
+    }
+
+    async fn segment_id(
+        &self,
+        pool: &PgPool,
+    ) -> Result<Option<Option<uuid::Uuid>>, actix_web::Error> {
+        match &self.segment_id {
+            Some(segment_id) => Ok(Some(parse_segment_id(pool, segment_id).await?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct PublishFormData {
+    idempotency_key: String,
+    // Checkbox, only sent when checked
+    track_engagement: Option<String>,
+}
+
+// POST /admin/newsletters/drafts
+#[tracing::instrument(
+    name = "Save a new draft",
+    skip(form, pool, user_id),
+    fields(user_id=tracing::field::Empty)
+)]
+#[post("/newsletters/drafts")]
+pub async fn create_draft(
+    form: web::Form<DraftFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserID>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    tracing::Span::current().record("user_id", tracing::field::display(*user_id));
+    let newsletter_issue_id = uuid::Uuid::new_v4();
+    let list_id = form.list_id(&pool).await?.unwrap_or(DEFAULT_LIST_ID);
+    let segment_id = form.segment_id(&pool).await?.flatten();
+    let content = form.content();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue
+            (
+                newsletter_issue_id, title, markdown, text, html, list_id, segment_id,
+                user_id, created_at, updated_at
+            )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, now(), now())
+    "#,
+        sqlx::types::Uuid::from_bytes(newsletter_issue_id.into_bytes()),
+        form.title,
+        content.markdown,
+        content.text,
+        content.html,
+        sqlx::types::Uuid::from_bytes(list_id.into_bytes()),
+        segment_id.map(|id| sqlx::types::Uuid::from_bytes(id.into_bytes())),
+        sqlx::types::Uuid::from_bytes(user_id.into_bytes())
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the draft")
+    .map_err(e500)?;
+
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&format!(
+        "/admin/newsletters/drafts/{}",
+        newsletter_issue_id
+    )))
+}
+
+// POST /admin/newsletters/drafts/{newsletter_issue_id}
+#[tracing::instrument(name = "Update a draft", skip(form, pool))]
+#[post("/newsletters/drafts/{newsletter_issue_id}")]
+pub async fn update_draft(
+    newsletter_issue_id: web::Path<uuid::Uuid>,
+    form: web::Form<DraftFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let list_id = form.list_id(&pool).await?;
+    let segment_id = form.segment_id(&pool).await?;
+    let content = form.content();
+
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issue
+        SET
+            title = $2,
+            markdown = $3,
+            text = $4,
+            html = $5,
+            list_id = COALESCE($6, list_id),
+            segment_id = CASE WHEN $7 THEN $8 ELSE segment_id END,
+            updated_at = now()
+        WHERE
+            newsletter_issue_id = $1 AND
+            published_at IS NULL AND
+            scheduled_at IS NULL
+    "#,
+        sqlx::types::Uuid::from_bytes(newsletter_issue_id.into_bytes()),
+        form.title,
+        content.markdown,
+        content.text,
+        content.html,
+        list_id.map(|id| sqlx::types::Uuid::from_bytes(id.into_bytes())),
+        segment_id.is_some(),
+        segment_id
+            .flatten()
+            .map(|id| sqlx::types::Uuid::from_bytes(id.into_bytes()))
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update the draft")
+    .map_err(e500)?
+    .rows_affected();
+
+    if n_updated == 0 {
+        FlashMessage::error("The draft does not exist anymore.").send();
+        return Ok(see_other("/admin/newsletters/drafts"));
+    }
+
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&format!(
+        "/admin/newsletters/drafts/{}",
+        newsletter_issue_id
+    )))
+}
+
+// POST /admin/newsletters/drafts/{newsletter_issue_id}/delete
+#[tracing::instrument(name = "Delete a draft", skip(pool))]
+#[post("/newsletters/drafts/{newsletter_issue_id}/delete")]
+pub async fn delete_draft(
+    newsletter_issue_id: web::Path<uuid::Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let n_deleted = sqlx::query!(
+        r#"
+        DELETE FROM newsletter_issue
+        WHERE
+            newsletter_issue_id = $1 AND
+            published_at IS NULL AND
+            scheduled_at IS NULL
+    "#,
+        sqlx::types::Uuid::from_bytes(newsletter_issue_id.into_inner().into_bytes())
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to delete the draft")
+    .map_err(e500)?
+    .rows_affected();
+
+    if n_deleted == 0 {
+        FlashMessage::error("The draft does not exist anymore.").send();
+    } else {
+        FlashMessage::info("The draft has been deleted.").send();
+    }
+    Ok(see_other("/admin/newsletters/drafts"))
+}
+
+// POST /admin/newsletters/drafts/{newsletter_issue_id}/publish
+// Same idempotency guarantees as POST /admin/newsletters
+#[tracing::instrument(
+    name = "Publish a draft",
+    skip(form, pool, user_id),
+    fields(user_id=tracing::field::Empty)
+)]
+#[post("/newsletters/drafts/{newsletter_issue_id}/publish")]
+pub async fn publish_draft(
+    newsletter_issue_id: web::Path<uuid::Uuid>,
+    form: web::Form<PublishFormData>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<String>,
+    app_port: web::Data<u16>,
+    user_id: web::ReqData<UserID>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    tracing::Span::current().record("user_id", tracing::field::display(*user_id));
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let PublishFormData {
+        idempotency_key,
+        track_engagement,
+    } = form.into_inner();
+    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+
+    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
+        .await
+        .map_err(e500)?
+    {
+        NextAction::ReturnSaveResponse(resp) => {
+            success_message(None).send();
+            return Ok(resp);
+        }
+        NextAction::StartProcessing(t) => t,
+    };
+
+    let draft = sqlx::query!(
+        r#"
+        UPDATE newsletter_issue
+        SET
+            published_at = now(),
+            track_engagement = $2,
+            updated_at = now()
+        WHERE
+            newsletter_issue_id = $1 AND
+            published_at IS NULL AND
+            scheduled_at IS NULL
+        RETURNING title, markdown, text, html
+    "#,
+        sqlx::types::Uuid::from_bytes(newsletter_issue_id.into_bytes()),
+        track_engagement.is_some()
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to publish the draft")
+    .map_err(e500)?;
+
+    // Dropping the transaction releases the idempotency key as well
+    let draft = match draft {
+        Some(draft) => draft,
+        None => {
+            FlashMessage::error("The draft does not exist anymore.").send();
+            return Ok(see_other("/admin/newsletters/drafts"));
+        }
+    };
+    let draft_page = format!("/admin/newsletters/drafts/{}", newsletter_issue_id);
+    // Drafts are saved with missing parts, issues are not sent without them
+    if let Err(e) = IssueContent::parse(
+        draft.markdown.as_deref().unwrap_or_default(),
+        &draft.text,
+        &draft.html,
+    ) {
+        FlashMessage::error(e).send();
+        return Ok(see_other(&draft_page));
+    }
+    if let Err(e) = check_merge_tags(&draft.text, &draft.html) {
+        FlashMessage::error(e).send();
+        return Ok(see_other(&draft_page));
+    }
+    let html = match prepare_issue_html(&draft.html, &app_base_url(&base_url, **app_port)) {
+        Ok(html) => html,
+        Err(e) => {
+            FlashMessage::error(e.to_string()).send();
+            return Ok(see_other(&draft_page));
+        }
+    };
+
+    // The draft keeps its HTML as written until it is published
+    sqlx::query!(
+        "UPDATE newsletter_issue SET html = $2 WHERE newsletter_issue_id = $1",
+        sqlx::types::Uuid::from_bytes(newsletter_issue_id.into_bytes()),
+        html
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store the HTML of the issue")
+    .map_err(e500)?;
+    let title = draft.title;
+    assign_slug(&mut transaction, newsletter_issue_id, &title)
+        .await
+        .context("Failed to assign a slug to the newsletter issue")
+        .map_err(e500)?;
+
+    enqueue_delivery_task(&mut transaction, newsletter_issue_id)
+        .await
+        .context("failed to enqueue delivery details")
+        .map_err(e500)?;
+
+    success_message(None).send();
+    clipping_warning(&html);
+    let response = save_response(
+        transaction,
+        &idempotency_key,
+        *user_id,
+        see_other("/admin/newsletters"),
+    )
+    .await
+    .map_err(e500)?;
+    Ok(response)
+}
@@ -0,0 +1,184 @@
+use actix_web::{post, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+use crate::domain::{MergeTemplate, MergeValues, SubscriberEmail};
+use crate::email_client::EmailTransport;
+use crate::email_html::prepare_issue_html;
+use crate::email_layouts::get_default_layout;
+use crate::startup::TestSubjectPrefix;
+use crate::utils::{app_base_url, e500, see_other};
+
+use super::get::get_draft;
+
+#[derive(serde::Deserialize)]
+pub struct TestEmailFormData {
+    // Comma or whitespace separated
+    recipients: String,
+}
+
+// POST /admin/newsletters/drafts/{newsletter_issue_id}/test
+// Sends the draft to the given addresses only, nothing goes through
+// `issue_delivery_queue` and the draft stays a draft
+#[tracing::instrument(
+    name = "Send a test email of a draft",
+    skip(form, pool, email_client, subject_prefix, base_url)
+)]
+#[post("/newsletters/drafts/{newsletter_issue_id}/test")]
+pub async fn send_test_email(
+    newsletter_issue_id: web::Path<uuid::Uuid>,
+    form: web::Form<TestEmailFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailTransport>,
+    subject_prefix: web::Data<TestSubjectPrefix>,
+    base_url: web::Data<String>,
+    app_port: web::Data<u16>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let draft_page = format!("/admin/newsletters/drafts/{}", newsletter_issue_id);
+
+    let recipients = match parse_recipients(&form.recipients) {
+        Ok(recipients) => recipients,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&draft_page));
+        }
+    };
+
+    let draft = match get_draft(&pool, newsletter_issue_id).await.map_err(e500)? {
+        Some(draft) => draft,
+        None => {
+            FlashMessage::error("The draft does not exist anymore.").send();
+            return Ok(see_other("/admin/newsletters/drafts"));
+        }
+    };
+
+    // Made safe the way publishing the draft does, so the test email is
+    // what subscribers are going to get
+    let html = match prepare_issue_html(&draft.html, &app_base_url(&base_url, **app_port)) {
+        Ok(html) => html,
+        Err(e) => {
+            FlashMessage::error(format!("{} No test email has been sent.", e)).send();
+            return Ok(see_other(&draft_page));
+        }
+    };
+    let (html, text) = match (
+        MergeTemplate::parse(&html),
+        MergeTemplate::parse(&draft.text),
+    ) {
+        (Ok(html), Ok(text)) => (html, text),
+        (Err(e), _) | (_, Err(e)) => {
+            FlashMessage::error(format!("{} No test email has been sent.", e)).send();
+            return Ok(see_other(&draft_page));
+        }
+    };
+
+    let layout = get_default_layout(&pool).await.map_err(e500)?;
+    let subject = format!("{} {}", subject_prefix.0, draft.title);
+    let mut failed = Vec::new();
+    for recipient in &recipients {
+        // Test recipients are not subscribers, their other tags use their fallback
+        let values = MergeValues {
+            email: Some(recipient.as_ref()),
+            ..Default::default()
+        };
+        let mut html = html.render_html(&values);
+        let mut text = text.render_text(&values);
+        // The unsubscribe link of the layout leads nowhere, they have
+        // nothing to unsubscribe from
+        if let Some(layout) = &layout {
+            html = layout.wrap_html(&html, Some("#"));
+            text = layout.wrap_text(&text, Some("#"));
+        }
+        if let Err(e) = email_client
+            .send_email(recipient, &subject, &html, &text, &[])
+            .await
+        {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send a test email to {}",
+                recipient
+            );
+            failed.push(recipient.as_ref());
+        }
+    }
+
+    if failed.is_empty() {
+        FlashMessage::info(format!(
+            "The test email has been sent to {}.",
+            join(&recipients)
+        ))
+        .send();
+    } else {
+        FlashMessage::error(format!(
+            "Failed to send the test email to {}.",
+            failed.join(", ")
+        ))
+        .send();
+    }
+    Ok(see_other(&draft_page))
+}
+
+// Every address has to be valid, a typo should not go unnoticed
+// because the other recipients got their email
+fn parse_recipients(recipients: &str) -> Result<Vec<SubscriberEmail>, String> {
+    let mut parsed = Vec::new();
+    let mut invalid = Vec::new();
+    for recipient in recipients
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|r| !r.is_empty())
+    {
+        match SubscriberEmail::parse(recipient.to_string()) {
+            Ok(email) => parsed.push(email),
+            Err(_) => invalid.push(recipient),
+        }
+    }
+
+    if !invalid.is_empty() {
+        return Err(format!(
+            "Invalid email address: {}. No test email has been sent.",
+            invalid.join(", ")
+        ));
+    }
+    if parsed.is_empty() {
+        return Err("Please enter at least one email address.".into());
+    }
+    Ok(parsed)
+}
+
+fn join(recipients: &[SubscriberEmail]) -> String {
+    recipients
+        .iter()
+        .map(|r| r.as_ref())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_recipients;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn recipients_can_be_separated_by_commas_and_new_lines() {
+        let recipients = assert_ok!(parse_recipients(
+            "ursula@domain.com, le_guin@domain.com\r\nbob@x.io"
+        ));
+        let recipients: Vec<_> = recipients.iter().map(|r| r.as_ref()).collect();
+        assert_eq!(
+            recipients,
+            vec!["ursula@domain.com", "le_guin@domain.com", "bob@x.io"]
+        );
+    }
+
+    #[test]
+    fn a_single_invalid_address_rejects_them_all() {
+        assert_err!(parse_recipients("ursula@domain.com, not-an-email"));
+    }
+
+    #[test]
+    fn no_address_is_rejected() {
+        assert_err!(parse_recipients(" ,\n "));
+    }
+}
@@ -1,27 +1,166 @@
-use actix_web::{get, http::header::ContentType, HttpResponse};
-use actix_web_flash_messages::{IncomingFlashMessages, Level};
+use actix_web::{get, http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages, Level};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::mailing_lists::{get_lists, DEFAULT_LIST_ID};
+use crate::segments::{count_recipients, get_segments};
+use crate::utils::{e500, see_other};
+
+use super::super::lists::list_options_html;
+use super::super::segments::segment_options_html;
+use super::post::IssueAudience;
+
+struct PublishedIssue {
+    newsletter_issue_id: sqlx::types::Uuid,
+    title: String,
+    published_at: DateTime<Utc>,
+}
+
+struct ScheduledIssue {
+    newsletter_issue_id: sqlx::types::Uuid,
+    title: String,
+    scheduled_at: DateTime<Utc>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RecipientsQuery {
+    list_id: Option<uuid::Uuid>,
+    #[serde(default)]
+    segment_id: String,
+}
 
 #[get("/newsletters")]
 pub async fn issue_page(
     flash_message: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let mut message_str = String::new();
 
     for m in flash_message.iter() {
-        let mut class = "error";
-        if m.level() == Level::Info {
-            class = "info"
-        }
+        let class = match m.level() {
+            Level::Info => "info",
+            Level::Warning => "warning",
+            _ => "error",
+        };
 
         message_str.push_str(format!("<p class='{}'><i>{}</i></p>", class, m.content()).as_str());
     }
 
+    let issues = get_published_issues(&pool).await.map_err(e500)?;
+    let mut issues_str = String::new();
+    for issue in issues {
+        issues_str.push_str(&format!(
+            "<li><a href=\"/admin/newsletters/{}\">{}</a> ({})</li>",
+            issue.newsletter_issue_id,
+            htmlescape::encode_minimal(&issue.title),
+            issue.published_at.to_rfc2822()
+        ));
+    }
+
+    let scheduled = get_scheduled_issues(&pool).await.map_err(e500)?;
+    let mut scheduled_str = String::new();
+    for issue in scheduled {
+        scheduled_str.push_str(&format!(
+            r#"<li>
+                {} (scheduled for {})
+                <form action="/admin/newsletters/{id}/schedule" method="post">
+                    <input type="datetime-local" name="scheduled_at" required>
+                    <input type="text" name="timezone" value="UTC">
+                    <button type="submit">Reschedule</button>
+                </form>
+                <form action="/admin/newsletters/{id}/cancel" method="post">
+                    <button type="submit">Cancel</button>
+                </form>
+            </li>"#,
+            htmlescape::encode_minimal(&issue.title),
+            issue.scheduled_at.to_rfc2822(),
+            id = issue.newsletter_issue_id,
+        ));
+    }
+
+    let lists = get_lists(&pool).await.map_err(e500)?;
+    let list_options = list_options_html(&lists, DEFAULT_LIST_ID);
+    let segments = get_segments(&pool).await.map_err(e500)?;
+    let segment_options = segment_options_html(&segments, None);
+
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(get_issue_page_html(message_str)))
+        .body(get_issue_page_html(
+            message_str,
+            list_options,
+            segment_options,
+            issues_str,
+            scheduled_str,
+        )))
+}
+
+// GET /admin/newsletters/recipients?list_id=...&segment_id=...
+// How many subscribers an issue sent to the list and segment would reach
+#[tracing::instrument(name = "Count the recipients of an issue", skip_all)]
+#[get("/newsletters/recipients")]
+pub async fn issue_recipients(
+    query: web::Query<RecipientsQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let audience = IssueAudience::parse(&pool, query.list_id, &query.segment_id).await?;
+    let n_recipients = count_recipients(&pool, audience.list_id, audience.segment_id)
+        .await
+        .map_err(e500)?;
+
+    FlashMessage::info(format!(
+        "An issue sent now would reach {} subscriber(s).",
+        n_recipients
+    ))
+    .send();
+    Ok(see_other("/admin/newsletters"))
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_published_issues(pool: &PgPool) -> Result<Vec<PublishedIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        PublishedIssue,
+        r#"
+        SELECT newsletter_issue_id, title, published_at as "published_at!"
+        FROM newsletter_issue
+        WHERE published_at IS NOT NULL
+        ORDER BY published_at DESC
+        LIMIT 50
+    "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch published newsletter issues")?;
+
+    Ok(issues)
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_scheduled_issues(pool: &PgPool) -> Result<Vec<ScheduledIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        ScheduledIssue,
+        r#"
+        SELECT newsletter_issue_id, title, scheduled_at as "scheduled_at!"
+        FROM newsletter_issue
+        WHERE published_at IS NULL AND scheduled_at IS NOT NULL
+        ORDER BY scheduled_at
+    "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch scheduled newsletter issues")?;
+
+    Ok(issues)
 }
 
-fn get_issue_page_html(message: String) -> String {
+fn get_issue_page_html(
+    message: String,
+    list_options: String,
+    segment_options: String,
+    issues: String,
+    scheduled: String,
+) -> String {
     let idempotency_key = uuid::Uuid::new_v4().to_string();
 
     format!(
@@ -38,6 +177,10 @@ fn get_issue_page_html(message: String) -> String {
                         color: green;
                         font-weight: bold;
                     }}
+                    .warning {{
+                        color: darkorange;
+                        font-weight: bold;
+                    }}
                 </style>
             </head>
             <body>
@@ -51,10 +194,17 @@ fn get_issue_page_html(message: String) -> String {
                         >
                     </label>
 
+                    <label>Markdown
+                        <textarea
+                            placeholder="Markdown content"
+                            name="markdown"
+                        ></textarea>
+                    </label>
+
                     <label>Text
                         <input
                             type="text"
-                            placeholder="Text content"
+                            placeholder="Text content, generated from the Markdown when empty"
                             name="text"
                         >
                     </label>
@@ -62,11 +212,45 @@ fn get_issue_page_html(message: String) -> String {
                     <label>HTML
                         <input
                             type="text"
-                            placeholder="Html content"
+                            placeholder="Html content, generated from the Markdown when empty"
                             name="html"
                         >
                     </label>
 
+                    <label>List
+                        <select name="list_id">{list_options}</select>
+                    </label>
+
+                    <label>Segment
+                        <select name="segment_id">{segment_options}</select>
+                    </label>
+
+                    <label>Send at (leave empty to send now)
+                        <input
+                            type="datetime-local"
+                            name="scheduled_at"
+                        >
+                    </label>
+
+                    <label>Timezone
+                        <input
+                            type="text"
+                            placeholder="Europe/Paris"
+                            name="timezone"
+                            value="UTC"
+                        >
+                    </label>
+
+                    <label>
+                        <input type="checkbox" name="show_in_archive">
+                        Show in the public archive
+                    </label>
+
+                    <label>
+                        <input type="checkbox" name="track_engagement">
+                        Track opens and clicks
+                    </label>
+
                     <input 
                         hidden
                         type="text"
@@ -76,6 +260,23 @@ fn get_issue_page_html(message: String) -> String {
 
                     <button type="submit">Send</button>
                 </form>
+                <form action="/admin/newsletters/recipients" method="get">
+                    <label>Recipients of
+                        <select name="list_id">{list_options}</select>
+                        <select name="segment_id">{segment_options}</select>
+                    </label>
+                    <button type="submit">Count</button>
+                </form>
+                <p><a href="/admin/segments">Segments</a></p>
+                <p><a href="/admin/newsletters/drafts">Drafts</a></p>
+                <h2>Scheduled issues</h2>
+                <ul>
+                    {scheduled}
+                </ul>
+                <h2>Published issues</h2>
+                <ul>
+                    {issues}
+                </ul>
             </body>
         </html>
     "#
@@ -1,5 +1,11 @@
+mod drafts;
 mod get;
 mod post;
+mod report;
+mod schedule;
 
-pub use get::issue_page;
-pub use post::newsletter_issue;
+pub use drafts::*;
+pub use get::{issue_page, issue_recipients};
+pub use post::{insert_newsletter_issue, newsletter_issue, IssueAudience, IssuePublication};
+pub use report::{issue_report, set_issue_archive_visibility};
+pub use schedule::{cancel_scheduled_issue, reschedule_issue};
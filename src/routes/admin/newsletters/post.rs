@@ -1,17 +1,44 @@
 use crate::authentication::UserID;
+use crate::domain::{IssueContent, IssueSlug, MergeTemplate};
+use crate::email_html::{is_clipped_by_gmail, prepare_issue_html, GMAIL_CLIPPING_SIZE};
 use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
-use crate::utils::{e400, e500, see_other};
+use crate::issue_delivery_workers::enqueue_delivery_task;
+use crate::mailing_lists::{list_exists, DEFAULT_LIST_ID};
+use crate::segments::segment_exists;
+use crate::utils::{app_base_url, e400, e500, see_other};
 use actix_web::{post, web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
+use chrono::{DateTime, Utc};
 use sqlx::{PgPool, Postgres, Transaction};
 
+use super::schedule::parse_scheduled_at;
+
 #[derive(serde::Deserialize)]
 pub struct BodyData {
     title: String,
+    // Either part is generated from the Markdown when left empty
+    #[serde(default)]
+    markdown: String,
+    #[serde(default)]
     text: String,
+    #[serde(default)]
     html: String,
     idempotency_key: String,
+    // Left empty to publish straight away
+    #[serde(default)]
+    scheduled_at: String,
+    #[serde(default)]
+    timezone: String,
+    // Checkbox, only sent when checked
+    show_in_archive: Option<String>,
+    // Checkbox, tracking opens and clicks when `issue_delivery.track_engagement` is enabled
+    track_engagement: Option<String>,
+    // The default list when missing
+    list_id: Option<uuid::Uuid>,
+    // Empty to send the issue to the whole list
+    #[serde(default)]
+    segment_id: String,
 }
 
 #[tracing::instrument(
@@ -23,6 +50,8 @@ pub struct BodyData {
 pub async fn newsletter_issue(
     body: web::Form<BodyData>,
     pool: web::Data<PgPool>,
+    base_url: web::Data<String>,
+    app_port: web::Data<u16>,
     user_id: web::ReqData<UserID>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
@@ -30,34 +59,68 @@ pub async fn newsletter_issue(
 
     let BodyData {
         title,
+        markdown,
         text,
         html,
         idempotency_key,
+        scheduled_at,
+        timezone,
+        show_in_archive,
+        track_engagement,
+        list_id,
+        segment_id,
     } = body.0;
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let scheduled_at = if scheduled_at.trim().is_empty() {
+        None
+    } else {
+        Some(parse_scheduled_at(&scheduled_at, &timezone).map_err(e400)?)
+    };
+    let audience = IssueAudience::parse(&pool, list_id, &segment_id).await?;
+    let mut content = IssueContent::parse(&markdown, &text, &html).map_err(e400)?;
+    check_merge_tags(&content.text, &content.html).map_err(e400)?;
+    content.html =
+        prepare_issue_html(&content.html, &app_base_url(&base_url, **app_port)).map_err(e400)?;
 
     let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
         .await
         .map_err(e500)?
     {
         NextAction::ReturnSaveResponse(resp) => {
-            success_message().send();
+            success_message(scheduled_at).send();
             return Ok(resp);
         }
         NextAction::StartProcessing(t) => t,
     };
 
-    let newsletter_issue_id = insert_newsletter_issue(&mut transaction, &title, &text, &html)
-        .await
-        .context("Failed to store newsletter issue details")
-        .map_err(e500)?;
+    let publication = match scheduled_at {
+        Some(scheduled_at) => IssuePublication::Scheduled(scheduled_at),
+        None => IssuePublication::Now,
+    };
+    let newsletter_issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &title,
+        &content,
+        publication,
+        show_in_archive.is_some(),
+        track_engagement.is_some(),
+        audience,
+        Some(*user_id),
+    )
+    .await
+    .context("Failed to store newsletter issue details")
+    .map_err(e500)?;
 
-    enqueue_delivery_task(&mut transaction, newsletter_issue_id)
-        .await
-        .context("failed to enqueue delivery details")
-        .map_err(e500)?;
+    // Scheduled issues are enqueued by the scheduler once their time has come
+    if scheduled_at.is_none() {
+        enqueue_delivery_task(&mut transaction, newsletter_issue_id)
+            .await
+            .context("failed to enqueue delivery details")
+            .map_err(e500)?;
+    }
 
-    success_message().send();
+    success_message(scheduled_at).send();
+    clipping_warning(&content.html);
     let response = save_response(
         transaction,
         &idempotency_key,
@@ -69,54 +132,177 @@ pub async fn newsletter_issue(
     Ok(response)
 }
 
-fn success_message() -> FlashMessage {
-    FlashMessage::info("The newsletter issue has been published!")
+pub(super) fn success_message(scheduled_at: Option<DateTime<Utc>>) -> FlashMessage {
+    match scheduled_at {
+        Some(scheduled_at) => FlashMessage::info(format!(
+            "The newsletter issue has been scheduled for {}!",
+            scheduled_at.to_rfc2822()
+        )),
+        None => FlashMessage::info("The newsletter issue has been published!"),
+    }
 }
 
-#[tracing::instrument(skip_all)]
-async fn enqueue_delivery_task(
-    transaction: &mut Transaction<'_, Postgres>,
-    newsletter_issue_id: uuid::Uuid,
-) -> Result<(), sqlx::Error> {
-    let sqlx_uuid = sqlx::types::Uuid::from_bytes(newsletter_issue_id.into_bytes());
+pub(super) fn clipping_warning(html: &str) {
+    if is_clipped_by_gmail(html) {
+        FlashMessage::warning(format!(
+            "The HTML part weighs {} KB, Gmail only shows the first {} KB of a message.",
+            html.len() / 1024,
+            GMAIL_CLIPPING_SIZE / 1024
+        ))
+        .send();
+    }
+}
 
-    sqlx::query!(
-        r#"
-        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
-        SELECT $1, email
-        FROM subscriptions
-        WHERE status = 'confirmed'
-    "#,
-        sqlx_uuid
-    )
-    .execute(transaction)
-    .await?;
+// The merge tags of an issue are rendered for each recipient by the
+// delivery workers, a typo must be caught before anything is sent
+pub(super) fn check_merge_tags(text: &str, html: &str) -> Result<(), String> {
+    MergeTemplate::parse(text).map_err(|e| format!("Text content: {}", e))?;
+    MergeTemplate::parse(html).map_err(|e| format!("HTML content: {}", e))?;
+    Ok(())
+}
+
+// Who an issue is delivered to, see `enqueue_delivery_task`
+pub struct IssueAudience {
+    pub list_id: uuid::Uuid,
+    // The whole list when `None`
+    pub segment_id: Option<uuid::Uuid>,
+}
+
+impl Default for IssueAudience {
+    fn default() -> Self {
+        Self {
+            list_id: DEFAULT_LIST_ID,
+            segment_id: None,
+        }
+    }
+}
+
+impl IssueAudience {
+    // The `list_id` and `segment_id` fields of the issue forms
+    pub(super) async fn parse(
+        pool: &PgPool,
+        list_id: Option<uuid::Uuid>,
+        segment_id: &str,
+    ) -> Result<Self, actix_web::Error> {
+        let list_id = list_id.unwrap_or(DEFAULT_LIST_ID);
+        check_list(pool, list_id).await?;
+        let segment_id = parse_segment_id(pool, segment_id).await?;
+
+        Ok(Self {
+            list_id,
+            segment_id,
+        })
+    }
+}
 
+pub(super) async fn check_list(pool: &PgPool, list_id: uuid::Uuid) -> Result<(), actix_web::Error> {
+    if !list_exists(pool, list_id).await.map_err(e500)? {
+        return Err(e400(format!("{} is not a list", list_id)));
+    }
     Ok(())
 }
 
+// `None` for an empty field
+pub(super) async fn parse_segment_id(
+    pool: &PgPool,
+    segment_id: &str,
+) -> Result<Option<uuid::Uuid>, actix_web::Error> {
+    if segment_id.trim().is_empty() {
+        return Ok(None);
+    }
+    let segment_id: uuid::Uuid = segment_id.trim().parse().map_err(e400)?;
+    if !segment_exists(pool, segment_id).await.map_err(e500)? {
+        return Err(e400(format!("{} is not a segment", segment_id)));
+    }
+    Ok(Some(segment_id))
+}
+
+pub enum IssuePublication {
+    Draft,
+    Now,
+    // Published by the scheduler once its time has come
+    Scheduled(DateTime<Utc>),
+}
+
+// Deliveries of an issue published now still have to be enqueued by the caller
+#[allow(clippy::too_many_arguments)]
 #[tracing::instrument(skip_all)]
-async fn insert_newsletter_issue(
+pub async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
     title: &str,
-    text: &str,
-    html: &str,
+    content: &IssueContent,
+    publication: IssuePublication,
+    show_in_archive: bool,
+    track_engagement: bool,
+    audience: IssueAudience,
+    // `None` when the issue is not created by an admin
+    user_id: Option<uuid::Uuid>,
 ) -> Result<uuid::Uuid, sqlx::Error> {
     let newsletter_issue_id = uuid::Uuid::new_v4();
     let sqlx_issue_id = sqlx::types::Uuid::from_bytes(newsletter_issue_id.into_bytes());
+    let (publish_now, scheduled_at) = match publication {
+        IssuePublication::Draft => (false, None),
+        IssuePublication::Now => (true, None),
+        IssuePublication::Scheduled(scheduled_at) => (false, Some(scheduled_at)),
+    };
 
     sqlx::query!(
         r#"
-        INSERT INTO newsletter_issue (newsletter_issue_id, title, text, html, published_at)
-        VALUES ($1, $2, $3, $4, now());
+        INSERT INTO newsletter_issue
+            (
+                newsletter_issue_id, title, markdown, text, html, published_at,
+                scheduled_at, show_in_archive, track_engagement, list_id, segment_id, user_id
+            )
+        VALUES ($1, $2, $3, $4, $5, CASE WHEN $6 THEN now() END, $7, $8, $9, $10, $11, $12);
     "#,
         sqlx_issue_id,
         title,
-        text,
-        html
+        content.markdown,
+        content.text,
+        content.html,
+        publish_now,
+        scheduled_at,
+        show_in_archive,
+        track_engagement,
+        sqlx::types::Uuid::from_bytes(audience.list_id.into_bytes()),
+        audience
+            .segment_id
+            .map(|id| sqlx::types::Uuid::from_bytes(id.into_bytes())),
+        user_id.map(|id| sqlx::types::Uuid::from_bytes(id.into_bytes()))
     )
-    .execute(transaction)
+    .execute(&mut *transaction)
     .await?;
+    assign_slug(transaction, newsletter_issue_id, title).await?;
 
     Ok(newsletter_issue_id)
 }
+
+// Issues sharing a title are told apart by the beginning of their id
+#[tracing::instrument(skip(transaction))]
+pub(super) async fn assign_slug(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: uuid::Uuid,
+    title: &str,
+) -> Result<(), sqlx::Error> {
+    let slug = IssueSlug::from_title(title);
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issue
+        SET slug = CASE
+            WHEN EXISTS (
+                SELECT 1 FROM newsletter_issue
+                WHERE slug = $2 AND newsletter_issue_id != $1
+            )
+            THEN $2 || '-' || left(newsletter_issue_id::text, 8)
+            ELSE $2
+        END
+        WHERE newsletter_issue_id = $1
+    "#,
+        sqlx::types::Uuid::from_bytes(newsletter_issue_id.into_bytes()),
+        slug.as_ref()
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,372 @@
+use actix_web::{get, http::header::ContentType, post, web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+
+use crate::utils::{e500, see_other};
+
+struct IssueSummary {
+    newsletter_issue_id: sqlx::types::Uuid,
+    title: String,
+    published_at: Option<DateTime<Utc>>,
+    scheduled_at: Option<DateTime<Utc>>,
+    show_in_archive: bool,
+    slug: Option<String>,
+    track_engagement: bool,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ArchiveFormData {
+    // Checkbox, only sent when checked
+    show_in_archive: Option<String>,
+}
+
+struct DeliveryTotals {
+    sent: i64,
+    failed: i64,
+    skipped: i64,
+    pending: i64,
+}
+
+// Unique counts are the number of subscribers who opened or clicked
+struct EngagementTotals {
+    unique_opens: i64,
+    total_opens: i64,
+    unique_clicks: i64,
+    total_clicks: i64,
+}
+
+struct FailedRecipient {
+    subscriber_email: String,
+    n_retries: i16,
+    last_error: String,
+    failed_at: DateTime<Utc>,
+}
+
+// GET /admin/newsletters/{newsletter_issue_id}
+// Delivery report of a published issue
+#[get("/newsletters/{newsletter_issue_id}")]
+pub async fn issue_report(
+    newsletter_issue_id: web::Path<uuid::Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id =
+        sqlx::types::Uuid::from_bytes(newsletter_issue_id.into_inner().into_bytes());
+
+    let issue = match get_issue_summary(&pool, newsletter_issue_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(issue) => issue,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let totals = get_delivery_totals(&pool, newsletter_issue_id)
+        .await
+        .map_err(e500)?;
+    let engagement = if issue.track_engagement {
+        Some(
+            get_engagement_totals(&pool, newsletter_issue_id)
+                .await
+                .map_err(e500)?,
+        )
+    } else {
+        None
+    };
+    let failed_recipients = get_failed_recipients(&pool, newsletter_issue_id)
+        .await
+        .map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(get_issue_report_html(
+            issue,
+            totals,
+            engagement,
+            failed_recipients,
+        )))
+}
+
+// POST /admin/newsletters/{newsletter_issue_id}/archive
+// Show or hide the issue in the public archive at `/issues`
+#[tracing::instrument(name = "Toggle the archive visibility of an issue", skip(form, pool))]
+#[post("/newsletters/{newsletter_issue_id}/archive")]
+pub async fn set_issue_archive_visibility(
+    newsletter_issue_id: web::Path<uuid::Uuid>,
+    form: web::Form<ArchiveFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issue
+        SET show_in_archive = $2, updated_at = now()
+        WHERE newsletter_issue_id = $1
+    "#,
+        sqlx::types::Uuid::from_bytes(newsletter_issue_id.into_bytes()),
+        form.show_in_archive.is_some()
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update the archive visibility")
+    .map_err(e500)?
+    .rows_affected();
+
+    if n_updated == 0 {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    Ok(see_other(&format!(
+        "/admin/newsletters/{}",
+        newsletter_issue_id
+    )))
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_issue_summary(
+    pool: &PgPool,
+    newsletter_issue_id: sqlx::types::Uuid,
+) -> Result<Option<IssueSummary>, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT
+            newsletter_issue_id,
+            title,
+            published_at,
+            scheduled_at,
+            show_in_archive,
+            slug,
+            track_engagement
+        FROM newsletter_issue
+        WHERE newsletter_issue_id = $1
+    "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch the newsletter issue")?;
+
+    Ok(issue)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_delivery_totals(
+    pool: &PgPool,
+    newsletter_issue_id: sqlx::types::Uuid,
+) -> Result<DeliveryTotals, anyhow::Error> {
+    let totals = sqlx::query_as!(
+        DeliveryTotals,
+        r#"
+        SELECT
+            count(*) FILTER (WHERE outcome = 'sent') as "sent!",
+            count(*) FILTER (WHERE outcome = 'failed') as "failed!",
+            count(*) FILTER (WHERE outcome = 'skipped_invalid_address') as "skipped!",
+            (
+                SELECT count(*) FROM issue_delivery_queue
+                WHERE newsletter_issue_id = $1
+            ) as "pending!"
+        FROM issue_delivery_log
+        WHERE newsletter_issue_id = $1
+    "#,
+        newsletter_issue_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to compute the delivery totals")?;
+
+    Ok(totals)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_engagement_totals(
+    pool: &PgPool,
+    newsletter_issue_id: sqlx::types::Uuid,
+) -> Result<EngagementTotals, anyhow::Error> {
+    let totals = sqlx::query_as!(
+        EngagementTotals,
+        r#"
+        SELECT
+            opens.unique_opens as "unique_opens!",
+            opens.total_opens as "total_opens!",
+            clicks.unique_clicks as "unique_clicks!",
+            clicks.total_clicks as "total_clicks!"
+        FROM
+            (
+                SELECT
+                    count(DISTINCT subscriber_id) as unique_opens,
+                    count(*) as total_opens
+                FROM issue_opens
+                WHERE newsletter_issue_id = $1
+            ) opens,
+            (
+                SELECT
+                    count(DISTINCT subscriber_id) as unique_clicks,
+                    count(*) as total_clicks
+                FROM issue_clicks
+                WHERE newsletter_issue_id = $1
+            ) clicks
+    "#,
+        newsletter_issue_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to compute the engagement totals")?;
+
+    Ok(totals)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_failed_recipients(
+    pool: &PgPool,
+    newsletter_issue_id: sqlx::types::Uuid,
+) -> Result<Vec<FailedRecipient>, anyhow::Error> {
+    let recipients = sqlx::query_as!(
+        FailedRecipient,
+        r#"
+        SELECT subscriber_email, n_retries, last_error, failed_at
+        FROM issue_delivery_failures
+        WHERE newsletter_issue_id = $1
+        ORDER BY failed_at
+    "#,
+        newsletter_issue_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch the failed recipients")?;
+
+    Ok(recipients)
+}
+
+fn get_issue_report_html(
+    issue: IssueSummary,
+    totals: DeliveryTotals,
+    engagement: Option<EngagementTotals>,
+    failed_recipients: Vec<FailedRecipient>,
+) -> String {
+    let title = encode_minimal(&issue.title);
+    let published_at = match (issue.published_at, issue.scheduled_at) {
+        (Some(published_at), _) => format!("Published at {}", published_at.to_rfc2822()),
+        (None, Some(scheduled_at)) => format!("Scheduled for {}", scheduled_at.to_rfc2822()),
+        (None, None) => "Not published".to_string(),
+    };
+    let DeliveryTotals {
+        sent,
+        failed,
+        skipped,
+        pending,
+    } = totals;
+    let n_failed_recipients = failed_recipients.len();
+    let archive = get_archive_html(&issue);
+    let engagement = get_engagement_html(engagement, sent);
+
+    let mut rows = String::new();
+    for r in failed_recipients {
+        rows.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            encode_minimal(&r.subscriber_email),
+            r.n_retries,
+            encode_minimal(&r.last_error),
+            r.failed_at.to_rfc2822()
+        ));
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+    <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Delivery report</title>
+        </head>
+        <body>
+            <h1>{title}</h1>
+            <p>{published_at}</p>
+            {archive}
+            <ul>
+                <li>Sent: {sent}</li>
+                <li>Failed attempts: {failed}</li>
+                <li>Skipped (invalid address): {skipped}</li>
+                <li>Pending: {pending}</li>
+                <li>Failed recipients: {n_failed_recipients}</li>
+            </ul>
+            <h2>Engagement</h2>
+            {engagement}
+            <h2>Failed recipients</h2>
+            <table>
+                <tr>
+                    <th>Email</th>
+                    <th>Attempts</th>
+                    <th>Last error</th>
+                    <th>Failed at</th>
+                </tr>
+                {rows}
+            </table>
+            <p><a href="/admin/newsletters">&lt;- Back</a></p>
+        </body>
+    </html>"#
+    )
+}
+
+fn get_engagement_html(engagement: Option<EngagementTotals>, sent: i64) -> String {
+    let EngagementTotals {
+        unique_opens,
+        total_opens,
+        unique_clicks,
+        total_clicks,
+    } = match engagement {
+        Some(engagement) => engagement,
+        None => return "<p>Opens and clicks are not tracked for this issue.</p>".to_string(),
+    };
+    // Rates are relative to the emails sent
+    let rate = |n: i64| {
+        if sent == 0 {
+            "-".to_string()
+        } else {
+            format!("{:.1}%", n as f64 * 100.0 / sent as f64)
+        }
+    };
+    let (unique_open_rate, total_open_rate) = (rate(unique_opens), rate(total_opens));
+    let (unique_click_rate, total_click_rate) = (rate(unique_clicks), rate(total_clicks));
+
+    format!(
+        r#"<ul>
+                <li>Unique opens: {unique_opens} ({unique_open_rate})</li>
+                <li>Total opens: {total_opens} ({total_open_rate})</li>
+                <li>Unique clicks: {unique_clicks} ({unique_click_rate})</li>
+                <li>Total clicks: {total_clicks} ({total_click_rate})</li>
+            </ul>
+            <p>Opens are only seen when the mail client loads images, more subscribers read the issue.</p>"#
+    )
+}
+
+fn get_archive_html(issue: &IssueSummary) -> String {
+    let id = issue.newsletter_issue_id;
+    let (status, checked) = if !issue.show_in_archive {
+        ("Hidden from the public archive".to_string(), "")
+    } else if issue.published_at.is_some() {
+        let path = match &issue.slug {
+            Some(slug) => format!("/issues/{}", encode_minimal(slug)),
+            None => format!("/issues/{}", id),
+        };
+        (
+            format!(r#"Shown in the <a href="{path}">public archive</a>"#),
+            "checked",
+        )
+    } else {
+        (
+            "Shown in the public archive once published".to_string(),
+            "checked",
+        )
+    };
+
+    format!(
+        r#"<form action="/admin/newsletters/{id}/archive" method="post">
+                <p>{status}</p>
+                <label>
+                    <input type="checkbox" name="show_in_archive" {checked}>
+                    Show in the public archive
+                </label>
+                <button type="submit">Save</button>
+            </form>"#
+    )
+}
@@ -0,0 +1,174 @@
+use actix_web::{post, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use sqlx::PgPool;
+
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct ScheduleFormData {
+    scheduled_at: String,
+    timezone: String,
+}
+
+// POST /admin/newsletters/{newsletter_issue_id}/schedule
+// Move a scheduled issue to another time, as long as it has not been sent yet
+#[tracing::instrument(name = "Reschedule a newsletter issue", skip(form, pool))]
+#[post("/newsletters/{newsletter_issue_id}/schedule")]
+pub async fn reschedule_issue(
+    newsletter_issue_id: web::Path<uuid::Uuid>,
+    form: web::Form<ScheduleFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let scheduled_at = match parse_scheduled_at(&form.scheduled_at, &form.timezone) {
+        Ok(scheduled_at) => scheduled_at,
+        Err(e) => {
+            FlashMessage::error(e.to_string()).send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    };
+    let newsletter_issue_id =
+        sqlx::types::Uuid::from_bytes(newsletter_issue_id.into_inner().into_bytes());
+
+    // The scheduler locks the row while publishing, once it is done
+    // `published_at` is set and the issue can't be moved anymore
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issue
+        SET scheduled_at = $2
+        WHERE
+            newsletter_issue_id = $1 AND
+            scheduled_at IS NOT NULL AND
+            published_at IS NULL
+    "#,
+        newsletter_issue_id,
+        scheduled_at
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to reschedule the newsletter issue")
+    .map_err(e500)?
+    .rows_affected();
+
+    if n_updated == 0 {
+        FlashMessage::error("The newsletter issue is not scheduled anymore.").send();
+    } else {
+        FlashMessage::info(format!(
+            "The newsletter issue has been rescheduled for {}!",
+            scheduled_at.to_rfc2822()
+        ))
+        .send();
+    }
+    Ok(see_other("/admin/newsletters"))
+}
+
+// POST /admin/newsletters/{newsletter_issue_id}/cancel
+// Drop a scheduled issue before it is sent
+#[tracing::instrument(name = "Cancel a scheduled newsletter issue", skip(pool))]
+#[post("/newsletters/{newsletter_issue_id}/cancel")]
+pub async fn cancel_scheduled_issue(
+    newsletter_issue_id: web::Path<uuid::Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id =
+        sqlx::types::Uuid::from_bytes(newsletter_issue_id.into_inner().into_bytes());
+
+    let n_deleted = sqlx::query!(
+        r#"
+        DELETE FROM newsletter_issue
+        WHERE
+            newsletter_issue_id = $1 AND
+            scheduled_at IS NOT NULL AND
+            published_at IS NULL
+    "#,
+        newsletter_issue_id
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to cancel the newsletter issue")
+    .map_err(e500)?
+    .rows_affected();
+
+    if n_deleted == 0 {
+        FlashMessage::error("The newsletter issue is not scheduled anymore.").send();
+    } else {
+        FlashMessage::info("The scheduled newsletter issue has been cancelled.").send();
+    }
+    Ok(see_other("/admin/newsletters"))
+}
+
+// `scheduled_at` comes from a `datetime-local` input, i.e. a wall-clock time
+// without offset, and `timezone` is an IANA name such as `Europe/Paris`
+pub fn parse_scheduled_at(
+    scheduled_at: &str,
+    timezone: &str,
+) -> Result<DateTime<Utc>, anyhow::Error> {
+    let timezone = timezone.trim();
+    let tz: Tz = if timezone.is_empty() {
+        Tz::UTC
+    } else {
+        timezone
+            .parse()
+            .map_err(|_| anyhow::anyhow!("`{}` is not a known timezone.", timezone))?
+    };
+
+    let scheduled_at = scheduled_at.trim();
+    let naive = NaiveDateTime::parse_from_str(scheduled_at, "%Y-%m-%dT%H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(scheduled_at, "%Y-%m-%dT%H:%M:%S"))
+        .map_err(|_| anyhow::anyhow!("`{}` is not a valid date and time.", scheduled_at))?;
+
+    // Skipped (or repeated) when the clocks change
+    let scheduled_at = tz
+        .from_local_datetime(&naive)
+        .single()
+        .ok_or_else(|| anyhow::anyhow!("{} does not exist or is ambiguous in {}.", naive, tz))?
+        .with_timezone(&Utc);
+
+    if scheduled_at <= Utc::now() {
+        anyhow::bail!("The send time must be in the future.");
+    }
+
+    Ok(scheduled_at)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_scheduled_at;
+    use chrono::{TimeZone, Utc};
+    use claim::assert_err;
+
+    #[test]
+    fn the_timezone_is_applied() {
+        let scheduled_at = parse_scheduled_at("2099-01-01T09:00", "Europe/Paris").unwrap();
+        assert_eq!(
+            scheduled_at,
+            Utc.with_ymd_and_hms(2099, 1, 1, 8, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn an_empty_timezone_is_utc() {
+        let scheduled_at = parse_scheduled_at("2099-06-01T09:30:15", "").unwrap();
+        assert_eq!(
+            scheduled_at,
+            Utc.with_ymd_and_hms(2099, 6, 1, 9, 30, 15).unwrap()
+        );
+    }
+
+    #[test]
+    fn an_unknown_timezone_is_rejected() {
+        assert_err!(parse_scheduled_at("2099-01-01T09:00", "Mars/Olympus_Mons"));
+    }
+
+    #[test]
+    fn a_time_in_the_past_is_rejected() {
+        assert_err!(parse_scheduled_at("2001-01-01T09:00", "UTC"));
+    }
+
+    #[test]
+    fn a_time_skipped_by_daylight_saving_is_rejected() {
+        assert_err!(parse_scheduled_at("2099-03-29T02:30", "Europe/Paris"));
+    }
+}
@@ -0,0 +1,319 @@
+use actix_web::{get, http::header::ContentType, post, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages, Level};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+
+use crate::domain::SubscriberTag;
+use crate::segments::{insert_segment, Segment, SegmentFilter};
+use crate::utils::{e500, see_other};
+
+use super::subscribers::{parse_day, STATUSES};
+
+struct SegmentSummary {
+    name: String,
+    include_tags: Vec<String>,
+    exclude_tags: Vec<String>,
+    status: Option<String>,
+    subscribed_from: Option<DateTime<Utc>>,
+    subscribed_before: Option<DateTime<Utc>>,
+    n_members: i64,
+}
+
+// Every field but the name is left empty when not used
+#[derive(serde::Deserialize)]
+pub struct SegmentFormData {
+    name: String,
+    // Comma separated
+    #[serde(default)]
+    include_tags: String,
+    #[serde(default)]
+    exclude_tags: String,
+    #[serde(default)]
+    status: String,
+    // YYYY-MM-DD, both days included
+    #[serde(default)]
+    subscribed_from: String,
+    #[serde(default)]
+    subscribed_to: String,
+}
+
+impl SegmentFormData {
+    fn filter(&self) -> Result<SegmentFilter, String> {
+        fn field(value: &str) -> Option<&str> {
+            Some(value.trim()).filter(|v| !v.is_empty())
+        }
+
+        let status = match field(&self.status) {
+            Some(status) if !STATUSES.contains(&status) => {
+                return Err(format!("{} is not a subscriber status", status));
+            }
+            status => status.map(str::to_owned),
+        };
+
+        Ok(SegmentFilter {
+            include_tags: SubscriberTag::parse_list(&self.include_tags)?,
+            exclude_tags: SubscriberTag::parse_list(&self.exclude_tags)?,
+            status,
+            subscribed_from: parse_day(field(&self.subscribed_from))?,
+            subscribed_before: parse_day(field(&self.subscribed_to))?
+                .map(|t| t + chrono::Duration::days(1)),
+        })
+    }
+}
+
+// GET /admin/segments
+#[get("/segments")]
+pub async fn segments_page(
+    flash_message: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let message = flash_messages_html(&flash_message);
+
+    let segments = get_segment_summaries(&pool).await.map_err(e500)?;
+    let mut rows = String::new();
+    for segment in segments {
+        rows.push_str(&format!(
+            r#"<tr>
+                <td>{}</td>
+                <td>{}</td>
+                <td>{}</td>
+            </tr>"#,
+            encode_minimal(&segment.name),
+            encode_minimal(&describe_segment(&segment)),
+            segment.n_members,
+        ));
+    }
+
+    let mut status_options = String::from(r#"<option value="">Any</option>"#);
+    for status in STATUSES {
+        status_options.push_str(&format!(r#"<option value="{status}">{status}</option>"#));
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(get_segments_page_html(message, rows, status_options)))
+}
+
+// POST /admin/segments
+#[tracing::instrument(name = "Create a segment", skip(form, pool), fields(name = %form.name))]
+#[post("/segments")]
+pub async fn create_segment(
+    form: web::Form<SegmentFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = form.name.trim();
+    if name.is_empty() {
+        FlashMessage::error("The segment needs a name.").send();
+        return Ok(see_other("/admin/segments"));
+    }
+    let filter = match form.filter() {
+        Ok(filter) => filter,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/segments"));
+        }
+    };
+
+    match insert_segment(&pool, name, &filter).await.map_err(e500)? {
+        Some(_) => FlashMessage::info(format!("The {} segment has been created.", name)).send(),
+        None => FlashMessage::error(format!("{} is already the name of a segment.", name)).send(),
+    }
+    Ok(see_other("/admin/segments"))
+}
+
+// The options of a `segment_id` select, for the issue forms
+pub(super) fn segment_options_html(segments: &[Segment], selected: Option<uuid::Uuid>) -> String {
+    let mut options = String::from(r#"<option value="">Whole list</option>"#);
+    for segment in segments {
+        options.push_str(&format!(
+            r#"<option value="{}"{}>{}</option>"#,
+            segment.segment_id,
+            if Some(segment.segment_id) == selected {
+                " selected"
+            } else {
+                ""
+            },
+            encode_minimal(&segment.name)
+        ));
+    }
+
+    options
+}
+
+// e.g. `tagged rust, not tagged beta, confirmed, subscribed from 2026-01-01`
+fn describe_segment(segment: &SegmentSummary) -> String {
+    let mut parts = vec![];
+    if !segment.include_tags.is_empty() {
+        parts.push(format!("tagged {}", segment.include_tags.join(" or ")));
+    }
+    if !segment.exclude_tags.is_empty() {
+        parts.push(format!("not tagged {}", segment.exclude_tags.join(" or ")));
+    }
+    if let Some(status) = &segment.status {
+        parts.push(status.clone());
+    }
+    if let Some(from) = segment.subscribed_from {
+        parts.push(format!("subscribed from {}", from.format("%Y-%m-%d")));
+    }
+    if let Some(before) = segment.subscribed_before {
+        let to = before - chrono::Duration::days(1);
+        parts.push(format!("subscribed until {}", to.format("%Y-%m-%d")));
+    }
+
+    if parts.is_empty() {
+        "every subscriber".into()
+    } else {
+        parts.join(", ")
+    }
+}
+
+fn flash_messages_html(flash_message: &IncomingFlashMessages) -> String {
+    let mut message_str = String::new();
+
+    for m in flash_message.iter() {
+        let mut class = "error";
+        if m.level() == Level::Info {
+            class = "info"
+        }
+
+        // Messages may quote the name typed in the form
+        message_str.push_str(
+            format!(
+                "<p class='{}'><i>{}</i></p>",
+                class,
+                encode_minimal(m.content())
+            )
+            .as_str(),
+        );
+    }
+
+    message_str
+}
+
+// `n_members` only counts the confirmed subscribers, the ones issues go to
+#[tracing::instrument(skip_all)]
+async fn get_segment_summaries(pool: &PgPool) -> Result<Vec<SegmentSummary>, anyhow::Error> {
+    let segments = sqlx::query_as!(
+        SegmentSummary,
+        r#"
+        SELECT
+            segments.name,
+            include_tags,
+            exclude_tags,
+            segments.status,
+            subscribed_from,
+            subscribed_before,
+            count(subscriptions.id) FILTER (
+                WHERE subscriptions.status = 'confirmed'
+            ) as "n_members!"
+        FROM segments
+        LEFT JOIN segment_members ON segment_members.segment_id = segments.segment_id
+        LEFT JOIN subscriptions ON subscriptions.id = segment_members.subscriber_id
+        GROUP BY segments.segment_id
+        ORDER BY segments.name
+    "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch the segments")?;
+
+    Ok(segments)
+}
+
+fn get_segments_page_html(message: String, rows: String, status_options: String) -> String {
+    format!(
+        r#"
+        <html>
+            <head>
+                <title>Segments</title>
+                <style>
+                    .error {{
+                        color: red;
+                        font-weight: bold;
+                    }}
+                    .info {{
+                        color: green;
+                        font-weight: bold;
+                    }}
+                    td, th {{
+                        padding: 0 1em 0 0;
+                        text-align: left;
+                    }}
+                </style>
+            </head>
+            <body>
+                {message}
+                <table>
+                    <tr><th>Name</th><th>Filter</th><th>Confirmed members</th></tr>
+                    {rows}
+                </table>
+                <h2>New segment</h2>
+                <form action="/admin/segments" method="post">
+                    <label>Name
+                        <input type="text" name="name" placeholder="Rust fans" required>
+                    </label>
+                    <label>Tagged with one of
+                        <input type="text" name="include_tags" placeholder="rust, go">
+                    </label>
+                    <label>Not tagged with
+                        <input type="text" name="exclude_tags" placeholder="beta">
+                    </label>
+                    <label>Status
+                        <select name="status">{status_options}</select>
+                    </label>
+                    <label>Subscribed from
+                        <input type="date" name="subscribed_from">
+                    </label>
+                    <label>to
+                        <input type="date" name="subscribed_to">
+                    </label>
+                    <button type="submit">Create</button>
+                </form>
+                <p>Empty fields do not filter anything. Issues sent to a segment only reach its confirmed members in their list.</p>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+        </html>
+    "#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SegmentFormData;
+
+    fn form(query: &str) -> SegmentFormData {
+        serde_urlencoded::from_str(query).unwrap()
+    }
+
+    #[test]
+    fn empty_fields_do_not_filter_anything() {
+        let filter =
+            form("name=All&include_tags=&exclude_tags=&status=&subscribed_from=&subscribed_to=")
+                .filter()
+                .unwrap();
+        assert!(filter.include_tags.is_empty());
+        assert!(filter.exclude_tags.is_empty());
+        assert_eq!(filter.status, None);
+        assert_eq!(filter.subscribed_from, None);
+        assert_eq!(filter.subscribed_before, None);
+    }
+
+    #[test]
+    fn the_date_range_includes_its_last_day() {
+        let filter = form("name=January&subscribed_from=2026-01-01&subscribed_to=2026-01-31")
+            .filter()
+            .unwrap();
+        let from = filter.subscribed_from.unwrap();
+        let before = filter.subscribed_before.unwrap();
+        assert_eq!(before - from, chrono::Duration::days(31));
+    }
+
+    #[test]
+    fn invalid_fields_are_rejected() {
+        assert!(form("name=A&status=vip").filter().is_err());
+        assert!(form("name=A&include_tags=not+valid").filter().is_err());
+        assert!(form("name=A&subscribed_to=01/31/2026").filter().is_err());
+    }
+}
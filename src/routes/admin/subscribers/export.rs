@@ -0,0 +1,195 @@
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::web::Bytes;
+use actix_web::{get, web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use csv_async::AsyncWriterBuilder;
+use sqlx::PgPool;
+
+use crate::utils::e400;
+
+use super::get::STATUSES;
+
+// Rows fetched with each query, only one batch is in memory at a time
+const EXPORT_BATCH_SIZE: i64 = 1000;
+
+#[derive(serde::Deserialize, Debug)]
+pub struct ExportParameters {
+    format: Option<String>,
+    status: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ExportFormat {
+    Csv,
+    Json,
+}
+
+struct ExportedSubscriber {
+    id: sqlx::types::Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscription_at: DateTime<Utc>,
+}
+
+struct ExportState {
+    pool: PgPool,
+    format: ExportFormat,
+    status: Option<String>,
+    // Keyset of the last exported row, `None` before the first batch
+    after: Option<(DateTime<Utc>, sqlx::types::Uuid)>,
+    done: bool,
+}
+
+// GET /admin/subscribers/export?format=csv|json&status=...
+// The response is streamed one batch of rows at a time, oldest subscribers
+// first. CSV is the default format, every status is exported by default.
+#[tracing::instrument(name = "Export subscribers", skip(pool))]
+#[get("/subscribers/export")]
+pub async fn export_subscribers(
+    params: web::Query<ExportParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let params = params.into_inner();
+    let format = match params.format.as_deref().unwrap_or("csv") {
+        "csv" => ExportFormat::Csv,
+        "json" => ExportFormat::Json,
+        other => return Err(e400(format!("{} is not an export format", other))),
+    };
+    let status = params.status.filter(|s| !s.is_empty());
+    if let Some(status) = &status {
+        if !STATUSES.contains(&status.as_str()) {
+            return Err(e400(format!("{} is not a subscriber status", status)));
+        }
+    }
+
+    let (content_type, filename) = match format {
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "subscribers.csv"),
+        ExportFormat::Json => ("application/json", "subscribers.json"),
+    };
+    let state = ExportState {
+        pool: pool.get_ref().clone(),
+        format,
+        status,
+        after: None,
+        done: false,
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(filename.into())],
+        })
+        .streaming(futures_util::stream::try_unfold(state, next_chunk)))
+}
+
+// The chunks of a JSON export put together form a single array
+async fn next_chunk(mut state: ExportState) -> Result<Option<(Bytes, ExportState)>, anyhow::Error> {
+    if state.done {
+        return Ok(None);
+    }
+    let is_first_chunk = state.after.is_none();
+    let subscribers = get_batch(&state).await?;
+    state.done = (subscribers.len() as i64) < EXPORT_BATCH_SIZE;
+    state.after = subscribers
+        .last()
+        .map(|s| (s.subscription_at, s.id))
+        .or(state.after);
+
+    let mut chunk = match state.format {
+        ExportFormat::Csv => csv_rows(&subscribers, is_first_chunk).await?,
+        ExportFormat::Json => json_rows(&subscribers, is_first_chunk)?,
+    };
+    if state.format == ExportFormat::Json && state.done {
+        chunk.extend_from_slice(b"\n]\n");
+    }
+
+    Ok(Some((Bytes::from(chunk), state)))
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_batch(state: &ExportState) -> Result<Vec<ExportedSubscriber>, anyhow::Error> {
+    let (after_subscription_at, after_id) = state.after.unzip();
+
+    let subscribers = sqlx::query_as!(
+        ExportedSubscriber,
+        r#"
+        SELECT id, email, name, status, subscription_at
+        FROM subscriptions
+        WHERE
+            ($1::text IS NULL OR status = $1) AND
+            ($2::timestamptz IS NULL OR (subscription_at, id) > ($2, $3::uuid))
+        ORDER BY subscription_at, id
+        LIMIT $4
+    "#,
+        state.status,
+        after_subscription_at,
+        after_id,
+        EXPORT_BATCH_SIZE
+    )
+    .fetch_all(&state.pool)
+    .await
+    .context("Failed to fetch a batch of subscribers to export")?;
+
+    Ok(subscribers)
+}
+
+async fn csv_rows(
+    subscribers: &[ExportedSubscriber],
+    with_header: bool,
+) -> Result<Vec<u8>, anyhow::Error> {
+    let mut writer = AsyncWriterBuilder::new()
+        .has_headers(false)
+        .create_writer(vec![]);
+    if with_header {
+        writer
+            .write_record(&["id", "email", "name", "status", "subscription_at"])
+            .await?;
+    }
+    for s in subscribers {
+        writer
+            .write_record(&[
+                s.id.to_string().as_str(),
+                &s.email,
+                &s.name,
+                &s.status,
+                &s.subscription_at.to_rfc3339(),
+            ])
+            .await?;
+    }
+
+    writer
+        .into_inner()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to write the exported rows: {}", e))
+}
+
+fn json_rows(
+    subscribers: &[ExportedSubscriber],
+    is_first_chunk: bool,
+) -> Result<Vec<u8>, anyhow::Error> {
+    let mut rows = vec![];
+    if is_first_chunk {
+        rows.push(b'[');
+    }
+    for (i, s) in subscribers.iter().enumerate() {
+        if i > 0 || !is_first_chunk {
+            rows.push(b',');
+        }
+        rows.extend_from_slice(b"\n  ");
+        serde_json::to_writer(
+            &mut rows,
+            &serde_json::json!({
+                "id": s.id.to_string(),
+                "email": s.email,
+                "name": s.name,
+                "status": s.status,
+                "subscription_at": s.subscription_at.to_rfc3339(),
+            }),
+        )?;
+    }
+
+    Ok(rows)
+}
@@ -0,0 +1,486 @@
+use actix_web::{get, http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{IncomingFlashMessages, Level};
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, Utc};
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+
+use crate::utils::{e400, e500, page_number};
+
+const SUBSCRIBERS_PER_PAGE: i64 = 50;
+pub(in crate::routes::admin) const STATUSES: [&str; 3] =
+    ["pending_confirmation", "confirmed", "unsubscribed"];
+
+struct SubscriberSummary {
+    id: sqlx::types::Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscription_at: DateTime<Utc>,
+}
+
+struct Subscriber {
+    email: String,
+    name: String,
+    status: String,
+    subscription_at: DateTime<Utc>,
+    last_confirmation_sent_at: Option<DateTime<Utc>>,
+    // The lists joined with their status, e.g. `Newsletter (confirmed)`
+    lists: Option<String>,
+    // Comma separated
+    tags: Option<String>,
+}
+
+// Every field comes from the search form, left empty when not used
+#[derive(serde::Deserialize, serde::Serialize, Clone, Default)]
+pub struct SubscriberFilters {
+    q: Option<String>,
+    status: Option<String>,
+    // YYYY-MM-DD, both days included
+    subscribed_from: Option<String>,
+    subscribed_to: Option<String>,
+    page: Option<i64>,
+}
+
+impl SubscriberFilters {
+    fn field(value: &Option<String>) -> Option<&str> {
+        value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+    }
+
+    fn status(&self) -> Result<Option<&str>, String> {
+        match Self::field(&self.status) {
+            Some(status) if !STATUSES.contains(&status) => {
+                Err(format!("{} is not a subscriber status", status))
+            }
+            status => Ok(status),
+        }
+    }
+
+    // Substring of the email or the name, `%` and `_` are matched literally
+    fn pattern(&self) -> Option<String> {
+        Self::field(&self.q).map(|q| {
+            let q = q
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            format!("%{}%", q)
+        })
+    }
+
+    fn subscribed_from(&self) -> Result<Option<DateTime<Utc>>, String> {
+        parse_day(Self::field(&self.subscribed_from))
+    }
+
+    // Exclusive upper bound, the day after `subscribed_to`
+    fn subscribed_before(&self) -> Result<Option<DateTime<Utc>>, String> {
+        Ok(parse_day(Self::field(&self.subscribed_to))?.map(|t| t + chrono::Duration::days(1)))
+    }
+
+    fn page(&self) -> i64 {
+        page_number(self.page)
+    }
+
+    fn page_url(&self, page: i64) -> String {
+        let filters = SubscriberFilters {
+            page: Some(page),
+            ..self.clone()
+        };
+        format!(
+            "/admin/subscribers?{}",
+            serde_urlencoded::to_string(filters).unwrap_or_default()
+        )
+    }
+}
+
+pub(in crate::routes::admin) fn parse_day(
+    day: Option<&str>,
+) -> Result<Option<DateTime<Utc>>, String> {
+    day.map(|day| {
+        NaiveDate::parse_from_str(day, "%Y-%m-%d")
+            .map(|d| d.and_hms_opt(0, 0, 0).unwrap().and_utc())
+            .map_err(|_| format!("{} is not a valid date, expected YYYY-MM-DD", day))
+    })
+    .transpose()
+}
+
+// GET /admin/subscribers
+// Most recent subscribers first, filtered by the search form
+#[tracing::instrument(name = "List subscribers", skip_all)]
+#[get("/subscribers")]
+pub async fn subscribers_page(
+    filters: web::Query<SubscriberFilters>,
+    flash_message: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let message = flash_messages_html(&flash_message);
+    let page = filters.page();
+
+    let mut subscribers = get_subscribers(&pool, &filters).await?;
+    // One more subscriber than displayed is fetched to know if there is a next page
+    let has_next_page = subscribers.len() as i64 > SUBSCRIBERS_PER_PAGE;
+    subscribers.truncate(SUBSCRIBERS_PER_PAGE as usize);
+
+    let mut rows = String::new();
+    for subscriber in &subscribers {
+        rows.push_str(&format!(
+            r#"<tr>
+                <td><a href="/admin/subscribers/{}">{}</a></td>
+                <td>{}</td>
+                <td>{}</td>
+                <td>{}</td>
+            </tr>"#,
+            subscriber.id,
+            encode_minimal(&subscriber.email),
+            encode_minimal(&subscriber.name),
+            subscriber.status,
+            subscriber.subscription_at.format("%Y-%m-%d %H:%M")
+        ));
+    }
+    if subscribers.is_empty() {
+        rows.push_str(r#"<tr><td colspan="4">No subscriber found.</td></tr>"#);
+    }
+
+    let mut pagination = String::new();
+    if page > 1 {
+        pagination.push_str(&format!(
+            r#"<a href="{}">&lt;- Previous</a> "#,
+            encode_minimal(&filters.page_url(page - 1))
+        ));
+    }
+    if has_next_page {
+        pagination.push_str(&format!(
+            r#"<a href="{}">Next -&gt;</a>"#,
+            encode_minimal(&filters.page_url(page + 1))
+        ));
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(get_subscribers_page_html(
+            message, &filters, rows, pagination,
+        )))
+}
+
+// GET /admin/subscribers/{subscriber_id}
+#[tracing::instrument(name = "Subscriber details", skip(flash_message, pool))]
+#[get("/subscribers/{subscriber_id}")]
+pub async fn subscriber_page(
+    subscriber_id: web::Path<uuid::Uuid>,
+    flash_message: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let message = flash_messages_html(&flash_message);
+
+    let subscriber = match get_subscriber(&pool, subscriber_id).await.map_err(e500)? {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(get_subscriber_page_html(message, subscriber_id, subscriber)))
+}
+
+pub(super) fn flash_messages_html(flash_message: &IncomingFlashMessages) -> String {
+    let mut message_str = String::new();
+
+    for m in flash_message.iter() {
+        let mut class = "error";
+        if m.level() == Level::Info {
+            class = "info"
+        }
+
+        // Messages may quote subscriber emails
+        message_str.push_str(
+            format!(
+                "<p class='{}'><i>{}</i></p>",
+                class,
+                encode_minimal(m.content())
+            )
+            .as_str(),
+        );
+    }
+
+    message_str
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_subscribers(
+    pool: &PgPool,
+    filters: &SubscriberFilters,
+) -> Result<Vec<SubscriberSummary>, actix_web::Error> {
+    let status = filters.status().map_err(e400)?;
+    let subscribed_from = filters.subscribed_from().map_err(e400)?;
+    let subscribed_before = filters.subscribed_before().map_err(e400)?;
+
+    let subscribers = sqlx::query_as!(
+        SubscriberSummary,
+        r#"
+        SELECT id, email, name, status, subscription_at
+        FROM subscriptions
+        WHERE
+            ($1::text IS NULL OR email ILIKE $1 OR name ILIKE $1) AND
+            ($2::text IS NULL OR status = $2) AND
+            ($3::timestamptz IS NULL OR subscription_at >= $3) AND
+            ($4::timestamptz IS NULL OR subscription_at < $4)
+        ORDER BY subscription_at DESC, email
+        LIMIT $5 OFFSET $6
+    "#,
+        filters.pattern(),
+        status,
+        subscribed_from,
+        subscribed_before,
+        SUBSCRIBERS_PER_PAGE + 1,
+        (filters.page() - 1) * SUBSCRIBERS_PER_PAGE
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch the subscribers")
+    .map_err(e500)?;
+
+    Ok(subscribers)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_subscriber(
+    pool: &PgPool,
+    subscriber_id: uuid::Uuid,
+) -> Result<Option<Subscriber>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT
+            email, name, status, subscription_at,
+            (
+                SELECT max(created_at) FROM subscription_tokens
+                WHERE subscriber_id = subscriptions.id
+            ) as last_confirmation_sent_at,
+            (
+                SELECT string_agg(lists.name || ' (' || list_memberships.status || ')', ', ')
+                FROM list_memberships
+                JOIN lists ON lists.list_id = list_memberships.list_id
+                WHERE subscriber_id = subscriptions.id
+            ) as lists,
+            (
+                SELECT string_agg(tag, ', ' ORDER BY tag) FROM subscriber_tags
+                WHERE subscriber_id = subscriptions.id
+            ) as tags
+        FROM subscriptions
+        WHERE id = $1
+    "#,
+        sqlx::types::Uuid::from_bytes(subscriber_id.into_bytes())
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch the subscriber")?;
+
+    Ok(subscriber)
+}
+
+const STYLE: &str = r#"
+    <style>
+        .error {
+            color: red;
+            font-weight: bold;
+        }
+        .info {
+            color: green;
+            font-weight: bold;
+        }
+        td, th {
+            padding: 0 1em 0 0;
+            text-align: left;
+        }
+    </style>
+"#;
+
+fn get_subscribers_page_html(
+    message: String,
+    filters: &SubscriberFilters,
+    rows: String,
+    pagination: String,
+) -> String {
+    let value = |field: &Option<String>| encode_minimal(field.as_deref().unwrap_or_default());
+    let q = value(&filters.q);
+    let subscribed_from = value(&filters.subscribed_from);
+    let subscribed_to = value(&filters.subscribed_to);
+    // The export only filters on the status
+    let (export_status, export_query) = match SubscriberFilters::field(&filters.status) {
+        Some(status) => (
+            format!(" with the {} status", encode_minimal(status)),
+            format!("&amp;status={}", urlencoding::encode(status)),
+        ),
+        None => (String::new(), String::new()),
+    };
+    let mut status_options = String::from(r#"<option value="">Any</option>"#);
+    for status in STATUSES {
+        let selected = if filters.status.as_deref() == Some(status) {
+            " selected"
+        } else {
+            ""
+        };
+        status_options.push_str(&format!(
+            r#"<option value="{status}"{selected}>{status}</option>"#
+        ));
+    }
+
+    format!(
+        r#"
+        <html>
+            <head>
+                <title>Subscribers</title>
+                {STYLE}
+            </head>
+            <body>
+                {message}
+                <form action="/admin/subscribers" method="get">
+                    <label>Email or name
+                        <input type="text" name="q" value="{q}">
+                    </label>
+                    <label>Status
+                        <select name="status">{status_options}</select>
+                    </label>
+                    <label>Subscribed from
+                        <input type="date" name="subscribed_from" value="{subscribed_from}">
+                    </label>
+                    <label>to
+                        <input type="date" name="subscribed_to" value="{subscribed_to}">
+                    </label>
+                    <button type="submit">Search</button>
+                </form>
+                <table>
+                    <tr><th>Email</th><th>Name</th><th>Status</th><th>Subscribed at</th></tr>
+                    {rows}
+                </table>
+                <p>{pagination}</p>
+                <p>
+                    Export the subscribers{export_status} as
+                    <a href="/admin/subscribers/export?format=csv{export_query}">CSV</a> or
+                    <a href="/admin/subscribers/export?format=json{export_query}">JSON</a>
+                </p>
+                <h2>Import</h2>
+                <p>A CSV file with a header line, its <code>email</code> and <code>name</code> columns are imported.</p>
+                <form action="/admin/subscribers/import" method="post" enctype="multipart/form-data">
+                    <label>Imported subscribers
+                        <select name="mode">
+                            <option value="pending">receive a confirmation email</option>
+                            <option value="confirmed">are confirmed</option>
+                        </select>
+                    </label>
+                    <input type="file" name="file" accept=".csv,text/csv" required>
+                    <button type="submit">Import</button>
+                </form>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+        </html>
+    "#
+    )
+}
+
+fn get_subscriber_page_html(
+    message: String,
+    subscriber_id: uuid::Uuid,
+    subscriber: Subscriber,
+) -> String {
+    let email = encode_minimal(&subscriber.email);
+    let name = encode_minimal(&subscriber.name);
+    let status = subscriber.status;
+    let subscription_at = subscriber.subscription_at.to_rfc2822();
+    let last_confirmation_sent_at = subscriber
+        .last_confirmation_sent_at
+        .map(|t| t.to_rfc2822())
+        .unwrap_or_else(|| "never".into());
+    let lists = encode_minimal(subscriber.lists.as_deref().unwrap_or("none"));
+    let tags = encode_minimal(subscriber.tags.as_deref().unwrap_or("none"));
+    let tags_value = encode_attribute(subscriber.tags.as_deref().unwrap_or_default());
+
+    let mut actions = String::new();
+    if status != "confirmed" {
+        actions.push_str(&format!(
+            r#"<form action="/admin/subscribers/{subscriber_id}/confirm" method="post">
+                    <button type="submit">Confirm</button>
+                </form>"#
+        ));
+    }
+    if status != "unsubscribed" {
+        actions.push_str(&format!(
+            r#"<form action="/admin/subscribers/{subscriber_id}/unsubscribe" method="post">
+                    <button type="submit">Unsubscribe</button>
+                </form>"#
+        ));
+    }
+
+    format!(
+        r#"
+        <html>
+            <head>
+                <title>Subscriber</title>
+                {STYLE}
+            </head>
+            <body>
+                {message}
+                <h2>{email}</h2>
+                <table>
+                    <tr><th>Name</th><td>{name}</td></tr>
+                    <tr><th>Status</th><td>{status}</td></tr>
+                    <tr><th>Subscribed at</th><td>{subscription_at}</td></tr>
+                    <tr><th>Last confirmation email</th><td>{last_confirmation_sent_at}</td></tr>
+                    <tr><th>Lists</th><td>{lists}</td></tr>
+                    <tr><th>Tags</th><td>{tags}</td></tr>
+                </table>
+                <form action="/admin/subscribers/{subscriber_id}/tags" method="post">
+                    <label>Tags
+                        <input type="text" name="tags" value="{tags_value}" placeholder="rust, beta">
+                    </label>
+                    <button type="submit">Save tags</button>
+                </form>
+                {actions}
+                <form action="/admin/subscribers/{subscriber_id}/delete" method="post">
+                    <button type="submit">Delete</button>
+                </form>
+                <p><a href="/admin/subscribers">&lt;- Back</a></p>
+            </body>
+        </html>
+    "#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriberFilters;
+
+    #[test]
+    fn empty_fields_are_ignored() {
+        let filters: SubscriberFilters =
+            serde_urlencoded::from_str("q=&status=&subscribed_from=&subscribed_to=").unwrap();
+        assert_eq!(filters.pattern(), None);
+        assert_eq!(filters.status(), Ok(None));
+        assert_eq!(filters.subscribed_from(), Ok(None));
+        assert_eq!(filters.subscribed_before(), Ok(None));
+    }
+
+    #[test]
+    fn wildcards_of_the_search_are_escaped() {
+        let filters: SubscriberFilters = serde_urlencoded::from_str("q=50%25_off").unwrap();
+        assert_eq!(filters.pattern().as_deref(), Some("%50\\%\\_off%"));
+    }
+
+    #[test]
+    fn the_date_range_includes_its_last_day() {
+        let filters: SubscriberFilters =
+            serde_urlencoded::from_str("subscribed_from=2026-01-01&subscribed_to=2026-01-31")
+                .unwrap();
+        let from = filters.subscribed_from().unwrap().unwrap();
+        let before = filters.subscribed_before().unwrap().unwrap();
+        assert_eq!(before - from, chrono::Duration::days(31));
+    }
+
+    #[test]
+    fn invalid_filters_are_rejected() {
+        let filters: SubscriberFilters =
+            serde_urlencoded::from_str("status=vip&subscribed_from=01/31/2026").unwrap();
+        assert!(filters.status().is_err());
+        assert!(filters.subscribed_from().is_err());
+    }
+}
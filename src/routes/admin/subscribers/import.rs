@@ -0,0 +1,292 @@
+use actix_multipart::{Field, Multipart};
+use actix_web::http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType};
+use actix_web::{get, post, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use sqlx::PgPool;
+use tokio::io::AsyncWriteExt;
+
+use crate::authentication::UserID;
+use crate::email_client::EmailTransport;
+use crate::subscriber_import::{import_subscribers, ImportError, ImportMode, ImportReport};
+use crate::utils::{app_base_url, e500, see_other};
+
+use super::get::flash_messages_html;
+
+struct SubscriberImport {
+    mode: String,
+    n_rows: i32,
+    n_imported: i32,
+    n_failed: i32,
+    n_errors: i32,
+    created_at: DateTime<Utc>,
+}
+
+// POST /admin/subscribers/import
+// A `multipart/form-data` form with a `mode` field, `confirmed` or
+// `pending`, followed by the CSV `file`
+#[tracing::instrument(
+    name = "Import subscribers",
+    skip_all,
+    fields(user_id=tracing::field::Empty)
+)]
+#[post("/subscribers/import")]
+pub async fn import_subscribers_upload(
+    mut payload: Multipart,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailTransport>,
+    base_url: web::Data<String>,
+    app_port: web::Data<u16>,
+    user_id: web::ReqData<UserID>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    tracing::Span::current().record("user_id", tracing::field::display(*user_id));
+    let base_host_url = app_base_url(&base_url, **app_port);
+
+    let mut mode = None;
+    while let Some(field) = payload.try_next().await? {
+        match field.name() {
+            "mode" => {
+                let value = read_text_field(field).await?;
+                match value.parse::<ImportMode>() {
+                    Ok(m) => mode = Some(m),
+                    Err(e) => {
+                        FlashMessage::error(e).send();
+                        return Ok(see_other("/admin/subscribers"));
+                    }
+                }
+            }
+            "file" => {
+                // The form puts the mode first, the file is imported as it is uploaded
+                let mode = match mode {
+                    Some(mode) => mode,
+                    None => {
+                        FlashMessage::error("Choose how to import the subscribers.").send();
+                        return Ok(see_other("/admin/subscribers"));
+                    }
+                };
+                let report =
+                    match import_field(&pool, email_client.get_ref(), &base_host_url, mode, field)
+                        .await
+                    {
+                        Ok(report) => report,
+                        Err(ImportError::InvalidFile(e)) => {
+                            FlashMessage::error(e).send();
+                            return Ok(see_other("/admin/subscribers"));
+                        }
+                        Err(e) => return Err(e500(e)),
+                    };
+
+                let subscriber_import_id = store_import(&pool, *user_id, mode, &report)
+                    .await
+                    .map_err(e500)?;
+                FlashMessage::info(format!(
+                    "{} of the {} rows have been imported.",
+                    report.n_imported, report.n_rows
+                ))
+                .send();
+                return Ok(see_other(&format!(
+                    "/admin/subscribers/imports/{}",
+                    subscriber_import_id
+                )));
+            }
+            _ => {}
+        }
+    }
+
+    FlashMessage::error("Choose a CSV file to import.").send();
+    Ok(see_other("/admin/subscribers"))
+}
+
+// GET /admin/subscribers/imports/{subscriber_import_id}
+#[tracing::instrument(name = "Subscriber import summary", skip(flash_message, pool))]
+#[get("/subscribers/imports/{subscriber_import_id}")]
+pub async fn subscriber_import_page(
+    subscriber_import_id: web::Path<uuid::Uuid>,
+    flash_message: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_import_id = subscriber_import_id.into_inner();
+    let message = flash_messages_html(&flash_message);
+
+    let import = sqlx::query_as!(
+        SubscriberImport,
+        r#"
+        SELECT mode, n_rows, n_imported, n_failed, n_errors, created_at
+        FROM subscriber_imports
+        WHERE subscriber_import_id = $1
+    "#,
+        sqlx::types::Uuid::from_bytes(subscriber_import_id.into_bytes())
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to fetch the import")
+    .map_err(e500)?;
+
+    let import = match import {
+        Some(import) => import,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(get_import_html(message, subscriber_import_id, import)))
+}
+
+// GET /admin/subscribers/imports/{subscriber_import_id}/errors.csv
+#[tracing::instrument(name = "Download an import error report", skip(pool))]
+#[get("/subscribers/imports/{subscriber_import_id}/errors.csv")]
+pub async fn subscriber_import_errors(
+    subscriber_import_id: web::Path<uuid::Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let error_report = sqlx::query!(
+        r#"SELECT error_report FROM subscriber_imports WHERE subscriber_import_id = $1"#,
+        sqlx::types::Uuid::from_bytes(subscriber_import_id.into_inner().into_bytes())
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to fetch the error report")
+    .map_err(e500)?
+    .map(|r| r.error_report);
+
+    match error_report {
+        Some(error_report) => Ok(HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .insert_header(ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename("import-errors.csv".into())],
+            })
+            .body(error_report)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+async fn read_text_field(mut field: Field) -> Result<String, actix_web::Error> {
+    let mut value = vec![];
+    while let Some(chunk) = field.try_next().await? {
+        value.extend_from_slice(&chunk);
+    }
+    Ok(String::from_utf8_lossy(&value).trim().to_owned())
+}
+
+// The upload is piped to the importer, which reads it as it arrives
+async fn import_field(
+    pool: &PgPool,
+    email_client: &dyn EmailTransport,
+    base_host_url: &str,
+    mode: ImportMode,
+    mut field: Field,
+) -> Result<ImportReport, ImportError> {
+    let (mut writer, reader) = tokio::io::duplex(64 * 1024);
+    let upload = async move {
+        while let Some(chunk) = field.try_next().await? {
+            // The importer stopped reading, its result tells why
+            if writer.write_all(&chunk).await.is_err() {
+                break;
+            }
+        }
+        Ok::<_, actix_multipart::MultipartError>(())
+    };
+
+    let (upload, report) = tokio::join!(
+        upload,
+        import_subscribers(pool, email_client, base_host_url, mode, reader)
+    );
+    let report = report?;
+    upload.map_err(|e| anyhow::anyhow!("The upload was interrupted: {}", e))?;
+    Ok(report)
+}
+
+#[tracing::instrument(skip(pool, report))]
+async fn store_import(
+    pool: &PgPool,
+    user_id: uuid::Uuid,
+    mode: ImportMode,
+    report: &ImportReport,
+) -> Result<uuid::Uuid, anyhow::Error> {
+    let subscriber_import_id = uuid::Uuid::new_v4();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_imports (
+            subscriber_import_id, user_id, mode,
+            n_rows, n_imported, n_failed, n_errors, error_report, created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, now())
+    "#,
+        sqlx::types::Uuid::from_bytes(subscriber_import_id.into_bytes()),
+        sqlx::types::Uuid::from_bytes(user_id.into_bytes()),
+        mode.as_str(),
+        report.n_rows as i32,
+        report.n_imported as i32,
+        report.n_failed as i32,
+        report.errors.len() as i32,
+        report.error_report().await?
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the import")?;
+
+    Ok(subscriber_import_id)
+}
+
+fn get_import_html(
+    message: String,
+    subscriber_import_id: uuid::Uuid,
+    import: SubscriberImport,
+) -> String {
+    let SubscriberImport {
+        mode,
+        n_rows,
+        n_imported,
+        n_failed,
+        n_errors,
+        created_at,
+    } = import;
+    let created_at = created_at.to_rfc2822();
+    let report = if n_errors > 0 {
+        format!(
+            r#"<p>
+                    {n_errors} rows need attention, see the
+                    <a href="/admin/subscribers/imports/{subscriber_import_id}/errors.csv">error report</a>.
+                </p>"#
+        )
+    } else {
+        "<p>Every row has been imported.</p>".into()
+    };
+
+    format!(
+        r#"
+        <html>
+            <head>
+                <title>Subscriber import</title>
+                <style>
+                    .error {{
+                        color: red;
+                        font-weight: bold;
+                    }}
+                    .info {{
+                        color: green;
+                        font-weight: bold;
+                    }}
+                </style>
+            </head>
+            <body>
+                {message}
+                <h2>Import of {created_at}</h2>
+                <ul>
+                    <li>Mode: {mode}</li>
+                    <li>Rows: {n_rows}</li>
+                    <li>Imported: {n_imported}</li>
+                    <li>Not imported: {n_failed}</li>
+                </ul>
+                {report}
+                <p><a href="/admin/subscribers">&lt;- Back</a></p>
+            </body>
+        </html>
+    "#
+    )
+}
@@ -0,0 +1,11 @@
+mod export;
+mod get;
+mod import;
+mod post;
+
+pub use export::*;
+pub use get::*;
+pub use import::*;
+pub use post::*;
+
+pub(super) use get::{parse_day, STATUSES};
@@ -0,0 +1,178 @@
+use actix_web::{post, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::domain::SubscriberTag;
+use crate::mailing_lists::confirm_pending_memberships;
+use crate::routes::mark_subscriber_as_unsubscribed;
+use crate::segments::set_tags;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct TagsFormData {
+    // Comma separated, replaces every tag of the subscriber
+    tags: String,
+}
+
+fn subscriber_url(subscriber_id: uuid::Uuid) -> String {
+    format!("/admin/subscribers/{}", subscriber_id)
+}
+
+// POST /admin/subscribers/{subscriber_id}/confirm
+// For people who could not click their confirmation link
+#[tracing::instrument(name = "Confirm a subscriber manually", skip(pool))]
+#[post("/subscribers/{subscriber_id}/confirm")]
+pub async fn confirm_subscriber_manually(
+    subscriber_id: web::Path<uuid::Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let id = sqlx::types::Uuid::from_bytes(subscriber_id.into_bytes());
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+
+    // Someone who unsubscribed withdrew their consent, only an admin
+    // mistake would confirm them again
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'confirmed'
+        WHERE id = $1 AND status = 'pending_confirmation'
+    "#,
+        id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to confirm the subscriber")
+    .map_err(e500)?
+    .rows_affected();
+    if n_updated == 0 {
+        FlashMessage::error("Only a subscriber pending confirmation can be confirmed.").send();
+        return Ok(see_other(&subscriber_url(subscriber_id)));
+    }
+
+    // Links still in their inbox have nothing left to confirm
+    sqlx::query!(
+        r#"
+        UPDATE subscription_tokens SET consumed_at = now()
+        WHERE subscriber_id = $1 AND consumed_at IS NULL
+    "#,
+        id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to consume the confirmation tokens")
+    .map_err(e500)?;
+    confirm_pending_memberships(&mut transaction, id)
+        .await
+        .context("Failed to confirm the lists of the subscriber")
+        .map_err(e500)?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the confirmation")
+        .map_err(e500)?;
+
+    FlashMessage::info("The subscriber has been confirmed.").send();
+    Ok(see_other(&subscriber_url(subscriber_id)))
+}
+
+// POST /admin/subscribers/{subscriber_id}/unsubscribe
+// Same as the subscriber clicking the link at the bottom of an issue
+#[tracing::instrument(name = "Unsubscribe a subscriber manually", skip(pool))]
+#[post("/subscribers/{subscriber_id}/unsubscribe")]
+pub async fn unsubscribe_subscriber_manually(
+    subscriber_id: web::Path<uuid::Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+
+    mark_subscriber_as_unsubscribed(&pool, subscriber_id)
+        .await
+        .map_err(e500)?;
+
+    FlashMessage::info("The subscriber has been unsubscribed.").send();
+    Ok(see_other(&subscriber_url(subscriber_id)))
+}
+
+// POST /admin/subscribers/{subscriber_id}/tags
+#[tracing::instrument(name = "Tag a subscriber", skip(form, pool), fields(tags = %form.tags))]
+#[post("/subscribers/{subscriber_id}/tags")]
+pub async fn tag_subscriber(
+    subscriber_id: web::Path<uuid::Uuid>,
+    form: web::Form<TagsFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let tags = match SubscriberTag::parse_list(&form.tags) {
+        Ok(tags) => tags,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&subscriber_url(subscriber_id)));
+        }
+    };
+
+    if !set_tags(&pool, subscriber_id, &tags).await.map_err(e500)? {
+        FlashMessage::error("The subscriber does not exist anymore.").send();
+        return Ok(see_other("/admin/subscribers"));
+    }
+
+    FlashMessage::info("The tags have been saved.").send();
+    Ok(see_other(&subscriber_url(subscriber_id)))
+}
+
+// POST /admin/subscribers/{subscriber_id}/delete
+// Their confirmation tokens go along with them (ON DELETE CASCADE)
+#[tracing::instrument(name = "Delete a subscriber", skip(pool))]
+#[post("/subscribers/{subscriber_id}/delete")]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<uuid::Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+
+    let email = sqlx::query!(
+        r#"DELETE FROM subscriptions WHERE id = $1 RETURNING email"#,
+        sqlx::types::Uuid::from_bytes(subscriber_id.into_inner().into_bytes())
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to delete the subscriber")
+    .map_err(e500)?
+    .map(|r| r.email);
+
+    let email = match email {
+        Some(email) => email,
+        None => {
+            FlashMessage::error("The subscriber does not exist anymore.").send();
+            return Ok(see_other("/admin/subscribers"));
+        }
+    };
+
+    // The delivery queue only knows subscribers by their email
+    sqlx::query!(
+        r#"DELETE FROM issue_delivery_queue WHERE subscriber_email = $1"#,
+        email
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to remove pending deliveries of the subscriber")
+    .map_err(e500)?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the deletion")
+        .map_err(e500)?;
+
+    FlashMessage::info(format!("{} has been deleted.", email)).send();
+    Ok(see_other("/admin/subscribers"))
+}
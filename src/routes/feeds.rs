@@ -0,0 +1,270 @@
+use actix_web::http::header::{
+    self, EntityTag, HttpDate, IfModifiedSince, IfNoneMatch, CACHE_CONTROL,
+};
+use actix_web::{get, web, HttpMessage, HttpRequest, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::time::{Duration, SystemTime};
+
+use crate::domain::{MergeTemplate, MergeValues};
+use crate::utils::{app_base_url, e500};
+
+const FEED_TITLE: &str = "Newsletter";
+const FEED_LENGTH: i64 = 20;
+
+struct FeedState {
+    n_issues: i64,
+    last_modified_at: Option<DateTime<Utc>>,
+}
+
+struct FeedItem {
+    newsletter_issue_id: sqlx::types::Uuid,
+    slug: Option<String>,
+    title: String,
+    html: String,
+    published_at: DateTime<Utc>,
+}
+
+// GET /feed.xml
+// RSS 2.0 feed of the issues shown in the public archive
+#[tracing::instrument(name = "RSS feed", skip_all)]
+#[get("/feed.xml")]
+pub async fn rss_feed(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<String>,
+    app_port: web::Data<u16>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let base_url = app_base_url(&base_url, **app_port);
+    feed_response(&request, &pool, "application/rss+xml", |items, state| {
+        get_rss_xml(&base_url, items, state)
+    })
+    .await
+}
+
+// GET /atom.xml
+// Same content as `/feed.xml`, for readers that prefer Atom
+#[tracing::instrument(name = "Atom feed", skip_all)]
+#[get("/atom.xml")]
+pub async fn atom_feed(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<String>,
+    app_port: web::Data<u16>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let base_url = app_base_url(&base_url, **app_port);
+    feed_response(&request, &pool, "application/atom+xml", |items, state| {
+        get_atom_xml(&base_url, items, state)
+    })
+    .await
+}
+
+// Feed readers poll a lot, a conditional request is answered from a
+// cheap aggregate and the issues themselves are only fetched when the
+// feed has changed since the reader's last visit.
+async fn feed_response(
+    request: &HttpRequest,
+    pool: &PgPool,
+    content_type: &str,
+    render: impl FnOnce(Vec<FeedItem>, &FeedState) -> String,
+) -> Result<HttpResponse, actix_web::Error> {
+    let state = get_feed_state(pool).await.map_err(e500)?;
+    let etag = EntityTag::new_strong(format!(
+        "{}-{}",
+        state.n_issues,
+        state
+            .last_modified_at
+            .map(|t| t.timestamp_micros())
+            .unwrap_or_default()
+    ));
+    let last_modified = state.last_modified_at.map(http_date);
+
+    if is_not_modified(request, &etag, last_modified) {
+        let mut response = HttpResponse::NotModified();
+        response.insert_header(header::ETag(etag));
+        if let Some(last_modified) = last_modified {
+            response.insert_header(header::LastModified(last_modified));
+        }
+        return Ok(response.finish());
+    }
+
+    let items = get_feed_items(pool).await.map_err(e500)?;
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type(format!("{}; charset=utf-8", content_type))
+        .insert_header((CACHE_CONTROL, "public, max-age=300"))
+        .insert_header(header::ETag(etag));
+    if let Some(last_modified) = last_modified {
+        response.insert_header(header::LastModified(last_modified));
+    }
+    Ok(response.body(render(items, &state)))
+}
+
+// `If-None-Match` wins over `If-Modified-Since` when both are sent (RFC 9110)
+fn is_not_modified(
+    request: &HttpRequest,
+    etag: &EntityTag,
+    last_modified: Option<HttpDate>,
+) -> bool {
+    if let Some(if_none_match) = request.get_header::<IfNoneMatch>() {
+        return match if_none_match {
+            IfNoneMatch::Any => true,
+            IfNoneMatch::Items(tags) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        };
+    }
+
+    match (request.get_header::<IfModifiedSince>(), last_modified) {
+        (Some(IfModifiedSince(since)), Some(last_modified)) => last_modified <= since,
+        _ => false,
+    }
+}
+
+// HTTP dates have a one second resolution
+fn http_date(t: DateTime<Utc>) -> HttpDate {
+    let seconds = Duration::from_secs(t.timestamp().max(0) as u64);
+    HttpDate::from(SystemTime::UNIX_EPOCH + seconds)
+}
+
+// Hidden issues count towards the last modification as well, hiding
+// one is bumping its `updated_at` and changes the feed
+#[tracing::instrument(skip_all)]
+async fn get_feed_state(pool: &PgPool) -> Result<FeedState, anyhow::Error> {
+    let state = sqlx::query_as!(
+        FeedState,
+        r#"
+        SELECT
+            count(*) FILTER (WHERE show_in_archive) as "n_issues!",
+            max(GREATEST(published_at, updated_at)) as last_modified_at
+        FROM newsletter_issue
+        WHERE published_at IS NOT NULL
+    "#
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to fetch the state of the feed")?;
+
+    Ok(state)
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_feed_items(pool: &PgPool) -> Result<Vec<FeedItem>, anyhow::Error> {
+    let items = sqlx::query_as!(
+        FeedItem,
+        r#"
+        SELECT newsletter_issue_id, slug, title, html, published_at as "published_at!"
+        FROM newsletter_issue
+        WHERE published_at IS NOT NULL AND show_in_archive
+        ORDER BY published_at DESC
+        LIMIT $1
+    "#,
+        FEED_LENGTH
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch the feed items")?;
+
+    Ok(items)
+}
+
+fn issue_url(base_url: &str, item: &FeedItem) -> String {
+    match &item.slug {
+        Some(slug) => format!("{}/issues/{}", base_url, slug),
+        None => format!("{}/issues/{}", base_url, item.newsletter_issue_id),
+    }
+}
+
+// Feed readers are anonymous, merge tags use their fallback as in the archive
+fn issue_html(item: &FeedItem) -> String {
+    MergeTemplate::parse_or_verbatim(&item.html).render_html(&MergeValues::default())
+}
+
+// `encode_minimal` only produces entities that XML knows about, so the
+// html of an issue ends up as escaped text, as both formats expect
+fn get_rss_xml(base_url: &str, items: Vec<FeedItem>, state: &FeedState) -> String {
+    let mut items_str = String::new();
+    for item in items {
+        items_str.push_str(&format!(
+            r#"
+        <item>
+            <title>{}</title>
+            <link>{}</link>
+            <guid isPermaLink="false">urn:uuid:{}</guid>
+            <pubDate>{}</pubDate>
+            <description>{}</description>
+        </item>"#,
+            encode_minimal(&item.title),
+            encode_minimal(&issue_url(base_url, &item)),
+            item.newsletter_issue_id,
+            item.published_at.to_rfc2822(),
+            encode_minimal(&issue_html(&item))
+        ));
+    }
+    let last_build_date = state
+        .last_modified_at
+        .map(|t| {
+            format!(
+                "\n        <lastBuildDate>{}</lastBuildDate>",
+                t.to_rfc2822()
+            )
+        })
+        .unwrap_or_default();
+    let base_url = encode_minimal(base_url);
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+    <channel>
+        <title>{FEED_TITLE}</title>
+        <link>{base_url}/issues</link>
+        <description>Past issues of the newsletter</description>
+        <atom:link href="{base_url}/feed.xml" rel="self" type="application/rss+xml"/>{last_build_date}{items_str}
+    </channel>
+</rss>
+"#
+    )
+}
+
+fn get_atom_xml(base_url: &str, items: Vec<FeedItem>, state: &FeedState) -> String {
+    let mut entries = String::new();
+    for item in items {
+        let published_at = item.published_at.to_rfc3339();
+        entries.push_str(&format!(
+            r#"
+    <entry>
+        <title>{}</title>
+        <link href="{}"/>
+        <id>urn:uuid:{}</id>
+        <published>{published_at}</published>
+        <updated>{published_at}</updated>
+        <content type="html">{}</content>
+    </entry>"#,
+            encode_minimal(&item.title),
+            encode_minimal(&issue_url(base_url, &item)),
+            item.newsletter_issue_id,
+            encode_minimal(&issue_html(&item))
+        ));
+    }
+    // `updated` is mandatory, an empty feed falls back to the epoch
+    let updated = state
+        .last_modified_at
+        .unwrap_or(DateTime::<Utc>::UNIX_EPOCH)
+        .to_rfc3339();
+    let base_url = encode_minimal(base_url);
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+    <title>{FEED_TITLE}</title>
+    <id>{base_url}/atom.xml</id>
+    <link href="{base_url}/issues"/>
+    <link href="{base_url}/atom.xml" rel="self"/>
+    <updated>{updated}</updated>
+    <author>
+        <name>{FEED_TITLE}</name>
+    </author>{entries}
+</feed>
+"#
+    )
+}
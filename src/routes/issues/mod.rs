@@ -0,0 +1,168 @@
+use actix_web::{get, http::header::ContentType, web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+
+use crate::domain::{MergeTemplate, MergeValues};
+use crate::utils::{e500, page_number};
+
+const ISSUES_PER_PAGE: i64 = 20;
+
+struct ArchivedIssue {
+    newsletter_issue_id: sqlx::types::Uuid,
+    slug: Option<String>,
+    title: String,
+    published_at: DateTime<Utc>,
+}
+
+struct Issue {
+    title: String,
+    html: String,
+    published_at: DateTime<Utc>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ArchiveParameters {
+    page: Option<i64>,
+}
+
+// GET /issues
+// Published issues an admin allowed in the archive, most recent first
+#[tracing::instrument(name = "Public archive", skip(params, pool))]
+#[get("/issues")]
+pub async fn archive(
+    params: web::Query<ArchiveParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let page = page_number(params.page);
+
+    let mut issues = get_archived_issues(&pool, page).await.map_err(e500)?;
+    // One more issue than displayed is fetched to know if there is a next page
+    let has_next_page = issues.len() as i64 > ISSUES_PER_PAGE;
+    issues.truncate(ISSUES_PER_PAGE as usize);
+
+    let mut content = String::from("<h1>Archive</h1>");
+    if issues.is_empty() {
+        content.push_str("<p>No issue has been published yet.</p>");
+    } else {
+        content.push_str("<ul>");
+        for issue in issues {
+            let path = match &issue.slug {
+                Some(slug) => encode_minimal(slug),
+                None => issue.newsletter_issue_id.to_string(),
+            };
+            content.push_str(&format!(
+                r#"<li><a href="/issues/{}">{}</a> - {}</li>"#,
+                path,
+                encode_minimal(&issue.title),
+                issue.published_at.format("%B %-d, %Y")
+            ));
+        }
+        content.push_str("</ul>");
+    }
+
+    content.push_str("<p>");
+    if page > 1 {
+        content.push_str(&format!(
+            r#"<a href="/issues?page={}">&lt;- Newer issues</a> "#,
+            page - 1
+        ));
+    }
+    if has_next_page {
+        content.push_str(&format!(
+            r#"<a href="/issues?page={}">Older issues -&gt;</a>"#,
+            page + 1
+        ));
+    }
+    content.push_str("</p>");
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(render("Archive", &content)))
+}
+
+// GET /issues/{slug_or_id}
+#[tracing::instrument(name = "Public issue", skip(pool))]
+#[get("/issues/{slug_or_id}")]
+pub async fn archived_issue(
+    slug_or_id: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue = match get_archived_issue(&pool, &slug_or_id).await.map_err(e500)? {
+        Some(issue) => issue,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let content = format!(
+        "<h1>{}</h1><p><small>{}</small></p>{}",
+        encode_minimal(&issue.title),
+        issue.published_at.format("%B %-d, %Y"),
+        // Readers of the archive are anonymous, merge tags use their fallback
+        MergeTemplate::parse_or_verbatim(&issue.html).render_html(&MergeValues::default())
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(render(&issue.title, &content)))
+}
+
+// `content` goes last, the stored html of an issue is not scanned for placeholders
+fn render(title: &str, content: &str) -> String {
+    include_str!("template.html")
+        .replace("{{ title }}", &encode_minimal(title))
+        .replace("{{ content }}", content)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_archived_issues(
+    pool: &PgPool,
+    page: i64,
+) -> Result<Vec<ArchivedIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+        SELECT newsletter_issue_id, slug, title, published_at as "published_at!"
+        FROM newsletter_issue
+        WHERE published_at IS NOT NULL AND show_in_archive
+        ORDER BY published_at DESC
+        LIMIT $1 OFFSET $2
+    "#,
+        ISSUES_PER_PAGE + 1,
+        (page - 1) * ISSUES_PER_PAGE
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch the archived issues")?;
+
+    Ok(issues)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_archived_issue(
+    pool: &PgPool,
+    slug_or_id: &str,
+) -> Result<Option<Issue>, anyhow::Error> {
+    let newsletter_issue_id = slug_or_id
+        .parse::<uuid::Uuid>()
+        .ok()
+        .map(|id| sqlx::types::Uuid::from_bytes(id.into_bytes()));
+
+    let issue = sqlx::query_as!(
+        Issue,
+        r#"
+        SELECT title, html, published_at as "published_at!"
+        FROM newsletter_issue
+        WHERE
+            (newsletter_issue_id = $1 OR slug = $2) AND
+            published_at IS NOT NULL AND
+            show_in_archive
+    "#,
+        newsletter_issue_id,
+        slug_or_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch the archived issue")?;
+
+    Ok(issue)
+}
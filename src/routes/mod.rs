@@ -1,13 +1,23 @@
 pub mod admin;
+mod feeds;
 pub mod home;
+mod issues;
 pub mod login;
 mod subscription_error;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_data;
+mod subscriptions_unsubscribe;
+mod tracking;
 
 pub use admin::*;
+pub use feeds::*;
 pub use home::*;
+pub use issues::*;
 pub use login::*;
 pub use subscription_error::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_data::*;
+pub use subscriptions_unsubscribe::*;
+pub use tracking::*;
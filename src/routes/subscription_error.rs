@@ -7,7 +7,7 @@ pub enum SubscribeError {
     InsertSubscriberError(sqlx::Error),
     TransactionCommitError(sqlx::Error),
     StoreTokenError(StoreTokenError),
-    SendEmailError(reqwest::Error),
+    SendEmailError(anyhow::Error),
 }
 
 impl std::fmt::Debug for SubscribeError {
@@ -43,7 +43,7 @@ impl std::error::Error for SubscribeError {
             Self::InsertSubscriberError(e) => Some(e),
             Self::TransactionCommitError(e) => Some(e),
             Self::StoreTokenError(e) => Some(e),
-            Self::SendEmailError(e) => Some(e),
+            Self::SendEmailError(e) => Some(e.as_ref()),
         }
     }
 }
@@ -63,8 +63,8 @@ impl From<StoreTokenError> for SubscribeError {
     }
 }
 
-impl From<reqwest::Error> for SubscribeError {
-    fn from(value: reqwest::Error) -> Self {
+impl From<anyhow::Error> for SubscribeError {
+    fn from(value: anyhow::Error) -> Self {
         Self::SendEmailError(value)
     }
 }
@@ -1,13 +1,19 @@
 use actix_web::http::header::ContentType;
 use actix_web::{get, post, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
 use chrono::Utc;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use sqlx::types::Uuid;
 use sqlx::PgPool;
+use tracing::Instrument;
 
-use crate::utils::see_other;
-use crate::{domain::NewSubscriber, email_client::EmailClient};
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberTag};
+use crate::email_client::{EmailMessage, EmailTransport};
+use crate::email_layouts::{apply_layout, get_default_layout};
+use crate::mailing_lists::{get_list_id, get_lists, join_list, MailingList, DEFAULT_LIST_ID};
+use crate::segments::add_tags;
+use crate::utils::{app_base_url, e500, see_other};
 
 use super::SubscribeError;
 
@@ -15,6 +21,12 @@ use super::SubscribeError;
 pub struct FormData {
     name: String,
     email: String,
+    // Slug of the list to join, the default list when left empty
+    #[serde(default)]
+    list: String,
+    // Comma separated, usually a hidden field of the form
+    #[serde(default)]
+    tags: String,
 }
 
 impl TryFrom<FormData> for NewSubscriber {
@@ -27,6 +39,13 @@ impl TryFrom<FormData> for NewSubscriber {
     }
 }
 
+#[derive(serde::Deserialize)]
+pub struct SubscribePageParameters {
+    list: Option<String>,
+    // Carried by a hidden field of the form
+    tags: Option<String>,
+}
+
 // GET /health_check
 // Health Check is an basic endpoint for check the status of the server
 #[get("/health_check")]
@@ -41,22 +60,65 @@ pub async fn health_check() -> HttpResponse {
     skip(form, db_pool, email_client),
     fields(
         subscriber_email = %form.email,
-        subscriber_name= %form.name
+        subscriber_name= %form.name,
+        list = %form.list,
+        tags = %form.tags
     )
 )]
 #[post("/subscription")]
 pub async fn subscribe(
     form: web::Form<FormData>,
     db_pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
+    email_client: web::Data<dyn EmailTransport>,
     base_url: web::Data<String>,
     app_port: web::Data<u16>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into()?;
+    let mut form = form.into_inner();
+    let list = std::mem::take(&mut form.list);
+    let tags = SubscriberTag::parse_list(&form.tags)?;
+    let new_subscriber: NewSubscriber = form.try_into()?;
+    let list_id = if list.is_empty() {
+        DEFAULT_LIST_ID
+    } else {
+        get_list_id(&db_pool, &list)
+            .await
+            .map_err(SubscribeError::PoolError)?
+            .ok_or_else(|| SubscribeError::ValidationError(format!("{} is not a list", list)))?
+    };
 
     let mut transition = db_pool.begin().await.map_err(SubscribeError::PoolError)?;
 
-    let subscriber_id = insert_subscriber(&mut transition, &new_subscriber)
+    let subscriber_id = match insert_subscriber(&mut transition, &new_subscriber)
+        .await
+        .map_err(SubscribeError::InsertSubscriberError)?
+    {
+        Some(subscriber_id) => Some(subscriber_id),
+        None => resubscribe(&mut transition, &new_subscriber)
+            .await
+            .map_err(SubscribeError::InsertSubscriberError)?,
+    };
+    // A confirmed subscriber only has to confirm the lists they join
+    let (subscriber_id, is_address_pending) = match subscriber_id {
+        Some(subscriber_id) => (subscriber_id, true),
+        None => (
+            get_subscriber_id(&mut transition, &new_subscriber.email)
+                .await
+                .map_err(SubscribeError::InsertSubscriberError)?,
+            false,
+        ),
+    };
+    let is_membership_pending = join_list(&mut transition, subscriber_id, list_id)
+        .await
+        .map_err(SubscribeError::InsertSubscriberError)?;
+    if !is_address_pending && !is_membership_pending {
+        // Same response as for a new subscriber, the form must not tell
+        // who is subscribed to the newsletter
+        subscribed_message().send();
+        return Ok(see_other("/subscription"));
+    }
+
+    // A confirmed member signing up again is left as is, tags included
+    add_tags(&mut transition, subscriber_id, &tags)
         .await
         .map_err(SubscribeError::InsertSubscriberError)?;
 
@@ -68,19 +130,41 @@ pub async fn subscribe(
         .await
         .map_err(SubscribeError::TransactionCommitError)?;
 
-    send_confirmation_email(
-        &email_client,
-        new_subscriber,
-        &base_url,
-        &app_port,
-        &subscription_token,
-    )
-    .await?;
+    // Sent in the background, a confirmed subscriber gets no email and
+    // waiting on the email provider would tell who is subscribed
+    let pool = db_pool.into_inner();
+    let email_client = email_client.into_inner();
+    tokio::spawn(
+        async move {
+            if let Err(e) = send_confirmation_email(
+                &pool,
+                email_client.as_ref(),
+                &new_subscriber.email,
+                &base_url,
+                &app_port,
+                &subscription_token,
+            )
+            .await
+            {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to send the confirmation email"
+                );
+            }
+        }
+        .in_current_span(),
+    );
 
     // Redirect to GET /subscription for showing the html page
+    subscribed_message().send();
     Ok(see_other("/subscription"))
 }
 
+pub(super) fn subscribed_message() -> FlashMessage {
+    FlashMessage::info("Thank you, please check your email for a confirmation link.")
+}
+
 #[tracing::instrument(
     name = "Store the subscription token in the database",
     skip(transition, subscriber_id, subscription_token)
@@ -148,55 +232,82 @@ impl actix_web::ResponseError for StoreTokenError {}
 
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_sub, base_url, token)
+    skip(pool, email_client, recipient, base_url, token)
 )]
 pub async fn send_confirmation_email(
-    email_client: &web::Data<EmailClient>,
-    new_sub: NewSubscriber,
+    pool: &PgPool,
+    email_client: &dyn EmailTransport,
+    recipient: &SubscriberEmail,
     base_url: &web::Data<String>,
     app_port: &web::Data<u16>,
     token: &str,
-) -> Result<(), reqwest::Error> {
-    let base_host_url = if base_url.contains("127.0.0.1") || base_url.contains("localhost") {
-        format!("{}:{}", base_url.as_str(), app_port.as_ref())
-    } else {
-        base_url.to_string()
-    };
+) -> Result<(), anyhow::Error> {
+    let base_host_url = app_base_url(base_url.as_str(), *app_port.get_ref());
+    let layout = get_default_layout(pool).await?;
+    let message = apply_layout(
+        layout.as_ref(),
+        confirmation_email(recipient.clone(), &base_host_url, token),
+    );
+
+    email_client
+        .send_email(
+            &message.recipient,
+            &message.subject,
+            &message.html_content,
+            &message.text_content,
+            &message.headers,
+        )
+        .await?;
 
+    Ok(())
+}
+
+// `base_host_url` as returned by `app_base_url`
+pub fn confirmation_email(
+    recipient: SubscriberEmail,
+    base_host_url: &str,
+    token: &str,
+) -> EmailMessage {
     let confirmation_link = format!(
         "{}/subscription/confirm?subscription_token={}",
         base_host_url, token
     );
-    let text_body = &format!(
+    let text_content = format!(
         "Welcome to my Newsletter!\nVisit {} to confirm your subscription",
         confirmation_link
     );
 
-    let html_body = &format!(
+    let html_content = format!(
         "Welcome to my Newsletter!<br />\
             Click <a href=\"{}\">here</a> to confirm your subscription.",
         confirmation_link
     );
 
-    email_client
-        .send_email(&new_sub.email, "Welcome", html_body, text_body)
-        .await
+    EmailMessage {
+        recipient,
+        subject: "Welcome".into(),
+        html_content,
+        text_content,
+        headers: vec![],
+    }
 }
 
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
     skip(new_subscriber, transition)
 )]
+// Returns `None` when the email is already known
 pub async fn insert_subscriber(
     transition: &mut sqlx::Transaction<'_, sqlx::Postgres>,
     new_subscriber: &NewSubscriber,
-) -> Result<Uuid, sqlx::Error> {
+) -> Result<Option<Uuid>, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
 
-    sqlx::query!(
+    let n_inserted = sqlx::query!(
         r#"
     INSERT INTO subscriptions (id, email, name, subscription_at, status)
     VALUES ($1, $2, $3, $4, 'pending_confirmation')
+    ON CONFLICT (email) DO NOTHING
     "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
@@ -208,11 +319,70 @@ pub async fn insert_subscriber(
     .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
         e
-    })?;
+    })?
+    .rows_affected();
+
+    Ok((n_inserted == 1).then_some(subscriber_id))
+}
+
+// Someone signing up again with a known email: a pending subscriber gets a
+// fresh confirmation link and an unsubscribed one goes through the double
+// opt-in again. Returns `None` for confirmed subscribers, nothing to do.
+// The name is kept, anyone can post the form with someone else's address.
+#[tracing::instrument(
+    name = "Resubscribe a known subscriber",
+    skip(new_subscriber, transition)
+)]
+pub async fn resubscribe(
+    transition: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    new_subscriber: &NewSubscriber,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let subscriber_id = sqlx::query!(
+        r#"
+    UPDATE subscriptions
+    SET
+        subscription_at = CASE WHEN status = 'unsubscribed' THEN $2 ELSE subscription_at END,
+        status = 'pending_confirmation'
+    WHERE email = $1 AND status != 'confirmed'
+    RETURNING id
+    "#,
+        new_subscriber.email.as_ref(),
+        Utc::now()
+    )
+    .fetch_optional(&mut *transition)
+    .await?
+    .map(|r| r.id);
+
+    // Only the link of the latest confirmation email works
+    if let Some(subscriber_id) = subscriber_id {
+        sqlx::query!(
+            r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+            subscriber_id
+        )
+        .execute(transition)
+        .await?;
+    }
+
+    Ok(subscriber_id)
+}
+
+#[tracing::instrument(name = "Get the id of a known subscriber", skip(transition))]
+async fn get_subscriber_id(
+    transition: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    email: &SubscriberEmail,
+) -> Result<Uuid, sqlx::Error> {
+    let subscriber_id = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE email = $1"#,
+        email.as_ref()
+    )
+    .fetch_one(transition)
+    .await?
+    .id;
+
     Ok(subscriber_id)
 }
 
-fn generate_subscription_token() -> String {
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
 
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
@@ -221,11 +391,63 @@ fn generate_subscription_token() -> String {
         .collect()
 }
 
+// GET /subscription?list=...&tags=...
+// The subscribe form, `list` preselects the list to join and `tags` are
+// given to the subscriber. After a
+// subscription the form comes back with a message above it.
 #[get("/subscription")]
-pub async fn subscribe_page() -> HttpResponse {
-    let html = include_str!("subscribe_page.html");
+pub async fn subscribe_page(
+    params: web::Query<SubscribePageParameters>,
+    flash_message: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut messages = String::new();
+    for m in flash_message.iter() {
+        messages.push_str(&format!(
+            "<p>{}</p>\n",
+            htmlescape::encode_minimal(m.content())
+        ));
+    }
+    let lists = get_lists(&db_pool).await.map_err(e500)?;
+    let content = format!(
+        "<h1>Subscribe to the newsletter</h1>\n{}{}",
+        messages,
+        subscribe_form_html(&lists, params.list.as_deref(), params.tags.as_deref())
+    );
 
-    HttpResponse::Ok()
+    let html = include_str!("subscribe_page.html").replace("{{ content }}", &content);
+    Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(html)
+        .body(html))
+}
+
+fn subscribe_form_html(
+    lists: &[MailingList],
+    selected: Option<&str>,
+    tags: Option<&str>,
+) -> String {
+    let mut options = String::new();
+    for list in lists {
+        options.push_str(&format!(
+            r#"<option value="{}"{}>{}</option>"#,
+            htmlescape::encode_minimal(&list.slug),
+            if selected == Some(list.slug.as_str()) {
+                " selected"
+            } else {
+                ""
+            },
+            htmlescape::encode_minimal(&list.name)
+        ));
+    }
+    let tags = htmlescape::encode_attribute(tags.unwrap_or_default());
+
+    format!(
+        r#"<form action="/subscription" method="post">
+        <input type="text" name="name" placeholder="Name" required>
+        <input type="email" name="email" placeholder="Email" required>
+        <select name="list">{options}</select>
+        <input type="hidden" name="tags" value="{tags}">
+        <button type="submit">Subscribe</button>
+      </form>"#
+    )
 }
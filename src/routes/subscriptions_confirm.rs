@@ -1,26 +1,59 @@
-use actix_web::{get, http::header::ContentType, web, HttpResponse};
+use actix_web::{get, http::header::ContentType, post, web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
 
+use crate::configuration::SubscriptionSettings;
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailTransport;
+use crate::mailing_lists::confirm_pending_memberships;
+use crate::utils::{e500, see_other};
+
+use super::{
+    generate_subscription_token, send_confirmation_email, store_token, subscribed_message,
+};
+
 #[derive(serde::Deserialize)]
 pub struct Parameters {
     subscription_token: String,
 }
 
-#[tracing::instrument(name = "Confirming a pending subscriber", skip(params))]
+pub enum ConfirmationToken {
+    Valid(sqlx::types::Uuid),
+    Expired,
+    Consumed,
+}
+
+#[tracing::instrument(name = "Confirming a pending subscriber", skip(params, settings))]
 #[get("/subscription/confirm")]
-pub async fn confirm(params: web::Query<Parameters>, db_pool: web::Data<PgPool>) -> HttpResponse {
+pub async fn confirm(
+    params: web::Query<Parameters>,
+    db_pool: web::Data<PgPool>,
+    settings: web::Data<SubscriptionSettings>,
+) -> HttpResponse {
     let token = params.0.subscription_token;
 
-    let id = match get_subscriber_id_from_token(&db_pool, token).await {
+    let id = match get_subscriber_id_from_token(&db_pool, &token, settings.confirmation_token_ttl())
+        .await
+    {
         Ok(id) => id,
         Err(_) => return HttpResponse::InternalServerError().finish(),
     };
 
     match id {
         None => HttpResponse::Unauthorized().finish(),
-        Some(id) => {
-            if confirm_subscriber(&db_pool, id).await.is_err() {
-                return HttpResponse::InternalServerError().finish();
+        Some(ConfirmationToken::Expired) => HttpResponse::Gone()
+            .content_type(ContentType::html())
+            .body(get_expired_link_html(&token)),
+        Some(ConfirmationToken::Consumed) => HttpResponse::Gone()
+            .content_type(ContentType::html())
+            .body(get_used_link_html()),
+        Some(ConfirmationToken::Valid(id)) => {
+            match confirm_subscriber(&db_pool, id, &token).await {
+                Ok(true) => {}
+                // Unsubscribed by an admin while their link was pending
+                Ok(false) => return HttpResponse::Unauthorized().finish(),
+                Err(_) => return HttpResponse::InternalServerError().finish(),
             }
             let html = include_str!("subscribe_confirm.html");
             HttpResponse::Ok()
@@ -30,13 +63,97 @@ pub async fn confirm(params: web::Query<Parameters>, db_pool: web::Data<PgPool>)
     }
 }
 
+// POST /subscription/resend
+// Offered by the page of an expired confirmation link. The expired token
+// identifies the subscriber, so nobody can learn whether an email is subscribed.
+#[tracing::instrument(
+    name = "Resending a confirmation email",
+    skip(form, db_pool, email_client, base_url)
+)]
+#[post("/subscription/resend")]
+pub async fn resend_confirmation(
+    form: web::Form<Parameters>,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailTransport>,
+    base_url: web::Data<String>,
+    app_port: web::Data<u16>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT subscriptions.id, subscriptions.email, subscriptions.status
+        FROM subscription_tokens
+        JOIN subscriptions ON subscriptions.id = subscription_tokens.subscriber_id
+        WHERE subscription_token = $1
+    "#,
+        form.subscription_token
+    )
+    .fetch_optional(db_pool.get_ref())
+    .await
+    .context("Failed to fetch the subscriber of the token")
+    .map_err(e500)?;
+
+    let subscriber = match subscriber {
+        Some(subscriber) => subscriber,
+        // Replaced by a newer link in the meantime
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+    if subscriber.status != "pending_confirmation" {
+        subscribed_message().send();
+        return Ok(see_other("/subscription"));
+    }
+    let email = SubscriberEmail::parse(subscriber.email).map_err(e500)?;
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    // Only the link of the latest confirmation email works
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber.id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the previous tokens")
+    .map_err(e500)?;
+    let subscription_token = generate_subscription_token();
+    store_token(&mut transaction, subscriber.id, &subscription_token)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the new token")
+        .map_err(e500)?;
+
+    send_confirmation_email(
+        &db_pool,
+        email_client.get_ref(),
+        &email,
+        &base_url,
+        &app_port,
+        &subscription_token,
+    )
+    .await
+    .map_err(e500)?;
+
+    subscribed_message().send();
+    Ok(see_other("/subscription"))
+}
+
 #[tracing::instrument(name = "Get Subscriber ID from database", skip(pool, token))]
 pub async fn get_subscriber_id_from_token(
     pool: &PgPool,
-    token: String,
-) -> Result<Option<sqlx::types::Uuid>, sqlx::Error> {
+    token: &str,
+    ttl: chrono::Duration,
+) -> Result<Option<ConfirmationToken>, sqlx::Error> {
     let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
+        r#"
+        SELECT subscriber_id, created_at, consumed_at
+        FROM subscription_tokens
+        WHERE subscription_token = $1
+    "#,
         token
     )
     .fetch_optional(pool)
@@ -46,21 +163,128 @@ pub async fn get_subscriber_id_from_token(
         e
     })?;
 
-    Ok(result.map(|r| r.subscriber_id))
+    Ok(result.map(|r| {
+        if r.consumed_at.is_some() {
+            ConfirmationToken::Consumed
+        } else if is_expired(r.created_at, ttl) {
+            ConfirmationToken::Expired
+        } else {
+            ConfirmationToken::Valid(r.subscriber_id)
+        }
+    }))
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed")]
-pub async fn confirm_subscriber(pool: &PgPool, id: sqlx::types::Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' where  id = $1 "#,
+fn is_expired(created_at: DateTime<Utc>, ttl: chrono::Duration) -> bool {
+    created_at + ttl < Utc::now()
+}
+
+#[tracing::instrument(name = "Mark subscriber as confirmed", skip(pool, token))]
+pub async fn confirm_subscriber(
+    pool: &PgPool,
+    id: sqlx::types::Uuid,
+    token: &str,
+) -> Result<bool, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+
+    // Confirmed subscribers joining a list confirm the membership only. An
+    // unsubscribed one left since the link was sent, it no longer works.
+    let n_confirmed = sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'confirmed'
+        WHERE id = $1 AND status IN ('pending_confirmation', 'confirmed')
+    "#,
         id
     )
-    .execute(pool)
+    .execute(&mut transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?
+    .rows_affected();
+    if n_confirmed == 0 {
+        return Ok(false);
+    }
+
+    sqlx::query!(
+        r#"UPDATE subscription_tokens SET consumed_at = now() WHERE subscription_token = $1"#,
+        token
+    )
+    .execute(&mut transaction)
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
         e
     })?;
 
-    Ok(())
+    confirm_pending_memberships(&mut transaction, id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+
+    transaction.commit().await?;
+    Ok(true)
+}
+
+fn get_expired_link_html(token: &str) -> String {
+    let form = format!(
+        r#"<form action="/subscription/resend" method="post">
+                <input hidden type="text" name="subscription_token" value="{}">
+                <button type="submit">Send me a new link</button>
+            </form>"#,
+        htmlescape::encode_minimal(token)
+    );
+    get_link_error_html(
+        "This confirmation link has expired.",
+        "We can send you a new one, it is only valid for a limited time too.",
+        &form,
+    )
+}
+
+fn get_used_link_html() -> String {
+    get_link_error_html(
+        "This confirmation link has already been used.",
+        "Each link can only confirm a subscription once.",
+        "",
+    )
+}
+
+fn get_link_error_html(title: &str, message: &str, form: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+  <head>
+    <meta charset="UTF-8" />
+    <meta name="viewport" content="width=device-width, initial-scale=1.0" />
+    <title>Subscribe Newsletter</title>
+    <style>
+      * {{
+        font-family: monospace;
+      }}
+    </style>
+  </head>
+  <body>
+    <center>
+      <h1>{title}</h1>
+      <p>{message}</p>
+      {form}
+    </center>
+  </body>
+</html>"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_expired;
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn a_token_is_valid_until_its_ttl() {
+        let ttl = Duration::hours(48);
+        assert!(!is_expired(Utc::now() - Duration::hours(47), ttl));
+        assert!(is_expired(Utc::now() - Duration::hours(49), ttl));
+    }
 }
@@ -0,0 +1,552 @@
+use actix_web::http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType};
+use actix_web::{get, post, web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use tracing::Instrument;
+
+use crate::domain::{DataRequestToken, SubscriberEmail};
+use crate::email_client::{EmailMessage, EmailTransport};
+use crate::email_layouts::{apply_layout, get_default_layout};
+use crate::startup::HmacSecret;
+use crate::subscriber_import::erase_email_from_reports;
+use crate::utils::{app_base_url, e400, e500};
+
+// How long the link sent by email gives access to the data
+const DATA_REQUEST_LINK_TTL_HOUR: i64 = 24;
+
+#[derive(serde::Deserialize)]
+pub struct DataRequestFormData {
+    email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DataRequestParameters {
+    subscriber_id: uuid::Uuid,
+    expires_at: i64,
+    token: String,
+}
+
+impl DataRequestParameters {
+    fn is_valid(&self, hmac_secret: &HmacSecret) -> bool {
+        DataRequestToken::verify(
+            &self.token,
+            self.subscriber_id,
+            self.expires_at,
+            &hmac_secret.0,
+        )
+        .is_ok()
+    }
+
+    fn query_string(&self) -> String {
+        format!(
+            "subscriber_id={}&expires_at={}&token={}",
+            self.subscriber_id, self.expires_at, self.token
+        )
+    }
+}
+
+// GET /subscription/data
+// Where subscribers ask for a copy of their data or for its erasure
+#[get("/subscription/data")]
+pub async fn data_request_form() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(get_page_html(
+            "Your data",
+            r#"<p>Enter the address you subscribed with, we will send it a link
+                to download or erase everything we store about you.</p>
+                <form action="/subscription/data" method="post">
+                    <input type="email" name="email" placeholder="Email" required>
+                    <button type="submit">Send me the link</button>
+                </form>"#,
+        ))
+}
+
+// POST /subscription/data
+// Only the owner of the address receives the link, and the answer is the
+// same whether the address is known or not, in content and in timing.
+#[tracing::instrument(
+    name = "Send a data request link",
+    skip(form, pool, email_client, base_url, hmac_secret)
+)]
+#[post("/subscription/data")]
+pub async fn request_data_link(
+    form: web::Form<DataRequestFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailTransport>,
+    base_url: web::Data<String>,
+    app_port: web::Data<u16>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = SubscriberEmail::parse(form.0.email).map_err(e400)?;
+
+    let subscriber_id = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE email = $1"#,
+        email.as_ref()
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to look up the subscriber")
+    .map_err(e500)?
+    .map(|r| uuid::Uuid::from_bytes(*r.id.as_bytes()));
+
+    if let Some(subscriber_id) = subscriber_id {
+        let expires_at = Utc::now().timestamp() + DATA_REQUEST_LINK_TTL_HOUR * 60 * 60;
+        let params = DataRequestParameters {
+            subscriber_id,
+            expires_at,
+            token: DataRequestToken::generate(subscriber_id, expires_at, &hmac_secret.0)
+                .as_ref()
+                .to_owned(),
+        };
+        let link = format!(
+            "{}/subscription/data/manage?{}",
+            app_base_url(&base_url, **app_port),
+            params.query_string()
+        );
+
+        // Sent in the background, waiting on the email provider or failing
+        // with it would tell who is subscribed
+        let pool = pool.get_ref().clone();
+        let email_client = email_client.into_inner();
+        tokio::spawn(
+            async move {
+                if let Err(e) =
+                    send_data_request_link(&pool, email_client.as_ref(), email, &link).await
+                {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to send the data request link"
+                    );
+                }
+            }
+            .in_current_span(),
+        );
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(get_page_html(
+            "Check your email",
+            "<p>If this address is subscribed, it received a link to manage its data.</p>",
+        )))
+}
+
+#[tracing::instrument(skip(pool, email_client, link))]
+async fn send_data_request_link(
+    pool: &PgPool,
+    email_client: &dyn EmailTransport,
+    email: SubscriberEmail,
+    link: &str,
+) -> Result<(), anyhow::Error> {
+    let layout = get_default_layout(pool).await?;
+    let message = apply_layout(
+        layout.as_ref(),
+        EmailMessage {
+            recipient: email,
+            subject: "Your data".into(),
+            html_content: format!(
+                "Click <a href=\"{}\">here</a> to download or erase your data.<br />\
+                The link is valid for {} hours.",
+                encode_minimal(link),
+                DATA_REQUEST_LINK_TTL_HOUR
+            ),
+            text_content: format!(
+                "Visit {} to download or erase your data.\nThe link is valid for {} hours.",
+                link, DATA_REQUEST_LINK_TTL_HOUR
+            ),
+            headers: vec![],
+        },
+    );
+
+    email_client
+        .send_email(
+            &message.recipient,
+            &message.subject,
+            &message.html_content,
+            &message.text_content,
+            &message.headers,
+        )
+        .await
+        .context("Failed to send the data request link")?;
+    Ok(())
+}
+
+// GET /subscription/data/manage
+#[tracing::instrument(
+    name = "Data request page",
+    skip(params, hmac_secret),
+    fields(subscriber_id = %params.subscriber_id)
+)]
+#[get("/subscription/data/manage")]
+pub async fn manage_data(
+    params: web::Query<DataRequestParameters>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    if !params.is_valid(&hmac_secret) {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    let query = encode_minimal(&params.query_string());
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(get_page_html(
+            "Your data",
+            &format!(
+                r#"<p><a href="/subscription/data/export?{query}">Download everything we store about you</a> (JSON)</p>
+                <p>Erasing your data also unsubscribes you, for good.</p>
+                <form action="/subscription/data/erase?{query}" method="post">
+                    <button type="submit">Erase my data</button>
+                </form>"#
+            ),
+        ))
+}
+
+// GET /subscription/data/export
+// The subscription, its lists, its tags, its confirmation tokens and every delivery attempt
+#[tracing::instrument(
+    name = "Export the data of a subscriber",
+    skip(params, pool, hmac_secret),
+    fields(subscriber_id = %params.subscriber_id)
+)]
+#[get("/subscription/data/export")]
+pub async fn export_data(
+    params: web::Query<DataRequestParameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !params.is_valid(&hmac_secret) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let data = match get_subscriber_data(&pool, params.subscriber_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(data) => data,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("my-data.json".into())],
+        })
+        .body(serde_json::to_string_pretty(&data).map_err(e500)?))
+}
+
+// POST /subscription/data/erase
+#[tracing::instrument(
+    name = "Erase the data of a subscriber",
+    skip(params, pool, hmac_secret),
+    fields(subscriber_id = %params.subscriber_id)
+)]
+#[post("/subscription/data/erase")]
+pub async fn erase_data(
+    params: web::Query<DataRequestParameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !params.is_valid(&hmac_secret) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    erase_subscriber_data(&pool, params.subscriber_id)
+        .await
+        .map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(get_page_html(
+            "Your data has been erased.",
+            "<p>We do not store anything about you anymore.</p>",
+        )))
+}
+
+fn timestamp(t: DateTime<Utc>) -> String {
+    t.to_rfc3339()
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_subscriber_data(
+    pool: &PgPool,
+    subscriber_id: uuid::Uuid,
+) -> Result<Option<serde_json::Value>, anyhow::Error> {
+    let id = sqlx::types::Uuid::from_bytes(subscriber_id.into_bytes());
+
+    let subscription = match sqlx::query!(
+        r#"SELECT email, name, status, subscription_at FROM subscriptions WHERE id = $1"#,
+        id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch the subscription")?
+    {
+        Some(subscription) => subscription,
+        None => return Ok(None),
+    };
+
+    let tokens: Vec<_> = sqlx::query!(
+        r#"
+        SELECT subscription_token, created_at, consumed_at
+        FROM subscription_tokens
+        WHERE subscriber_id = $1
+        ORDER BY created_at
+    "#,
+        id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch the confirmation tokens")?
+    .into_iter()
+    .map(|t| {
+        serde_json::json!({
+            "token": t.subscription_token,
+            "created_at": timestamp(t.created_at),
+            "consumed_at": t.consumed_at.map(timestamp),
+        })
+    })
+    .collect();
+
+    let lists: Vec<_> = sqlx::query!(
+        r#"
+        SELECT lists.name, list_memberships.status, list_memberships.subscribed_at
+        FROM list_memberships
+        JOIN lists ON lists.list_id = list_memberships.list_id
+        WHERE subscriber_id = $1
+        ORDER BY list_memberships.subscribed_at
+    "#,
+        id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch the lists")?
+    .into_iter()
+    .map(|l| {
+        serde_json::json!({
+            "name": l.name,
+            "status": l.status,
+            "subscribed_at": timestamp(l.subscribed_at),
+        })
+    })
+    .collect();
+
+    let tags: Vec<String> = sqlx::query!(
+        r#"SELECT tag FROM subscriber_tags WHERE subscriber_id = $1 ORDER BY tag"#,
+        id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch the tags")?
+    .into_iter()
+    .map(|t| t.tag)
+    .collect();
+
+    let deliveries: Vec<_> = sqlx::query!(
+        r#"
+        SELECT
+            l.newsletter_issue_id, i.title, l.outcome,
+            l.provider_message_id, l.error, l.attempted_at
+        FROM issue_delivery_log l
+        JOIN newsletter_issue i ON i.newsletter_issue_id = l.newsletter_issue_id
+        WHERE l.subscriber_email = $1
+        ORDER BY l.attempted_at
+    "#,
+        subscription.email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch the delivery log")?
+    .into_iter()
+    .map(|d| {
+        serde_json::json!({
+            "newsletter_issue_id": d.newsletter_issue_id.to_string(),
+            "title": d.title,
+            "outcome": d.outcome,
+            "provider_message_id": d.provider_message_id,
+            "error": d.error,
+            "attempted_at": timestamp(d.attempted_at),
+        })
+    })
+    .collect();
+
+    let pending_deliveries: Vec<_> = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id, n_retries, execute_after
+        FROM issue_delivery_queue
+        WHERE subscriber_email = $1
+    "#,
+        subscription.email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch the pending deliveries")?
+    .into_iter()
+    .map(|d| {
+        serde_json::json!({
+            "newsletter_issue_id": d.newsletter_issue_id.to_string(),
+            "n_retries": d.n_retries,
+            "execute_after": timestamp(d.execute_after),
+        })
+    })
+    .collect();
+
+    let failed_deliveries: Vec<_> = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id, n_retries, last_error, failed_at
+        FROM issue_delivery_failures
+        WHERE subscriber_email = $1
+    "#,
+        subscription.email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch the failed deliveries")?
+    .into_iter()
+    .map(|d| {
+        serde_json::json!({
+            "newsletter_issue_id": d.newsletter_issue_id.to_string(),
+            "n_retries": d.n_retries,
+            "last_error": d.last_error,
+            "failed_at": timestamp(d.failed_at),
+        })
+    })
+    .collect();
+
+    let opens: Vec<_> = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id, opened_at
+        FROM issue_opens
+        WHERE subscriber_id = $1
+        ORDER BY opened_at
+    "#,
+        id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch the opens")?
+    .into_iter()
+    .map(|o| {
+        serde_json::json!({
+            "newsletter_issue_id": o.newsletter_issue_id.to_string(),
+            "opened_at": timestamp(o.opened_at),
+        })
+    })
+    .collect();
+
+    let clicks: Vec<_> = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id, url, clicked_at
+        FROM issue_clicks
+        WHERE subscriber_id = $1
+        ORDER BY clicked_at
+    "#,
+        id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch the clicks")?
+    .into_iter()
+    .map(|c| {
+        serde_json::json!({
+            "newsletter_issue_id": c.newsletter_issue_id.to_string(),
+            "url": c.url,
+            "clicked_at": timestamp(c.clicked_at),
+        })
+    })
+    .collect();
+
+    Ok(Some(serde_json::json!({
+        "subscription": {
+            "id": subscriber_id.to_string(),
+            "email": subscription.email,
+            "name": subscription.name,
+            "status": subscription.status,
+            "subscription_at": timestamp(subscription.subscription_at),
+        },
+        "lists": lists,
+        "tags": tags,
+        "confirmation_tokens": tokens,
+        "deliveries": deliveries,
+        "pending_deliveries": pending_deliveries,
+        "failed_deliveries": failed_deliveries,
+        "opens": opens,
+        "clicks": clicks,
+    })))
+}
+
+// The confirmation tokens, list memberships, opens and clicks go along with
+// the subscription (ON DELETE CASCADE), the delivery tables and the import
+// reports only know the subscriber by their email
+#[tracing::instrument(skip(pool))]
+pub async fn erase_subscriber_data(
+    pool: &PgPool,
+    subscriber_id: uuid::Uuid,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+
+    let email = sqlx::query!(
+        r#"DELETE FROM subscriptions WHERE id = $1 RETURNING email"#,
+        sqlx::types::Uuid::from_bytes(subscriber_id.into_bytes())
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to delete the subscription")?
+    .map(|r| r.email);
+
+    // Erasing twice is fine, there is nothing left the second time
+    if let Some(email) = email {
+        sqlx::query!(
+            r#"DELETE FROM issue_delivery_queue WHERE subscriber_email = $1"#,
+            email
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete the pending deliveries")?;
+        sqlx::query!(
+            r#"DELETE FROM issue_delivery_failures WHERE subscriber_email = $1"#,
+            email
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete the failed deliveries")?;
+        sqlx::query!(
+            r#"DELETE FROM issue_delivery_log WHERE subscriber_email = $1"#,
+            email
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete the delivery log")?;
+        erase_email_from_reports(&mut transaction, &email).await?;
+    }
+
+    transaction.commit().await?;
+    Ok(())
+}
+
+fn get_page_html(title: &str, content: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+    <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Your data</title>
+            <style>
+                * {{
+                    font-family: monospace;
+                }}
+            </style>
+        </head>
+        <body>
+            <center>
+                <h1>{title}</h1>
+                {content}
+            </center>
+        </body>
+    </html>"#
+    )
+}
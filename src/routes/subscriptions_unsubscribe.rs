@@ -0,0 +1,219 @@
+use actix_web::{get, http::header::ContentType, post, web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::domain::{SubscriberEmail, UnsubscribeToken};
+use crate::email_client::{EmailMessage, EmailTransport};
+use crate::email_layouts::{apply_layout, get_default_layout};
+use crate::mailing_lists::leave_all_lists;
+use crate::startup::HmacSecret;
+use crate::utils::{app_base_url, e500};
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    subscriber_id: uuid::Uuid,
+    token: String,
+}
+
+impl UnsubscribeParameters {
+    fn is_valid(&self, hmac_secret: &HmacSecret) -> bool {
+        UnsubscribeToken::verify(&self.token, self.subscriber_id, &hmac_secret.0).is_ok()
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeFormData {
+    // Only sent by the form of `unsubscribe_form`
+    interactive: bool,
+}
+
+// GET /subscription/unsubscribe
+// Ask the subscriber to confirm, so that link scanners following every
+// link inside an email do not unsubscribe people by accident.
+#[tracing::instrument(name = "Unsubscribe form", skip(params, hmac_secret))]
+#[get("/subscription/unsubscribe")]
+pub async fn unsubscribe_form(
+    params: web::Query<UnsubscribeParameters>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    if !params.is_valid(&hmac_secret) {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(get_unsubscribe_form_html(
+            &params.subscriber_id.to_string(),
+            &params.token,
+        ))
+}
+
+// POST /subscription/unsubscribe
+// Also the target of the RFC 8058 one-click unsubscribe: mail clients POST
+// `List-Unsubscribe=One-Click` here without any cookie, so the signed
+// query parameters are all that is needed. The goodbye email is only sent
+// to someone who went through the form, a mail client unsubscribing on
+// their behalf is not expected to trigger another email.
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip(params, form, pool, email_client, base_url, hmac_secret),
+    fields(subscriber_id = %params.subscriber_id)
+)]
+#[post("/subscription/unsubscribe")]
+pub async fn unsubscribe(
+    params: web::Query<UnsubscribeParameters>,
+    form: Option<web::Form<UnsubscribeFormData>>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailTransport>,
+    base_url: web::Data<String>,
+    app_port: web::Data<u16>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !params.is_valid(&hmac_secret) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let email = mark_subscriber_as_unsubscribed(&pool, params.subscriber_id)
+        .await
+        .map_err(e500)?;
+
+    // They are unsubscribed whether the goodbye email makes it or not
+    let is_interactive = form.map(|f| f.interactive).unwrap_or(false);
+    if let (Some(email), true) = (email, is_interactive) {
+        let base_host_url = app_base_url(&base_url, **app_port);
+        if let Err(e) =
+            send_goodbye_email(&pool, email_client.get_ref(), email, &base_host_url).await
+        {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send the goodbye email"
+            );
+        }
+    }
+
+    let html = include_str!("unsubscribe_confirm.html");
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html))
+}
+
+// Returns the address of the subscriber when they were confirmed until now
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(pool))]
+pub async fn mark_subscriber_as_unsubscribed(
+    pool: &PgPool,
+    subscriber_id: uuid::Uuid,
+) -> Result<Option<String>, anyhow::Error> {
+    let subscriber_id = sqlx::types::Uuid::from_bytes(subscriber_id.into_bytes());
+    let mut transaction = pool.begin().await?;
+
+    let subscriber = sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'unsubscribed'
+        FROM (SELECT id, status FROM subscriptions WHERE id = $1 FOR UPDATE) previous
+        WHERE subscriptions.id = previous.id
+        RETURNING subscriptions.email, previous.status as previous_status
+    "#,
+        subscriber_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to mark subscriber as unsubscribed")?;
+
+    let mut was_confirmed = None;
+    if let Some(subscriber) = subscriber {
+        let email = subscriber.email;
+        leave_all_lists(&mut transaction, subscriber_id)
+            .await
+            .context("Failed to remove the subscriber from their lists")?;
+
+        // Issues that are still being delivered must not reach them either
+        sqlx::query!(
+            r#"DELETE FROM issue_delivery_queue WHERE subscriber_email = $1"#,
+            email
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to remove pending deliveries of the subscriber")?;
+
+        was_confirmed = (subscriber.previous_status == "confirmed").then_some(email);
+    }
+
+    transaction.commit().await?;
+    Ok(was_confirmed)
+}
+
+#[tracing::instrument(skip_all)]
+async fn send_goodbye_email(
+    pool: &PgPool,
+    email_client: &dyn EmailTransport,
+    email: String,
+    base_host_url: &str,
+) -> Result<(), anyhow::Error> {
+    let recipient = SubscriberEmail::parse(email).map_err(anyhow::Error::msg)?;
+    let subscribe_link = format!("{}/subscription", base_host_url);
+    let layout = get_default_layout(pool).await?;
+    let message = apply_layout(
+        layout.as_ref(),
+        EmailMessage {
+            recipient,
+            subject: "Goodbye".into(),
+            html_content: format!(
+                "You have been unsubscribed, no new issue will reach you.<br />\
+                Changed your mind? <a href=\"{}\">Subscribe again</a>.",
+                subscribe_link
+            ),
+            text_content: format!(
+                "You have been unsubscribed, no new issue will reach you.\n\
+                Changed your mind? Subscribe again at {}",
+                subscribe_link
+            ),
+            headers: vec![],
+        },
+    );
+
+    email_client
+        .send_email(
+            &message.recipient,
+            &message.subject,
+            &message.html_content,
+            &message.text_content,
+            &message.headers,
+        )
+        .await?;
+
+    Ok(())
+}
+
+fn get_unsubscribe_form_html(subscriber_id: &str, token: &str) -> String {
+    let subscriber_id = htmlescape::encode_attribute(subscriber_id);
+    let token = htmlescape::encode_attribute(token);
+
+    format!(
+        r#"<!DOCTYPE html>
+    <html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Unsubscribe</title>
+            <style>
+                * {{
+                    font-family: monospace;
+                }}
+            </style>
+        </head>
+        <body>
+            <center>
+                <h1>Unsubscribe from the newsletter?</h1>
+                <p>You will not receive any new issue after this.</p>
+                <form
+                    action="/subscription/unsubscribe?subscriber_id={subscriber_id}&amp;token={token}"
+                    method="post"
+                >
+                    <input type="hidden" name="interactive" value="true">
+                    <button type="submit">Unsubscribe</button>
+                </form>
+            </center>
+        </body>
+    </html>"#
+    )
+}
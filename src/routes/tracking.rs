@@ -0,0 +1,106 @@
+use actix_web::http::header::{CacheControl, CacheDirective, LOCATION};
+use actix_web::{get, web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::domain::{TrackedEvent, TrackingToken};
+use crate::startup::HmacSecret;
+
+// A transparent 1x1 GIF
+const PIXEL: [u8; 43] = [
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+// GET /t/o/{token}.gif
+// The pixel at the bottom of the issues that track engagement. Mail clients
+// loading it record an open; those blocking images are not counted.
+#[tracing::instrument(name = "Record an issue open", skip_all)]
+#[get("/t/o/{token}.gif")]
+pub async fn track_open(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    let token = match TrackingToken::verify(&token, &hmac_secret.0) {
+        Ok(token) if token.event == TrackedEvent::Open => token,
+        _ => return HttpResponse::NotFound().finish(),
+    };
+
+    // The subscriber sees the image whether the open is stored or not
+    if let Err(e) = store_open(&pool, &token).await {
+        tracing::error!(error.cause_chain = ?e, error.message = %e, "Failed to record an open");
+    }
+
+    HttpResponse::Ok()
+        .content_type("image/gif")
+        // Every open reaches us, to count the total ones
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(PIXEL.to_vec())
+}
+
+// GET /t/c/{token}
+// The links of the issues that track engagement lead here, the click is
+// recorded before redirecting to the link signed in the token
+#[tracing::instrument(name = "Record a link click", skip_all)]
+#[get("/t/c/{token}")]
+pub async fn track_click(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    let token = match TrackingToken::verify(&token, &hmac_secret.0) {
+        Ok(token) => token,
+        Err(_) => return HttpResponse::NotFound().finish(),
+    };
+    let url = match &token.event {
+        TrackedEvent::Click(url) => url,
+        TrackedEvent::Open => return HttpResponse::NotFound().finish(),
+    };
+
+    // A broken link would be worse than a missed click
+    if let Err(e) = store_click(&pool, &token, url).await {
+        tracing::error!(error.cause_chain = ?e, error.message = %e, "Failed to record a click");
+    }
+
+    HttpResponse::Found()
+        .insert_header((LOCATION, url.as_str()))
+        .finish()
+}
+
+// Subscribers who erased their data in the meantime are not recorded
+#[tracing::instrument(skip_all)]
+async fn store_open(pool: &PgPool, token: &TrackingToken) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_opens (newsletter_issue_id, subscriber_id, opened_at)
+        SELECT $1, id, now() FROM subscriptions WHERE id = $2
+    "#,
+        sqlx::types::Uuid::from_bytes(token.newsletter_issue_id.into_bytes()),
+        sqlx::types::Uuid::from_bytes(token.subscriber_id.into_bytes())
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the open")?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip(pool, token))]
+async fn store_click(pool: &PgPool, token: &TrackingToken, url: &str) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_clicks (newsletter_issue_id, subscriber_id, url, clicked_at)
+        SELECT $1, id, $3, now() FROM subscriptions WHERE id = $2
+    "#,
+        sqlx::types::Uuid::from_bytes(token.newsletter_issue_id.into_bytes()),
+        sqlx::types::Uuid::from_bytes(token.subscriber_id.into_bytes()),
+        url
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the click")?;
+
+    Ok(())
+}
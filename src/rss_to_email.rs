@@ -0,0 +1,333 @@
+use anyhow::Context;
+use feed_rs::model::Entry;
+use htmlescape::{decode_html, encode_minimal};
+use reqwest::Url;
+use sqlx::PgPool;
+
+use crate::{
+    configuration::{RssToEmailMode, RssToEmailSettings, Settings},
+    domain::IssueContent,
+    email_html::{is_clipped_by_gmail, prepare_issue_html},
+    issue_delivery_workers::enqueue_delivery_task,
+    routes::{insert_newsletter_issue, IssueAudience, IssuePublication},
+    startup::get_connection_pool,
+};
+
+pub async fn run_rss_to_email_until_stopped(configuration: Settings) {
+    // Returning would stop the whole application, see `main`
+    let settings = match configuration.rss_to_email {
+        Some(settings) => settings,
+        None => return std::future::pending().await,
+    };
+    let pool = get_connection_pool(&configuration.database);
+    let http_client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(30))
+        .build()
+        .expect("Failed to build the feed client");
+
+    loop {
+        if let Err(e) = poll_feed(&pool, &http_client, &settings).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to turn the new posts of the feed into newsletter issues"
+            );
+        }
+        tokio::time::sleep(settings.poll_interval()).await;
+    }
+}
+
+// A blog post, as it is going to be sent
+struct Post {
+    guid: String,
+    link: Option<String>,
+    title: String,
+    html: String,
+    text: String,
+}
+
+impl Post {
+    fn new(entry: Entry, feed_url: &str) -> Self {
+        let title = entry
+            .title
+            .map(|t| t.content)
+            .unwrap_or_else(|| "New post".into());
+        let link = entry
+            .links
+            .into_iter()
+            .next()
+            .and_then(|l| resolve_link(feed_url, &l.href));
+        // The full post when the feed has it, its summary otherwise
+        let body = match (entry.content.and_then(|c| c.body), entry.summary) {
+            (Some(body), _) => body,
+            (None, Some(summary)) if summary.content_type.subty() == "plain" => {
+                format!("<p>{}</p>", encode_minimal(&summary.content))
+            }
+            (None, Some(summary)) => summary.content,
+            (None, None) => String::new(),
+        };
+
+        let mut html = format!("<h1>{}</h1>{}", encode_minimal(&title), body);
+        let mut text = format!("{}\n\n{}", title, html_to_text(&body));
+        if let Some(link) = &link {
+            html.push_str(&format!(
+                r#"<p><a href="{}">Read it on the blog</a></p>"#,
+                encode_minimal(link)
+            ));
+            text.push_str(&format!("\n\nRead it on the blog: {}", link));
+        }
+
+        Self {
+            guid: entry.id,
+            link,
+            title,
+            html,
+            text,
+        }
+    }
+}
+
+// Links of a feed may be relative to the feed itself. One that cannot be
+// made absolute is dropped, the post is still sent.
+fn resolve_link(feed_url: &str, link: &str) -> Option<String> {
+    match Url::parse(feed_url).and_then(|base| base.join(link)) {
+        Ok(url) => Some(url.to_string()),
+        Err(e) => {
+            tracing::warn!(link, error = %e, "Ignored the invalid link of a post");
+            None
+        }
+    }
+}
+
+// Turn the new posts of the feed into issues, returns the number of created issues
+#[tracing::instrument(skip_all, fields(feed_url = %settings.feed_url), err)]
+pub async fn poll_feed(
+    pool: &PgPool,
+    http_client: &reqwest::Client,
+    settings: &RssToEmailSettings,
+) -> Result<usize, anyhow::Error> {
+    let body = http_client
+        .get(&settings.feed_url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .context("Failed to fetch the feed")?
+        .bytes()
+        .await
+        .context("Failed to read the feed")?;
+    let feed = feed_rs::parser::parse(body.as_ref()).context("Failed to parse the feed")?;
+
+    // Feeds list the most recent posts first, they are sent in publication order
+    let posts: Vec<Post> = feed
+        .entries
+        .into_iter()
+        .rev()
+        .map(|entry| Post::new(entry, &settings.feed_url))
+        .collect();
+
+    if record_first_poll(pool, &settings.feed_url, &posts).await? {
+        tracing::info!(
+            n_posts = posts.len(),
+            "Skipped the posts already in the feed"
+        );
+        return Ok(0);
+    }
+
+    let mut n_created = 0;
+    for post in &posts {
+        if create_issue(pool, settings, post).await? {
+            n_created += 1;
+        }
+    }
+    Ok(n_created)
+}
+
+// The posts published before the feed was configured are only marked as seen,
+// nobody wants to receive the whole history of the blog at once.
+// Returns `false` if the feed had already been polled.
+async fn record_first_poll(
+    pool: &PgPool,
+    feed_url: &str,
+    posts: &[Post],
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO rss_feeds (feed_url, first_polled_at)
+        VALUES ($1, now())
+        ON CONFLICT DO NOTHING
+    "#,
+        feed_url
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to register the feed")?
+    .rows_affected();
+    if n_inserted == 0 {
+        return Ok(false);
+    }
+
+    for post in posts {
+        sqlx::query!(
+            r#"
+            INSERT INTO rss_feed_items (feed_url, guid, seen_at)
+            VALUES ($1, $2, now())
+            ON CONFLICT DO NOTHING
+        "#,
+            feed_url,
+            post.guid
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to record a post of the feed")?;
+    }
+
+    transaction.commit().await?;
+    Ok(true)
+}
+
+// Returns `false` if the post had already been seen
+#[tracing::instrument(skip_all, fields(guid = %post.guid))]
+async fn create_issue(
+    pool: &PgPool,
+    settings: &RssToEmailSettings,
+    post: &Post,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+
+    // Also keeps two instances of the task from creating the same issue
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO rss_feed_items (feed_url, guid, seen_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT DO NOTHING
+    "#,
+        settings.feed_url,
+        post.guid
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to record the post")?
+    .rows_affected();
+    if n_inserted == 0 {
+        return Ok(false);
+    }
+
+    // The relative URLs of a post are relative to the post itself
+    let html = prepare_issue_html(
+        &post.html,
+        post.link.as_deref().unwrap_or(&settings.feed_url),
+    )?;
+    if is_clipped_by_gmail(&html) {
+        tracing::warn!(
+            guid = %post.guid,
+            "The issue created from the post is going to be clipped by Gmail"
+        );
+    }
+
+    let publication = match settings.mode {
+        RssToEmailMode::Draft => IssuePublication::Draft,
+        RssToEmailMode::Publish => IssuePublication::Now,
+    };
+    let newsletter_issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &post.title,
+        &IssueContent {
+            markdown: None,
+            text: post.text.clone(),
+            html,
+        },
+        publication,
+        settings.show_in_archive,
+        false,
+        IssueAudience::default(),
+        None,
+    )
+    .await
+    .context("Failed to store newsletter issue details")?;
+
+    sqlx::query!(
+        r#"
+        UPDATE rss_feed_items
+        SET newsletter_issue_id = $3
+        WHERE feed_url = $1 AND guid = $2
+    "#,
+        settings.feed_url,
+        post.guid,
+        sqlx::types::Uuid::from_bytes(newsletter_issue_id.into_bytes())
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to link the post to its issue")?;
+
+    if settings.mode == RssToEmailMode::Publish {
+        enqueue_delivery_task(&mut transaction, newsletter_issue_id)
+            .await
+            .context("failed to enqueue delivery details")?;
+    }
+
+    transaction.commit().await?;
+    tracing::info!(%newsletter_issue_id, "Created a newsletter issue from a new post");
+    Ok(true)
+}
+
+// Good enough for the plain text part of an email: tags are dropped, block
+// elements start a new line and entities are decoded
+fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut chars = html.chars();
+    while let Some(c) = chars.next() {
+        if c != '<' {
+            text.push(c);
+            continue;
+        }
+
+        let tag: String = chars.by_ref().take_while(|c| *c != '>').collect();
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if matches!(
+            name.as_str(),
+            "p" | "br" | "div" | "li" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6"
+        ) {
+            text.push('\n');
+        }
+    }
+
+    let text = decode_html(&text).unwrap_or(text);
+    let lines: Vec<&str> = text.lines().map(str::trim).collect();
+    lines
+        .split(|line| line.is_empty())
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| paragraph.join("\n"))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::html_to_text;
+
+    #[test]
+    fn tags_are_dropped_and_blocks_become_paragraphs() {
+        let text = html_to_text(r#"<p>Hello <b>there</b>,</p><p class="x">it's me</p>"#);
+        assert_eq!(text, "Hello there,\n\nit's me");
+    }
+
+    #[test]
+    fn line_breaks_are_kept() {
+        assert_eq!(html_to_text("one<br>two<br/>three"), "one\ntwo\nthree");
+    }
+
+    #[test]
+    fn entities_are_decoded() {
+        assert_eq!(
+            html_to_text("<p>Fish &amp; chips &lt;3</p>"),
+            "Fish & chips <3"
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+
+use crate::domain::SubscriberTag;
+
+pub struct Segment {
+    pub segment_id: uuid::Uuid,
+    pub name: String,
+}
+
+// Which subscribers belong to a segment, see the `segment_members` view.
+// Empty and `None` fields do not filter anything.
+#[derive(Debug, Default)]
+pub struct SegmentFilter {
+    // At least one of them
+    pub include_tags: Vec<SubscriberTag>,
+    // None of them
+    pub exclude_tags: Vec<SubscriberTag>,
+    pub status: Option<String>,
+    pub subscribed_from: Option<DateTime<Utc>>,
+    // Exclusive upper bound
+    pub subscribed_before: Option<DateTime<Utc>>,
+}
+
+fn tag_strings(tags: &[SubscriberTag]) -> Vec<String> {
+    tags.iter().map(|t| t.as_ref().to_owned()).collect()
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_segments(pool: &PgPool) -> Result<Vec<Segment>, anyhow::Error> {
+    let segments = sqlx::query!(r#"SELECT segment_id, name FROM segments ORDER BY name"#)
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch the segments")?
+        .into_iter()
+        .map(|r| Segment {
+            segment_id: uuid::Uuid::from_bytes(*r.segment_id.as_bytes()),
+            name: r.name,
+        })
+        .collect();
+
+    Ok(segments)
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn segment_exists(pool: &PgPool, segment_id: uuid::Uuid) -> Result<bool, anyhow::Error> {
+    let exists = sqlx::query!(
+        r#"SELECT EXISTS (SELECT 1 FROM segments WHERE segment_id = $1) as "exists!""#,
+        sqlx::types::Uuid::from_bytes(segment_id.into_bytes())
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to look up the segment")?
+    .exists;
+
+    Ok(exists)
+}
+
+// Returns `None` when the name is already taken
+#[tracing::instrument(skip(pool, filter))]
+pub async fn insert_segment(
+    pool: &PgPool,
+    name: &str,
+    filter: &SegmentFilter,
+) -> Result<Option<uuid::Uuid>, anyhow::Error> {
+    let segment_id = uuid::Uuid::new_v4();
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO segments
+            (
+                segment_id, name, include_tags, exclude_tags, status,
+                subscribed_from, subscribed_before, created_at
+            )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, now())
+        ON CONFLICT (name) DO NOTHING
+    "#,
+        sqlx::types::Uuid::from_bytes(segment_id.into_bytes()),
+        name,
+        &tag_strings(&filter.include_tags),
+        &tag_strings(&filter.exclude_tags),
+        filter.status,
+        filter.subscribed_from,
+        filter.subscribed_before
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the segment")?
+    .rows_affected();
+
+    Ok((n_inserted == 1).then_some(segment_id))
+}
+
+// How many subscribers an issue sent now would be enqueued for, see
+// `enqueue_delivery_task`
+#[tracing::instrument(skip(pool))]
+pub async fn count_recipients(
+    pool: &PgPool,
+    list_id: uuid::Uuid,
+    segment_id: Option<uuid::Uuid>,
+) -> Result<i64, anyhow::Error> {
+    let n_recipients = sqlx::query!(
+        r#"
+        SELECT count(*) as "count!"
+        FROM list_memberships
+        JOIN subscriptions ON subscriptions.id = list_memberships.subscriber_id
+        WHERE
+            list_memberships.list_id = $1 AND
+            list_memberships.status = 'confirmed' AND
+            subscriptions.status = 'confirmed' AND
+            (
+                $2::uuid IS NULL OR
+                EXISTS (
+                    SELECT 1 FROM segment_members
+                    WHERE segment_id = $2 AND subscriber_id = subscriptions.id
+                )
+            )
+    "#,
+        sqlx::types::Uuid::from_bytes(list_id.into_bytes()),
+        segment_id.map(|id| sqlx::types::Uuid::from_bytes(id.into_bytes()))
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count the recipients")?
+    .count;
+
+    Ok(n_recipients)
+}
+
+// Tags the subscriber already has are left as they are
+#[tracing::instrument(skip(transaction, tags))]
+pub async fn add_tags(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: sqlx::types::Uuid,
+    tags: &[SubscriberTag],
+) -> Result<(), sqlx::Error> {
+    let tags = tag_strings(tags);
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag)
+        SELECT $1, tag FROM UNNEST($2::text[]) AS tags (tag)
+        ON CONFLICT DO NOTHING
+    "#,
+        subscriber_id,
+        &tags
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+// Replaces every tag of the subscriber. Returns `false` when they do not
+// exist anymore.
+#[tracing::instrument(skip(pool, tags))]
+pub async fn set_tags(
+    pool: &PgPool,
+    subscriber_id: uuid::Uuid,
+    tags: &[SubscriberTag],
+) -> Result<bool, anyhow::Error> {
+    let subscriber_id = sqlx::types::Uuid::from_bytes(subscriber_id.into_bytes());
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    // Keeps the subscriber from being deleted before their tags are added
+    let exists = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to look up the subscriber")?
+    .is_some();
+    if !exists {
+        return Ok(false);
+    }
+
+    sqlx::query!(
+        r#"DELETE FROM subscriber_tags WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to remove the tags of the subscriber")?;
+    add_tags(&mut transaction, subscriber_id, tags)
+        .await
+        .context("Failed to tag the subscriber")?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the tags")?;
+
+    Ok(true)
+}
@@ -1,9 +1,10 @@
 use crate::authentication::reject_anonymous_user;
-use crate::configuration::{DatabaseSettings, Settings};
-use crate::email_client::EmailClient;
-use crate::routes::{admin, home};
-use crate::routes::{confirm, health_check, subscribe};
-use crate::routes::{login, subscribe_page};
+use crate::configuration::{DatabaseSettings, Settings, SubscriptionSettings};
+use crate::email_client::EmailTransport;
+use crate::routes::{admin, archive, archived_issue, atom_feed, home, rss_feed};
+use crate::routes::{confirm, health_check, subscribe, unsubscribe, unsubscribe_form};
+use crate::routes::{data_request_form, erase_data, export_data, manage_data, request_data_link};
+use crate::routes::{login, resend_confirmation, subscribe_page, track_click, track_open};
 use actix_session::storage::RedisSessionStore;
 use actix_session::SessionMiddleware;
 use actix_web::cookie::Key;
@@ -16,6 +17,7 @@ use secrecy::{ExposeSecret, Secret};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::{PgPool, Pool, Postgres};
 use std::net::TcpListener;
+use std::sync::Arc;
 use tracing_actix_web::TracingLogger;
 
 pub struct Application {
@@ -26,23 +28,15 @@ pub struct Application {
 #[derive(Clone)]
 pub struct HmacSecret(pub Secret<String>);
 
+#[derive(Clone)]
+pub struct TestSubjectPrefix(pub String);
+
 impl Application {
     pub async fn build(configuration: Settings) -> Result<Self, anyhow::Error> {
         let connection_pool = get_connection_pool(&configuration.database);
 
-        let sender = configuration
-            .email_client
-            .sender()
-            .expect("Invalid sender email");
-
-        let timeout = configuration.email_client.timeout();
-        let email_client = EmailClient::new(
-            configuration.email_client.base_url,
-            sender,
-            configuration.email_client.authorization_token,
-            timeout,
-        )
-        .expect("Failed to parse email server url");
+        let email_client = configuration.email_client.client()?;
+        let test_subject_prefix = TestSubjectPrefix(configuration.email_client.test_subject_prefix);
 
         let address = format!(
             "{}:{}",
@@ -60,6 +54,8 @@ impl Application {
             configuration.application.port,
             HmacSecret(configuration.application.hmac_secret),
             configuration.redis_uri,
+            test_subject_prefix,
+            configuration.subscriptions,
         )
         .await?;
 
@@ -75,14 +71,17 @@ impl Application {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     listener: TcpListener,
     db_pool: PgPool,
-    email_client: EmailClient,
+    email_client: Arc<dyn EmailTransport>,
     base_url: String,
     app_port: u16,
     hmac_secret: HmacSecret,
     redis_uri: Secret<String>,
+    test_subject_prefix: TestSubjectPrefix,
+    subscription_settings: SubscriptionSettings,
 ) -> Result<Server, anyhow::Error> {
     let secret_key = Key::from(hmac_secret.0.expose_secret().as_bytes());
     let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
@@ -104,23 +103,81 @@ pub async fn run(
             .service(subscribe)
             .service(subscribe_page)
             .service(confirm)
+            .service(resend_confirmation)
+            .service(unsubscribe_form)
+            .service(unsubscribe)
+            .service(data_request_form)
+            .service(request_data_link)
+            .service(manage_data)
+            .service(export_data)
+            .service(erase_data)
             .service(home)
+            .service(archive)
+            .service(archived_issue)
+            .service(rss_feed)
+            .service(atom_feed)
+            .service(track_open)
+            .service(track_click)
             .service(login::login_form)
             .service(login::login)
             .service(
                 web::scope("/admin")
                     .wrap(from_fn(reject_anonymous_user)) // Auth middleware
+                    // Issues outgrow the 16 KB forms are limited to by default
+                    .app_data(web::FormConfig::default().limit(1024 * 1024))
                     .service(admin::admin_dashboard)
                     .service(admin::change_password_form)
                     .service(admin::change_password)
                     .service(admin::admin_logout)
                     .service(admin::issue_page)
-                    .service(admin::newsletter_issue),
+                    .service(admin::newsletter_issue)
+                    // Before `issue_report`, whose path would match `/newsletters/drafts`
+                    // and `/newsletters/recipients`
+                    .service(admin::issue_recipients)
+                    .service(admin::drafts_page)
+                    .service(admin::create_draft)
+                    .service(admin::edit_draft_page)
+                    .service(admin::update_draft)
+                    .service(admin::preview_draft)
+                    .service(admin::delete_draft)
+                    .service(admin::publish_draft)
+                    .service(admin::send_test_email)
+                    .service(admin::issue_report)
+                    .service(admin::set_issue_archive_visibility)
+                    .service(admin::reschedule_issue)
+                    .service(admin::cancel_scheduled_issue)
+                    .service(admin::lists_page)
+                    .service(admin::create_list)
+                    .service(admin::segments_page)
+                    .service(admin::create_segment)
+                    .service(admin::layouts_page)
+                    .service(admin::create_layout)
+                    // Before `update_layout`, whose path would match `/layouts/preview`
+                    .service(admin::preview_layout_form)
+                    .service(admin::edit_layout_page)
+                    .service(admin::update_layout)
+                    .service(admin::set_default_layout)
+                    .service(admin::delete_layout)
+                    .service(admin::preview_layout)
+                    .service(admin::subscribers_page)
+                    // Before `subscriber_page`, whose path would match `/subscribers/export`
+                    .service(admin::export_subscribers)
+                    .service(admin::subscriber_page)
+                    .service(admin::confirm_subscriber_manually)
+                    .service(admin::unsubscribe_subscriber_manually)
+                    .service(admin::tag_subscriber)
+                    .service(admin::delete_subscriber)
+                    .service(admin::import_subscribers_upload)
+                    .service(admin::subscriber_import_page)
+                    .service(admin::subscriber_import_errors),
             )
             .app_data(Data::new(db_pool.clone()))
-            .app_data(Data::new(email_client.clone()))
+            .app_data(Data::from(email_client.clone()))
             .app_data(Data::new(base_url.clone()))
             .app_data(Data::new(app_port))
+            .app_data(Data::new(hmac_secret.clone()))
+            .app_data(Data::new(test_subject_prefix.clone()))
+            .app_data(Data::new(subscription_settings.clone()))
     })
     .listen(listener)?
     .run();
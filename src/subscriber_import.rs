@@ -0,0 +1,472 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use anyhow::Context;
+use csv_async::{AsyncReaderBuilder, AsyncWriter, StringRecord, Trim};
+use sqlx::types::Uuid;
+use sqlx::{PgPool, Postgres, Transaction};
+use tokio::io::AsyncRead;
+
+use crate::configuration::Settings;
+use crate::domain::{SubscriberEmail, SubscriberName};
+use crate::email_client::EmailTransport;
+use crate::email_layouts::{apply_layout, get_default_layout};
+use crate::mailing_lists::DEFAULT_LIST_ID;
+use crate::routes::{confirmation_email, generate_subscription_token};
+use crate::startup::get_connection_pool;
+use crate::utils::app_base_url;
+
+// Rows upserted with a single query, also the size of the batches of
+// confirmation emails
+const BATCH_SIZE: usize = 500;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportMode {
+    // The list went through a double opt-in with the previous provider
+    Confirmed,
+    // New subscribers receive a confirmation email, as if they used the form
+    Pending,
+}
+
+impl ImportMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportMode::Confirmed => "confirmed",
+            ImportMode::Pending => "pending",
+        }
+    }
+
+    fn status(&self) -> &'static str {
+        match self {
+            ImportMode::Confirmed => "confirmed",
+            ImportMode::Pending => "pending_confirmation",
+        }
+    }
+}
+
+impl FromStr for ImportMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "confirmed" => Ok(ImportMode::Confirmed),
+            "pending" => Ok(ImportMode::Pending),
+            other => Err(format!(
+                "{} is not an import mode, use `confirmed` or `pending`",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ImportError {
+    // Nothing has been imported
+    #[error("{0}")]
+    InvalidFile(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+// A row that needs attention, `line` is the line of the CSV file
+#[derive(Debug)]
+pub struct RowError {
+    pub line: u64,
+    pub email: String,
+    pub error: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub n_rows: usize,
+    pub n_imported: usize,
+    // Rows that were not imported. `errors` can be longer, an imported
+    // subscriber whose confirmation email failed is listed there too.
+    pub n_failed: usize,
+    pub errors: Vec<RowError>,
+}
+
+impl ImportReport {
+    fn fail(&mut self, line: u64, email: &str, error: impl Into<String>) {
+        self.n_failed += 1;
+        self.warn(line, email, error);
+    }
+
+    fn warn(&mut self, line: u64, email: &str, error: impl Into<String>) {
+        self.errors.push(RowError {
+            line,
+            email: email.to_owned(),
+            error: error.into(),
+        });
+    }
+
+    // CSV with a `line`, `email` and `error` column
+    pub async fn error_report(&self) -> Result<String, anyhow::Error> {
+        let mut writer = AsyncWriter::from_writer(vec![]);
+        writer.write_record(&["line", "email", "error"]).await?;
+        for e in &self.errors {
+            writer
+                .write_record(&[e.line.to_string().as_str(), &e.email, &e.error])
+                .await?;
+        }
+        let report = writer
+            .into_inner()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to write the error report: {}", e))?;
+
+        Ok(String::from_utf8(report)?)
+    }
+}
+
+struct Row {
+    line: u64,
+    email: SubscriberEmail,
+    name: SubscriberName,
+}
+
+// Imports the subscribers of a CSV file with a header line, the `email`
+// and `name` columns are required and any other column is ignored.
+// The file is read as it comes and its rows are upserted one batch at a
+// time, but every email seen so far is kept to catch repeated rows.
+// Known emails get their name updated, confirmed when `mode` is
+// `Confirmed`, but someone who unsubscribed is never subscribed again.
+#[tracing::instrument(skip(pool, email_client, base_host_url, reader))]
+pub async fn import_subscribers<R>(
+    pool: &PgPool,
+    email_client: &dyn EmailTransport,
+    base_host_url: &str,
+    mode: ImportMode,
+    reader: R,
+) -> Result<ImportReport, ImportError>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    let mut reader = AsyncReaderBuilder::new()
+        .trim(Trim::All)
+        .flexible(true)
+        .create_reader(reader);
+
+    let headers = reader
+        .headers()
+        .await
+        .map_err(|e| ImportError::InvalidFile(format!("Failed to read the header line: {}", e)))?;
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h.eq_ignore_ascii_case(name))
+            .ok_or_else(|| ImportError::InvalidFile(format!("The file has no `{}` column.", name)))
+    };
+    let email_column = column("email")?;
+    let name_column = column("name")?;
+
+    let mut report = ImportReport::default();
+    let mut seen = HashSet::new();
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    let mut record = StringRecord::new();
+    loop {
+        match reader.read_record(&mut record).await {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) if e.is_io_error() => {
+                return Err(anyhow::Error::new(e)
+                    .context("Failed to read the file")
+                    .into())
+            }
+            Err(e) => {
+                report.n_rows += 1;
+                let line = e.position().map(|p| p.line()).unwrap_or_default();
+                report.fail(line, "", e.to_string());
+                continue;
+            }
+        }
+        report.n_rows += 1;
+
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        let email = record.get(email_column).unwrap_or_default().to_owned();
+        let name = record.get(name_column).unwrap_or_default().to_owned();
+        let row = match (
+            SubscriberEmail::parse(email.clone()),
+            SubscriberName::parse(name),
+        ) {
+            (Ok(email), Ok(name)) => Row { line, email, name },
+            (email_result, name_result) => {
+                let errors: Vec<String> = [email_result.err(), name_result.err()]
+                    .into_iter()
+                    .flatten()
+                    .collect();
+                report.fail(line, &email, errors.join(", "));
+                continue;
+            }
+        };
+        // A batch cannot update the same row twice. The emails of the whole
+        // file are kept, a later batch would count a repeated row twice.
+        if !seen.insert(row.email.as_ref().to_owned()) {
+            report.fail(line, &email, "Duplicate of an earlier row");
+            continue;
+        }
+
+        batch.push(row);
+        if batch.len() == BATCH_SIZE {
+            import_batch(pool, email_client, base_host_url, mode, &batch, &mut report).await?;
+            batch.clear();
+        }
+    }
+    import_batch(pool, email_client, base_host_url, mode, &batch, &mut report).await?;
+
+    report.errors.sort_by_key(|e| e.line);
+    tracing::info!(
+        n_rows = report.n_rows,
+        n_imported = report.n_imported,
+        n_failed = report.n_failed,
+        "Imported subscribers"
+    );
+    Ok(report)
+}
+
+// What `newsletter import-subscribers <file.csv> <confirmed|pending>` runs.
+// The summary goes to stderr and the error report to stdout, so that it
+// can be redirected to a file.
+pub async fn import_subscribers_from_file(
+    configuration: Settings,
+    path: &str,
+    mode: &str,
+) -> Result<(), anyhow::Error> {
+    let mode: ImportMode = mode.parse().map_err(anyhow::Error::msg)?;
+    let pool = get_connection_pool(&configuration.database);
+    let email_client = configuration.email_client.client()?;
+    let base_host_url = app_base_url(
+        &configuration.application.base_url,
+        configuration.application.port,
+    );
+    let file = tokio::fs::File::open(path)
+        .await
+        .with_context(|| format!("Failed to open {}", path))?;
+
+    let report = import_subscribers(&pool, email_client.as_ref(), &base_host_url, mode, file)
+        .await
+        .map_err(anyhow::Error::new)?;
+
+    eprintln!(
+        "{} rows, {} imported, {} failed",
+        report.n_rows, report.n_imported, report.n_failed
+    );
+    print!("{}", report.error_report().await?);
+    Ok(())
+}
+
+#[tracing::instrument(skip_all, fields(n_rows = batch.len()))]
+async fn import_batch(
+    pool: &PgPool,
+    email_client: &dyn EmailTransport,
+    base_host_url: &str,
+    mode: ImportMode,
+    batch: &[Row],
+    report: &mut ImportReport,
+) -> Result<(), anyhow::Error> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+    let ids: Vec<Uuid> = batch.iter().map(|_| Uuid::new_v4()).collect();
+    let emails: Vec<String> = batch.iter().map(|r| r.email.as_ref().to_owned()).collect();
+    let names: Vec<String> = batch.iter().map(|r| r.name.as_ref().to_owned()).collect();
+
+    let mut transaction = pool.begin().await?;
+    // `xmax` is only set for the rows that already existed
+    let upserted: HashMap<String, (Uuid, bool)> = sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscription_at, status)
+        SELECT id, email, name, now(), $4
+        FROM UNNEST($1::uuid[], $2::text[], $3::text[]) AS rows (id, email, name)
+        ON CONFLICT (email) DO UPDATE
+        SET
+            name = EXCLUDED.name,
+            status = CASE
+                WHEN EXCLUDED.status = 'confirmed' THEN 'confirmed'
+                ELSE subscriptions.status
+            END
+        WHERE subscriptions.status != 'unsubscribed'
+        RETURNING id, email, (xmax = 0) as "inserted!"
+    "#,
+        &ids,
+        &emails,
+        &names,
+        mode.status()
+    )
+    .fetch_all(&mut transaction)
+    .await
+    .context("Failed to upsert a batch of subscribers")?
+    .into_iter()
+    .map(|r| (r.email, (r.id, r.inserted)))
+    .collect();
+
+    // Imported subscribers join the default list, with the status of their address
+    let upserted_ids: Vec<Uuid> = upserted.values().map(|(id, _)| *id).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at)
+        SELECT $1, id, status, now()
+        FROM subscriptions
+        WHERE id = ANY($2)
+        ON CONFLICT (list_id, subscriber_id) DO UPDATE
+        SET status = EXCLUDED.status
+    "#,
+        Uuid::from_bytes(DEFAULT_LIST_ID.into_bytes()),
+        &upserted_ids
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to add the subscribers to the default list")?;
+
+    let mut to_confirm = vec![];
+    for row in batch {
+        match upserted.get(row.email.as_ref()) {
+            None => report.fail(
+                row.line,
+                row.email.as_ref(),
+                "Unsubscribed from the newsletter, left as is",
+            ),
+            Some((id, inserted)) => {
+                report.n_imported += 1;
+                if *inserted && mode == ImportMode::Pending {
+                    to_confirm.push((row, *id, generate_subscription_token()));
+                }
+            }
+        }
+    }
+
+    let (subscriber_ids, tokens): (Vec<Uuid>, Vec<String>) = to_confirm
+        .iter()
+        .map(|(_, id, token)| (*id, token.clone()))
+        .unzip();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_tokens (subscription_token, subscriber_id)
+        SELECT * FROM UNNEST($1::text[], $2::uuid[])
+    "#,
+        &tokens,
+        &subscriber_ids
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store the confirmation tokens")?;
+    transaction.commit().await?;
+
+    if to_confirm.is_empty() {
+        return Ok(());
+    }
+    let layout = get_default_layout(pool).await?;
+    let messages: Vec<_> = to_confirm
+        .iter()
+        .map(|(row, _, token)| {
+            let message = confirmation_email(row.email.clone(), base_host_url, token);
+            apply_layout(layout.as_ref(), message)
+        })
+        .collect();
+    let results = email_client.send_email_batch(&messages).await;
+    for ((row, _, _), result) in to_confirm.iter().zip(results) {
+        if let Err(e) = result {
+            report.warn(
+                row.line,
+                row.email.as_ref(),
+                format!("Imported, but the confirmation email was not sent: {}", e),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+// The error reports of past imports quote the addresses of the rows, a
+// subscriber erasing their data gets their address blanked in every report
+#[tracing::instrument(skip_all)]
+pub async fn erase_email_from_reports(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+) -> Result<(), anyhow::Error> {
+    let reports = sqlx::query!(
+        r#"
+        SELECT subscriber_import_id, error_report
+        FROM subscriber_imports
+        WHERE strpos(error_report, $1) > 0
+        FOR UPDATE
+    "#,
+        email
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("Failed to fetch the import reports")?;
+
+    for report in reports {
+        sqlx::query!(
+            r#"
+            UPDATE subscriber_imports SET error_report = $2
+            WHERE subscriber_import_id = $1
+        "#,
+            report.subscriber_import_id,
+            without_email(&report.error_report, email).await?
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to update an import report")?;
+    }
+    Ok(())
+}
+
+async fn without_email(error_report: &str, email: &str) -> Result<String, anyhow::Error> {
+    let mut reader = AsyncReaderBuilder::new().create_reader(error_report.as_bytes());
+    let mut writer = AsyncWriter::from_writer(vec![]);
+    writer.write_record(reader.headers().await?).await?;
+    let mut record = StringRecord::new();
+    while reader.read_record(&mut record).await? {
+        let line = record.get(0).unwrap_or_default();
+        let error = record.get(2).unwrap_or_default();
+        match record.get(1) {
+            Some(e) if e == email => writer.write_record(&[line, "", error]).await?,
+            _ => writer.write_record(&record).await?,
+        }
+    }
+    let report = writer
+        .into_inner()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to write the error report: {}", e))?;
+
+    Ok(String::from_utf8(report)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{without_email, ImportMode, ImportReport};
+
+    #[test]
+    fn import_modes_are_parsed() {
+        assert_eq!("confirmed".parse(), Ok(ImportMode::Confirmed));
+        assert_eq!("pending".parse(), Ok(ImportMode::Pending));
+        assert!("unsubscribed".parse::<ImportMode>().is_err());
+    }
+
+    #[tokio::test]
+    async fn the_error_report_is_quoted_csv() {
+        let mut report = ImportReport::default();
+        report.fail(3, "a@b.com", "Bad, really bad");
+        report.warn(5, "c@d.com", r#"Said "no""#);
+
+        assert_eq!(
+            report.error_report().await.unwrap(),
+            "line,email,error\n3,a@b.com,\"Bad, really bad\"\n5,c@d.com,\"Said \"\"no\"\"\"\n"
+        );
+        assert_eq!(report.n_failed, 1);
+    }
+
+    #[tokio::test]
+    async fn an_erased_email_is_blanked_in_the_error_report() {
+        let mut report = ImportReport::default();
+        report.fail(3, "a@b.com", "Duplicate of an earlier row");
+        report.fail(5, "c@d.com", "Bad, really bad");
+        let report = report.error_report().await.unwrap();
+
+        assert_eq!(
+            without_email(&report, "a@b.com").await.unwrap(),
+            "line,email,error\n3,,Duplicate of an earlier row\n5,c@d.com,\"Bad, really bad\"\n"
+        );
+    }
+}
@@ -0,0 +1,53 @@
+use chrono::Utc;
+use sqlx::PgPool;
+
+use crate::{configuration::Settings, startup::get_connection_pool};
+
+pub async fn run_subscription_cleanup_until_stopped(configuration: Settings) {
+    let pool = get_connection_pool(&configuration.database);
+    let retention = configuration.subscriptions.pending_subscriber_retention();
+
+    loop {
+        match delete_stale_pending_subscribers(&pool, retention).await {
+            Ok(n_deleted) if n_deleted > 0 => {
+                tracing::info!(n_deleted, "Deleted stale pending subscribers");
+            }
+            Ok(_) => {}
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to delete stale pending subscribers"
+                );
+            }
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(60 * 60)).await;
+    }
+}
+
+// People who never clicked any of their confirmation links. A subscriber
+// who asked for a new link recently is kept, whatever its signup date.
+// Their tokens go along with them (ON DELETE CASCADE).
+#[tracing::instrument(skip(pool))]
+pub async fn delete_stale_pending_subscribers(
+    pool: &PgPool,
+    retention: chrono::Duration,
+) -> Result<u64, sqlx::Error> {
+    let n_deleted = sqlx::query!(
+        r#"
+        DELETE FROM subscriptions
+        WHERE status = 'pending_confirmation'
+        AND subscription_at < $1
+        AND NOT EXISTS (
+            SELECT 1 FROM subscription_tokens
+            WHERE subscriber_id = subscriptions.id AND created_at > $1
+        )
+    "#,
+        Utc::now() - retention
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(n_deleted)
+}
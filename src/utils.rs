@@ -21,3 +21,20 @@ where
 {
     actix_web::error::ErrorBadRequest(e)
 }
+
+// While running locally the `base_url` does not carry the port the application
+// is listening on, so links that we put inside emails need it added back.
+pub fn app_base_url(base_url: &str, app_port: u16) -> String {
+    if base_url.contains("127.0.0.1") || base_url.contains("localhost") {
+        format!("{}:{}", base_url, app_port)
+    } else {
+        base_url.to_string()
+    }
+}
+
+// Listings take a 1-based `?page=`. It is clamped so that the offset and the
+// link to the next page cannot overflow, pages that far are empty anyway.
+pub fn page_number(page: Option<i64>) -> i64 {
+    const MAX_PAGE: i64 = 100_000;
+    page.unwrap_or(1).clamp(1, MAX_PAGE)
+}
@@ -0,0 +1,265 @@
+use crate::helpers::{assert_redirect_to, create_unconfirmed_subscriber, spawn_app, TestApp};
+use sqlx::types::Uuid;
+
+async fn insert_subscriber(app: &TestApp, email: &str, name: &str, status: &str) -> Uuid {
+    let id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscription_at, status)
+        VALUES ($1, $2, $3, now(), $4)
+    "#,
+        id,
+        email,
+        name,
+        status
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    id
+}
+
+async fn subscriber_status(app: &TestApp, id: Uuid) -> Option<String> {
+    sqlx::query!("SELECT status FROM subscriptions WHERE id = $1", id)
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap()
+        .map(|r| r.status)
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_subscribers() {
+    let app = spawn_app().await;
+    let id = insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed").await;
+
+    let response = app.get_subscribers("").await;
+    assert_redirect_to(&response, "/login");
+    let response = app.post_subscriber_action(id, "delete").await;
+    assert_redirect_to(&response, "/login");
+
+    assert_eq!(
+        subscriber_status(&app, id).await.as_deref(),
+        Some("confirmed")
+    );
+}
+
+#[tokio::test]
+async fn subscribers_can_be_searched() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed").await;
+    insert_subscriber(
+        &app,
+        "le.guin@example.com",
+        "Ursula K.",
+        "pending_confirmation",
+    )
+    .await;
+    insert_subscriber(&app, "octavia@example.com", "Octavia", "unsubscribed").await;
+
+    let html = app.get_subscribers("").await.text().await.unwrap();
+    assert!(html.contains("ursula@example.com"));
+    assert!(html.contains("le.guin@example.com"));
+    assert!(html.contains("octavia@example.com"));
+
+    let html = app.get_subscribers("q=URSULA").await.text().await.unwrap();
+    assert!(html.contains("ursula@example.com"));
+    assert!(html.contains("le.guin@example.com"));
+    assert!(!html.contains("octavia@example.com"));
+
+    let html = app
+        .get_subscribers("q=ursula&status=confirmed")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("ursula@example.com"));
+    assert!(!html.contains("le.guin@example.com"));
+
+    let html = app
+        .get_subscribers("subscribed_to=2000-01-01")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("No subscriber found."));
+
+    let response = app.get_subscribers("status=vip").await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn the_subscribers_are_paginated() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    for i in 0..51 {
+        insert_subscriber(
+            &app,
+            &format!("reader{:02}@example.com", i),
+            "Reader",
+            "confirmed",
+        )
+        .await;
+    }
+
+    let html = app.get_subscribers("q=reader").await.text().await.unwrap();
+    assert_eq!(html.matches("@example.com").count(), 50);
+    // The filters are kept from one page to the next
+    assert!(html.contains("page=2"));
+    assert!(html.contains("q=reader"));
+
+    let html = app
+        .get_subscribers("q=reader&page=2")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(html.matches("@example.com").count(), 1);
+    assert!(!html.contains("page=3"));
+}
+
+#[tokio::test]
+async fn a_huge_page_of_subscribers_is_empty() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    insert_subscriber(&app, "reader@example.com", "Reader", "confirmed").await;
+
+    let response = app.get_subscribers(&format!("page={}", i64::MAX)).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(!html.contains("reader@example.com"));
+}
+
+#[tokio::test]
+async fn the_detail_page_shows_the_subscriber() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let id = insert_subscriber(&app, "ursula@example.com", "<b>Ursula</b>", "confirmed").await;
+
+    let response = app.get_subscriber(id).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("ursula@example.com"));
+    assert!(html.contains("&lt;b&gt;Ursula&lt;/b&gt;"));
+    assert!(html.contains(&format!("/admin/subscribers/{}/unsubscribe", id)));
+    assert!(!html.contains(&format!("/admin/subscribers/{}/confirm", id)));
+
+    let response = app.get_subscriber(Uuid::new_v4()).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn a_pending_subscriber_can_be_confirmed_manually() {
+    let app = spawn_app().await;
+    let confirmation_link = create_unconfirmed_subscriber(&app).await;
+    let id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    app.test_user.login(&app).await;
+
+    let response = app.post_subscriber_action(id, "confirm").await;
+    assert_redirect_to(&response, &format!("/admin/subscribers/{}", id));
+    let html = app.get_subscriber(id).await.text().await.unwrap();
+    assert!(html.contains("The subscriber has been confirmed."));
+
+    assert_eq!(
+        subscriber_status(&app, id).await.as_deref(),
+        Some("confirmed")
+    );
+    // The link of the confirmation email has nothing left to do
+    let response = reqwest::get(confirmation_link.html_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 410);
+}
+
+#[tokio::test]
+async fn a_subscriber_can_be_unsubscribed_manually() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let id = insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed").await;
+
+    let response = app.post_subscriber_action(id, "unsubscribe").await;
+    assert_redirect_to(&response, &format!("/admin/subscribers/{}", id));
+
+    assert_eq!(
+        subscriber_status(&app, id).await.as_deref(),
+        Some("unsubscribed")
+    );
+}
+
+#[tokio::test]
+async fn a_pending_subscriber_unsubscribed_manually_cannot_confirm() {
+    let app = spawn_app().await;
+    let confirmation_link = create_unconfirmed_subscriber(&app).await;
+    let id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    app.test_user.login(&app).await;
+    app.post_subscriber_action(id, "unsubscribe").await;
+
+    let response = reqwest::get(confirmation_link.html_link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        subscriber_status(&app, id).await.as_deref(),
+        Some("unsubscribed")
+    );
+    let n_members =
+        sqlx::query!(r#"SELECT count(*) as "n!" FROM list_memberships WHERE status = 'confirmed'"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .n;
+    assert_eq!(n_members, 0);
+}
+
+#[tokio::test]
+async fn an_unsubscribed_subscriber_cannot_be_confirmed_manually() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let id = insert_subscriber(&app, "ursula@example.com", "Ursula", "unsubscribed").await;
+
+    let response = app.post_subscriber_action(id, "confirm").await;
+    assert_redirect_to(&response, &format!("/admin/subscribers/{}", id));
+    let html = app.get_subscriber(id).await.text().await.unwrap();
+    assert!(html.contains("Only a subscriber pending confirmation can be confirmed."));
+
+    assert_eq!(
+        subscriber_status(&app, id).await.as_deref(),
+        Some("unsubscribed")
+    );
+}
+
+#[tokio::test]
+async fn deleting_a_subscriber_deletes_their_tokens() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    app.test_user.login(&app).await;
+
+    let response = app.post_subscriber_action(id, "delete").await;
+    assert_redirect_to(&response, "/admin/subscribers");
+    let html = app.get_subscribers("").await.text().await.unwrap();
+    assert!(html.contains("has been deleted."));
+    assert!(html.contains("No subscriber found."));
+
+    assert_eq!(subscriber_status(&app, id).await, None);
+    let n_tokens = sqlx::query!(r#"SELECT count(*) as "count!" FROM subscription_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tokens, 0);
+
+    // Deleting it twice is not an error
+    let response = app.post_subscriber_action(id, "delete").await;
+    assert_redirect_to(&response, "/admin/subscribers");
+}
@@ -0,0 +1,270 @@
+use crate::helpers::{
+    assert_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber,
+    postmark_batch_accepted, spawn_app, TestApp,
+};
+use sqlx::types::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn layout(name: &str) -> serde_json::Value {
+    serde_json::json!({
+        "name": name,
+        "header": "Rust & friends",
+        "footer": "You subscribed on our website.",
+        "postal_address": "1 Main Street, Springfield",
+        "brand_color": "#1A5FB4",
+        "background_color": "#f4f4f4",
+    })
+}
+
+// Create a layout as the logged in admin, make it the default and return its id
+async fn create_default_layout(app: &TestApp) -> Uuid {
+    let response = app.post_layout(&layout("Default")).await;
+    assert_redirect_to(&response, "/admin/layouts");
+    let layout_id = sqlx::query!("SELECT layout_id FROM email_layouts WHERE name = 'Default'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .layout_id;
+
+    let response = app.post_default_layout(layout_id).await;
+    assert_redirect_to(&response, "/admin/layouts");
+
+    layout_id
+}
+
+fn last_email_body(requests: &[wiremock::Request]) -> serde_json::Value {
+    serde_json::from_slice(&requests.last().unwrap().body).unwrap()
+}
+
+#[tokio::test]
+async fn issues_are_wrapped_in_the_default_layout() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    create_default_layout(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(postmark_batch_accepted())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletter(&serde_json::json!({
+        "title": "Hello",
+        "text": "Hello text",
+        "html": "<p>Hello html</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.dispatch_all_emails().await;
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body = last_email_body(&requests);
+    let html = body[0]["HtmlBody"].as_str().unwrap();
+    assert!(html.contains(">Rust &amp; friends</td>"));
+    assert!(html.contains("<p>Hello html</p>"));
+    assert!(html.contains("border-top: 4px solid #1a5fb4;"));
+    assert!(html.contains("1 Main Street, Springfield"));
+    assert!(!html.contains("<hr />"));
+    let text = body[0]["TextBody"].as_str().unwrap();
+    assert!(text.starts_with("Rust & friends\n\nHello text\n\n--\n"));
+    assert!(text.contains("1 Main Street, Springfield"));
+
+    // The footer of the layout holds the unsubscribe link
+    app.get_unsubscribe_link(requests.last().unwrap());
+}
+
+#[tokio::test]
+async fn confirmation_emails_are_wrapped_in_the_default_layout() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_default_layout(&app).await;
+
+    create_unconfirmed_subscriber(&app).await;
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body = last_email_body(&requests);
+    let html = body["HtmlBody"].as_str().unwrap();
+    assert!(html.contains("Welcome to my Newsletter!"));
+    assert!(html.contains("1 Main Street, Springfield"));
+    // Nothing to unsubscribe from yet
+    assert!(!html.contains("Unsubscribe"));
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("Rust & friends\n\nWelcome to my Newsletter!"));
+}
+
+#[tokio::test]
+async fn unsubscribing_sends_a_goodbye_email() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(postmark_batch_accepted())
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletter(&serde_json::json!({
+        "title": "Hello",
+        "text": "Hello",
+        "html": "<p>Hello</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.dispatch_all_emails().await;
+    let requests = app.email_server.received_requests().await.unwrap();
+    let link = app.get_unsubscribe_link(requests.last().unwrap());
+    create_default_layout(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // What the form of the unsubscribe page posts
+    app.api_client
+        .post(link.clone())
+        .form(&serde_json::json!({ "interactive": true }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body = last_email_body(&requests);
+    assert_eq!(body["Subject"], "Goodbye");
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("1 Main Street, Springfield"));
+
+    // Only once, they already left
+    app.api_client
+        .post(link)
+        .form(&serde_json::json!({ "interactive": true }))
+        .send()
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn one_click_unsubscribes_send_no_goodbye_email() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(postmark_batch_accepted())
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletter(&serde_json::json!({
+        "title": "Hello",
+        "text": "Hello",
+        "html": "<p>Hello</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.dispatch_all_emails().await;
+    let requests = app.email_server.received_requests().await.unwrap();
+    let link = app.get_unsubscribe_link(requests.last().unwrap());
+
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    // RFC 8058, sent by the mail client on the subscriber's behalf
+    app.api_client
+        .post(link)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn only_one_layout_is_the_default() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let first = create_default_layout(&app).await;
+    app.post_layout(&layout("Other")).await;
+    let other = sqlx::query!("SELECT layout_id FROM email_layouts WHERE name = 'Other'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .layout_id;
+
+    app.post_default_layout(other).await;
+
+    let defaults: Vec<_> = sqlx::query!("SELECT layout_id FROM email_layouts WHERE is_default")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.layout_id)
+        .collect();
+    assert_eq!(defaults, vec![other]);
+    assert_ne!(first, other);
+}
+
+#[tokio::test]
+async fn layouts_need_a_postal_address_and_valid_colors() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    for (field, value, error) in [
+        ("postal_address", " ", "The layout needs a postal address."),
+        ("brand_color", "blue", "blue is not a valid color"),
+    ] {
+        let mut body = layout("Default");
+        body[field] = value.into();
+        let response = app.post_layout(&body).await;
+        assert_redirect_to(&response, "/admin/layouts");
+
+        let html = app.get_layouts().await.text().await.unwrap();
+        assert!(html.contains(error));
+    }
+
+    let n_layouts = sqlx::query!(r#"SELECT count(*) as "n!" FROM email_layouts"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_layouts, 0);
+}
+
+#[tokio::test]
+async fn layouts_are_previewed_before_being_saved() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/admin/layouts/preview", &app.address))
+        .form(&layout("Default"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Security-Policy"], "sandbox");
+    let html = response.text().await.unwrap();
+    assert!(html.contains(">Rust &amp; friends</td>"));
+    assert!(html.contains("This week in Rust"));
+
+    let n_layouts = sqlx::query!(r#"SELECT count(*) as "n!" FROM email_layouts"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_layouts, 0);
+}
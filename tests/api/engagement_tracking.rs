@@ -0,0 +1,170 @@
+use crate::helpers::{
+    assert_redirect_to, create_confirmed_subscriber, postmark_batch_accepted, spawn_app, TestApp,
+};
+use sqlx::types::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+const POST: &str = "https://example.com/post";
+
+// Publish an issue linking to `link` and return the HTML part it was
+// delivered with
+async fn deliver_issue(app: &TestApp, track_engagement: bool, link: &str) -> String {
+    create_confirmed_subscriber(app).await;
+    app.test_user.login(app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(postmark_batch_accepted())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let mut body = serde_json::json!({
+        "title": "Hello",
+        "text": format!("Read {}", link),
+        "html": format!(
+            r#"<p>Read <a href="{}">the post</a> or <a href="{{{{ unsubscribe_url }}}}">leave</a></p>"#,
+            link
+        ),
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    if track_engagement {
+        body["track_engagement"] = "on".into();
+    }
+    let response = app.post_newsletter(&body).await;
+    assert_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_emails().await;
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests.last().unwrap().body).unwrap();
+    body[0]["HtmlBody"].as_str().unwrap().to_owned()
+}
+
+// The first URL of the HTML part that contains `pattern`
+fn find_url(html: &str, pattern: &str) -> String {
+    html.split('"')
+        .find(|s| s.starts_with("http") && s.contains(pattern))
+        .unwrap()
+        .replace("&amp;", "&")
+}
+
+async fn issue_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+#[tokio::test]
+async fn opens_and_clicks_of_tracked_issues_are_reported() {
+    let mut app = spawn_app().await;
+    app.issue_delivery.track_engagement = true;
+    let html = deliver_issue(&app, true, POST).await;
+
+    assert!(!html.contains(r#"href="https://example.com/post""#));
+    // Leaving is not tracked
+    assert!(html.contains("/subscription/unsubscribe?"));
+
+    let pixel = reqwest::get(find_url(&html, "/t/o/")).await.unwrap();
+    assert_eq!(pixel.status().as_u16(), 200);
+    assert_eq!(pixel.headers()["Content-Type"], "image/gif");
+    reqwest::get(find_url(&html, "/t/o/")).await.unwrap();
+
+    let click = app
+        .api_client
+        .get(find_url(&html, "/t/c/"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(click.status().as_u16(), 302);
+    assert_eq!(click.headers()["Location"], "https://example.com/post");
+
+    let report = app
+        .get_newsletter_report(issue_id(&app).await)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(report.contains("Unique opens: 1 (100.0%)"));
+    assert!(report.contains("Total opens: 2 (200.0%)"));
+    assert!(report.contains("Unique clicks: 1 (100.0%)"));
+}
+
+#[tokio::test]
+async fn clicks_on_links_with_non_ascii_characters_are_redirected() {
+    let mut app = spawn_app().await;
+    app.issue_delivery.track_engagement = true;
+    let html = deliver_issue(&app, true, "https://example.com/café").await;
+
+    let click = app
+        .api_client
+        .get(find_url(&html, "/t/c/"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(click.status().as_u16(), 302);
+    assert_eq!(click.headers()["Location"], "https://example.com/caf%C3%A9");
+}
+
+#[tokio::test]
+async fn issues_are_only_tracked_when_they_opt_in() {
+    let mut app = spawn_app().await;
+    app.issue_delivery.track_engagement = true;
+    let html = deliver_issue(&app, false, POST).await;
+
+    assert!(html.contains(r#"href="https://example.com/post""#));
+    assert!(!html.contains("/t/o/"));
+
+    let report = app
+        .get_newsletter_report(issue_id(&app).await)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(report.contains("Opens and clicks are not tracked for this issue."));
+}
+
+#[tokio::test]
+async fn issues_are_not_tracked_when_tracking_is_disabled() {
+    let app = spawn_app().await;
+    let html = deliver_issue(&app, true, POST).await;
+
+    assert!(html.contains(r#"href="https://example.com/post""#));
+    assert!(!html.contains("/t/o/"));
+}
+
+#[tokio::test]
+async fn tampered_tracking_links_are_rejected() {
+    let mut app = spawn_app().await;
+    app.issue_delivery.track_engagement = true;
+    let html = deliver_issue(&app, true, POST).await;
+
+    // An open token does not redirect anywhere
+    let open = find_url(&html, "/t/o/");
+    let token = open.rsplit('/').next().unwrap().trim_end_matches(".gif");
+    let response = app
+        .api_client
+        .get(format!("{}/t/c/{}", app.address, token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+
+    let click = find_url(&html, "/t/c/");
+    let response = app
+        .api_client
+        .get(format!("{}0", click))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+
+    let n_clicks = sqlx::query!(r#"SELECT count(*) as "n!" FROM issue_clicks"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_clicks, 0);
+}
@@ -0,0 +1,182 @@
+use crate::helpers::{publish_issue, spawn_app};
+
+// Sanitizing escapes the raw `&`, the feeds escape it once more
+const HTML: &str = r#"<p class="intro">Fish & chips</p>"#;
+
+#[tokio::test]
+async fn the_rss_feed_lists_the_archived_issues() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_issue_id = publish_issue(&app, "Issue <1>", HTML, true).await;
+    publish_issue(&app, "Private issue", HTML, false).await;
+
+    let response = app.get_feed("feed.xml").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/rss+xml; charset=utf-8"
+    );
+    let xml = response.text().await.unwrap();
+    assert!(xml.contains(r#"<rss version="2.0""#));
+    assert!(xml.contains("<title>Issue &lt;1&gt;</title>"));
+    assert!(xml.contains(&format!(
+        r#"<guid isPermaLink="false">urn:uuid:{}</guid>"#,
+        newsletter_issue_id
+    )));
+    assert!(xml.contains("/issues/issue-1</link>"));
+    assert!(xml.contains("<pubDate>"));
+    assert!(xml.contains(
+        "<description>&lt;p class=&quot;intro&quot;&gt;Fish &amp;amp; chips&lt;/p&gt;</description>"
+    ));
+    assert!(!xml.contains("Private issue"));
+}
+
+#[tokio::test]
+async fn the_atom_feed_lists_the_archived_issues() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_issue_id = publish_issue(&app, "Issue <1>", HTML, true).await;
+    publish_issue(&app, "Private issue", HTML, false).await;
+
+    let response = app.get_feed("atom.xml").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/atom+xml; charset=utf-8"
+    );
+    let xml = response.text().await.unwrap();
+    assert!(xml.contains(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#));
+    assert!(xml.contains("<title>Issue &lt;1&gt;</title>"));
+    assert!(xml.contains(&format!("<id>urn:uuid:{}</id>", newsletter_issue_id)));
+    assert!(xml.contains("<updated>"));
+    assert!(xml.contains(
+        r#"<content type="html">&lt;p class=&quot;intro&quot;&gt;Fish &amp;amp; chips&lt;/p&gt;</content>"#
+    ));
+    assert!(!xml.contains("Private issue"));
+}
+
+#[tokio::test]
+async fn the_feeds_use_the_fallbacks_of_merge_tags() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_issue(&app, "Hello", "<p>Hi {{ name | dear reader }}!</p>", true).await;
+
+    for feed in ["feed.xml", "atom.xml"] {
+        let xml = app.get_feed(feed).await.text().await.unwrap();
+        assert!(xml.contains("&lt;p&gt;Hi dear reader!&lt;/p&gt;"));
+        assert!(!xml.contains("{{"));
+    }
+}
+
+#[tokio::test]
+async fn an_unchanged_feed_is_not_sent_again_to_a_reader_with_its_etag() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_issue(&app, "First issue", HTML, true).await;
+
+    for feed in ["feed.xml", "atom.xml"] {
+        let response = app.get_feed(feed).await;
+        let etag = response.headers()["ETag"].to_str().unwrap().to_owned();
+
+        let response = app
+            .api_client
+            .get(format!("{}/{}", &app.address, feed))
+            .header("If-None-Match", &etag)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 304);
+        assert_eq!(response.headers()["ETag"], etag.as_str());
+        assert!(response.text().await.unwrap().is_empty());
+    }
+
+    // A new issue changes the etag
+    let etag = app.get_feed("feed.xml").await.headers()["ETag"]
+        .to_str()
+        .unwrap()
+        .to_owned();
+    publish_issue(&app, "Second issue", HTML, true).await;
+    let response = app
+        .api_client
+        .get(format!("{}/feed.xml", &app.address))
+        .header("If-None-Match", &etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("Second issue"));
+}
+
+#[tokio::test]
+async fn swapping_the_archived_issues_changes_the_etag() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let shown_id = publish_issue(&app, "Shown issue", HTML, true).await;
+    let hidden_id = publish_issue(&app, "Hidden issue", HTML, false).await;
+    let etag = app.get_feed("feed.xml").await.headers()["ETag"]
+        .to_str()
+        .unwrap()
+        .to_owned();
+
+    // The count and the last publication stay the same
+    app.post_issue_archive_visibility(shown_id, &serde_json::json!({}))
+        .await;
+    app.post_issue_archive_visibility(hidden_id, &serde_json::json!({ "show_in_archive": "on" }))
+        .await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/feed.xml", &app.address))
+        .header("If-None-Match", &etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let xml = response.text().await.unwrap();
+    assert!(xml.contains("Hidden issue"));
+    assert!(!xml.contains("Shown issue"));
+}
+
+#[tokio::test]
+async fn an_unchanged_feed_is_not_sent_again_since_its_last_modification() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_issue(&app, "First issue", HTML, true).await;
+
+    let response = app.get_feed("feed.xml").await;
+    let last_modified = response.headers()["Last-Modified"]
+        .to_str()
+        .unwrap()
+        .to_owned();
+
+    let response = app
+        .api_client
+        .get(format!("{}/feed.xml", &app.address))
+        .header("If-Modified-Since", &last_modified)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 304);
+
+    let response = app
+        .api_client
+        .get(format!("{}/feed.xml", &app.address))
+        .header("If-Modified-Since", "Sat, 01 Jan 2000 00:00:00 GMT")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn an_empty_feed_is_valid() {
+    let app = spawn_app().await;
+
+    for feed in ["feed.xml", "atom.xml"] {
+        let response = app.get_feed(feed).await;
+        assert_eq!(response.status().as_u16(), 200);
+        assert!(response.headers().get("Last-Modified").is_none());
+    }
+}
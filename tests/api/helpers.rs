@@ -1,17 +1,27 @@
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
-use newsletter::configuration::{get_configuration, DatabaseSettings};
-use newsletter::email_client::EmailClient;
+use fake::faker::{internet::en::SafeEmail, name::en::Name};
+use fake::Fake;
+use newsletter::configuration::{
+    get_configuration, DatabaseSettings, IssueDeliverySettings, RssToEmailSettings,
+    SubscriptionSettings,
+};
+use newsletter::email_client::EmailTransport;
 use newsletter::issue_delivery_workers::{try_execute_task, ExecutionOutput};
-use newsletter::startup::Application;
+use newsletter::issue_scheduler::publish_scheduled_issues;
+use newsletter::rss_to_email::poll_feed;
+use newsletter::startup::{Application, HmacSecret};
+use newsletter::subscriber_import::{import_subscribers, ImportMode, ImportReport};
+use newsletter::subscription_cleanup::delete_stale_pending_subscribers;
 use newsletter::telemetry::{get_subscriber, init_subscriber};
 use sqlx::types::Uuid;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
-use std::sync::Once;
+use std::sync::{Arc, Once};
 
 static START: Once = Once::new();
 
-use wiremock::MockServer;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
 
 pub struct TestApp {
     pub address: String,
@@ -20,7 +30,10 @@ pub struct TestApp {
     pub email_server: MockServer,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
-    pub email_client: EmailClient,
+    pub email_client: Arc<dyn EmailTransport>,
+    pub hmac_secret: HmacSecret,
+    pub issue_delivery: IssueDeliverySettings,
+    pub subscriptions: SubscriptionSettings,
 }
 
 pub struct TestUser {
@@ -45,6 +58,26 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    // POST /subscription/resend
+    pub async fn post_resend_confirmation(&self, subscription_token: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscription/resend", &self.address))
+            .form(&serde_json::json!({ "subscription_token": subscription_token }))
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // POST /subscription/data
+    pub async fn post_data_request(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscription/data", &self.address))
+            .form(&serde_json::json!({ "email": email }))
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
     pub async fn post_newsletter<B>(&self, body: &B) -> reqwest::Response
     where
         B: serde::Serialize,
@@ -104,6 +137,410 @@ impl TestApp {
         self.get_admin_dashboard().await.text().await.unwrap()
     }
 
+    // GET /admin/newsletters/{id}
+    pub async fn get_newsletter_report(&self, newsletter_issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/newsletters/{}",
+                &self.address, newsletter_issue_id
+            ))
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // POST /admin/newsletters/{id}/archive
+    pub async fn post_issue_archive_visibility<Body>(
+        &self,
+        newsletter_issue_id: Uuid,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/{}/archive",
+                &self.address, newsletter_issue_id
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // GET /issues
+    pub async fn get_archive(&self, page: Option<i64>) -> reqwest::Response {
+        let mut request = self.api_client.get(format!("{}/issues", &self.address));
+        if let Some(page) = page {
+            request = request.query(&[("page", page)]);
+        }
+        request.send().await.expect("failed to execute request.")
+    }
+
+    // GET /issues/{slug_or_id}
+    pub async fn get_archived_issue(&self, slug_or_id: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/issues/{}", &self.address, slug_or_id))
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // GET /feed.xml or /atom.xml
+    pub async fn get_feed(&self, feed: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/{}", &self.address, feed))
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // GET /admin/newsletters
+    pub async fn get_newsletters_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/newsletters", &self.address))
+            .send()
+            .await
+            .expect("failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    // POST /admin/newsletters/{id}/schedule
+    pub async fn post_reschedule_issue<Body>(
+        &self,
+        newsletter_issue_id: Uuid,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/{}/schedule",
+                &self.address, newsletter_issue_id
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // POST /admin/newsletters/{id}/cancel
+    pub async fn post_cancel_issue(&self, newsletter_issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/{}/cancel",
+                &self.address, newsletter_issue_id
+            ))
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // GET /admin/newsletters/drafts
+    pub async fn get_drafts_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/newsletters/drafts", &self.address))
+            .send()
+            .await
+            .expect("failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    // POST /admin/newsletters/drafts
+    pub async fn post_draft<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletters/drafts", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // GET /admin/newsletters/drafts/{id}
+    pub async fn get_draft(&self, newsletter_issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/newsletters/drafts/{}",
+                &self.address, newsletter_issue_id
+            ))
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // POST /admin/newsletters/drafts/{id}
+    pub async fn post_update_draft<Body>(
+        &self,
+        newsletter_issue_id: Uuid,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/drafts/{}",
+                &self.address, newsletter_issue_id
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // GET /admin/newsletters/drafts/{id}/preview
+    pub async fn get_draft_preview(&self, newsletter_issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/newsletters/drafts/{}/preview",
+                &self.address, newsletter_issue_id
+            ))
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // POST /admin/newsletters/drafts/{id}/delete
+    pub async fn post_delete_draft(&self, newsletter_issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/drafts/{}/delete",
+                &self.address, newsletter_issue_id
+            ))
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // POST /admin/newsletters/drafts/{id}/test
+    pub async fn post_draft_test_email<Body>(
+        &self,
+        newsletter_issue_id: Uuid,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/drafts/{}/test",
+                &self.address, newsletter_issue_id
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // POST /admin/newsletters/drafts/{id}/publish
+    pub async fn post_publish_draft<Body>(
+        &self,
+        newsletter_issue_id: Uuid,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/drafts/{}/publish",
+                &self.address, newsletter_issue_id
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // GET /admin/lists
+    pub async fn get_lists(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/lists", &self.address))
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // POST /admin/lists
+    pub async fn post_list<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/lists", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // GET /admin/segments
+    pub async fn get_segments(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/segments", &self.address))
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // POST /admin/segments
+    pub async fn post_segment<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/segments", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // GET /admin/layouts
+    pub async fn get_layouts(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/layouts", &self.address))
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // POST /admin/layouts
+    pub async fn post_layout<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/layouts", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // POST /admin/layouts/{layout_id}/default
+    pub async fn post_default_layout(&self, layout_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/layouts/{}/default",
+                &self.address, layout_id
+            ))
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // GET /admin/newsletters/recipients
+    pub async fn get_issue_recipients(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/newsletters/recipients?{}",
+                &self.address, query
+            ))
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // GET /admin/subscribers
+    pub async fn get_subscribers(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers?{}", &self.address, query))
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // GET /admin/subscribers/export
+    pub async fn get_subscribers_export(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/subscribers/export?{}",
+                &self.address, query
+            ))
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // GET /admin/subscribers/{id}
+    pub async fn get_subscriber(&self, subscriber_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/subscribers/{}",
+                &self.address, subscriber_id
+            ))
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // POST /admin/subscribers/{id}/tags
+    pub async fn post_subscriber_tags(&self, subscriber_id: Uuid, tags: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/subscribers/{}/tags",
+                &self.address, subscriber_id
+            ))
+            .form(&serde_json::json!({ "tags": tags }))
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // POST /admin/subscribers/{id}/confirm, /unsubscribe or /delete
+    pub async fn post_subscriber_action(
+        &self,
+        subscriber_id: Uuid,
+        action: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/subscribers/{}/{}",
+                &self.address, subscriber_id, action
+            ))
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // POST /admin/subscribers/import
+    pub async fn post_subscriber_import(&self, mode: &str, csv: &str) -> reqwest::Response {
+        let form = reqwest::multipart::Form::new()
+            .text("mode", mode.to_owned())
+            .part(
+                "file",
+                reqwest::multipart::Part::text(csv.to_owned())
+                    .file_name("subscribers.csv")
+                    .mime_str("text/csv")
+                    .unwrap(),
+            );
+        self.api_client
+            .post(format!("{}/admin/subscribers/import", &self.address))
+            .multipart(form)
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
+    // GET /admin/subscribers/imports/{id}/errors.csv
+    pub async fn get_subscriber_import_errors(&self, location: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}{}/errors.csv", &self.address, location))
+            .send()
+            .await
+            .expect("failed to execute request.")
+    }
+
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.api_client
             .get(&format!("{}/admin/password", &self.address))
@@ -133,6 +570,19 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    // The requests received by the email server once there are at least
+    // `n` of them, some emails are sent in the background
+    pub async fn email_requests(&self, n: usize) -> Vec<wiremock::Request> {
+        for _ in 0..100 {
+            let requests = self.email_server.received_requests().await.unwrap();
+            if requests.len() >= n {
+                return requests;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        panic!("The email server did not receive {} requests", n);
+    }
+
     pub async fn get_confirmation_url(
         &self,
         email_request: &wiremock::Request,
@@ -152,8 +602,8 @@ impl TestApp {
             confirm_link
         };
 
-        let html_link = get_link(&body["HtmlBody"].as_str().unwrap());
-        let text_link = get_link(&body["TextBody"].as_str().unwrap());
+        let html_link = get_link(body["HtmlBody"].as_str().unwrap());
+        let text_link = get_link(body["TextBody"].as_str().unwrap());
 
         ConfirmationLink {
             html_link,
@@ -161,11 +611,67 @@ impl TestApp {
         }
     }
 
+    // Every delivered issue ends with an unsubscribe link.
+    // Issues go through Postmark's batch endpoint, the link is taken
+    // from the first message of the batch.
+    pub fn get_unsubscribe_link(&self, email_request: &wiremock::Request) -> reqwest::Url {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+
+        let links: Vec<_> = linkify::LinkFinder::new()
+            .links(body[0]["TextBody"].as_str().unwrap())
+            .filter(|l| *l.kind() == linkify::LinkKind::Url)
+            .map(|l| l.as_str().to_owned())
+            .filter(|l| l.contains("/subscription/unsubscribe"))
+            .collect();
+        assert_eq!(links.len(), 1);
+
+        reqwest::Url::parse(&links[0]).unwrap()
+    }
+
+    // What the scheduler does on each of its ticks
+    pub async fn publish_scheduled_issues(&self) -> usize {
+        publish_scheduled_issues(&self.db_pool).await.unwrap()
+    }
+
+    // What the subscription cleanup task does every hour
+    pub async fn delete_stale_pending_subscribers(&self) -> u64 {
+        delete_stale_pending_subscribers(
+            &self.db_pool,
+            self.subscriptions.pending_subscriber_retention(),
+        )
+        .await
+        .unwrap()
+    }
+
+    // What `newsletter import-subscribers <file.csv> <mode>` does
+    pub async fn import_subscribers(&self, mode: ImportMode, csv: &str) -> ImportReport {
+        import_subscribers(
+            &self.db_pool,
+            self.email_client.as_ref(),
+            &self.address,
+            mode,
+            csv.as_bytes(),
+        )
+        .await
+        .unwrap()
+    }
+
+    // What the RSS-to-email task does every `poll_interval_second`
+    pub async fn poll_feed(&self, settings: &RssToEmailSettings) -> Result<usize, anyhow::Error> {
+        poll_feed(&self.db_pool, &reqwest::Client::new(), settings).await
+    }
+
     pub async fn dispatch_all_emails(&self) {
         loop {
-            if let ExecutionOutput::EmptyQueue = try_execute_task(&self.db_pool, &self.email_client)
-                .await
-                .unwrap()
+            if let ExecutionOutput::EmptyQueue = try_execute_task(
+                &self.db_pool,
+                self.email_client.as_ref(),
+                &self.address,
+                &self.hmac_secret,
+                &self.issue_delivery,
+            )
+            .await
+            .unwrap()
             {
                 break;
             }
@@ -245,7 +751,7 @@ pub async fn spawn_app() -> TestApp {
 
     let port = app.port();
     let address = format!("http://127.0.0.1:{}", &port);
-    let _ = tokio::spawn(app.run_until_stopped());
+    drop(tokio::spawn(app.run_until_stopped()));
 
     let test_user = TestUser::new();
     test_user.store(&pg_pool).await;
@@ -256,7 +762,8 @@ pub async fn spawn_app() -> TestApp {
         .build()
         .unwrap();
 
-    let email_client = configuration.email_client.client();
+    let email_client = configuration.email_client.client().unwrap();
+    let hmac_secret = HmacSecret(configuration.application.hmac_secret.clone());
 
     TestApp {
         address,
@@ -266,6 +773,9 @@ pub async fn spawn_app() -> TestApp {
         test_user,
         api_client,
         email_client,
+        hmac_secret,
+        issue_delivery: configuration.issue_delivery,
+        subscriptions: configuration.subscriptions,
     }
 }
 
@@ -297,3 +807,102 @@ pub fn assert_redirect_to(response: &reqwest::Response, location: &str) {
     assert_eq!(response.status().as_u16(), 303);
     assert_eq!(response.headers().get("Location").unwrap(), location);
 }
+
+// Reply of Postmark's batch endpoint with one result per message of the request,
+// messages sent to a recipient for which `reject` returns true are refused
+pub fn postmark_batch_reply(
+    reject: impl Fn(&str) -> bool + Send + Sync + 'static,
+) -> impl wiremock::Respond {
+    move |request: &wiremock::Request| {
+        let messages: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+        let results: Vec<_> = messages
+            .iter()
+            .map(|m| {
+                let to = m["To"].as_str().unwrap();
+                if reject(to) {
+                    serde_json::json!({
+                        "To": to,
+                        "ErrorCode": 406,
+                        "Message": "You tried to send to a recipient that has been marked as inactive."
+                    })
+                } else {
+                    serde_json::json!({
+                        "To": to,
+                        "MessageID": uuid::Uuid::new_v4().to_string(),
+                        "ErrorCode": 0,
+                        "Message": "OK"
+                    })
+                }
+            })
+            .collect();
+
+        ResponseTemplate::new(200).set_body_json(results)
+    }
+}
+
+pub fn postmark_batch_accepted() -> impl wiremock::Respond {
+    postmark_batch_reply(|_| false)
+}
+
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLink {
+    // when working with multiple subscribers
+    // use random credentials
+    let name: String = Name().fake();
+    let email: String = SafeEmail().fake();
+
+    subscribe_with(app, &serde_json::json!({ "name": name, "email": email })).await
+}
+
+// Submit the subscription form with `form` and return the link of the
+// confirmation email
+pub async fn subscribe_with(app: &TestApp, form: &serde_json::Value) -> ConfirmationLink {
+    let body = serde_urlencoded::to_string(form).unwrap();
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    let n_requests = app.email_server.received_requests().await.unwrap().len();
+    let response = app.post_subscriptions(body).await;
+    assert_redirect_to(&response, "/subscription");
+
+    let email_request = app.email_requests(n_requests + 1).await.pop().unwrap();
+    app.get_confirmation_url(&email_request).await
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    let confirmation_link = create_unconfirmed_subscriber(app).await;
+
+    reqwest::get(confirmation_link.html_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+// Publish an issue to nobody and return its id
+pub async fn publish_issue(app: &TestApp, title: &str, html: &str, show_in_archive: bool) -> Uuid {
+    let mut body = serde_json::json!({
+        "title": title,
+        "text": "text content",
+        "html": html,
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    if show_in_archive {
+        body["show_in_archive"] = "on".into();
+    }
+    let response = app.post_newsletter(&body).await;
+    assert_redirect_to(&response, "/admin/newsletters");
+
+    sqlx::query!(
+        "SELECT newsletter_issue_id FROM newsletter_issue ORDER BY created_at DESC LIMIT 1"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .newsletter_issue_id
+}
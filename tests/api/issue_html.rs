@@ -0,0 +1,125 @@
+use crate::helpers::{assert_redirect_to, spawn_app, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn stored_html(app: &TestApp) -> String {
+    sqlx::query!("SELECT html FROM newsletter_issue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .html
+}
+
+#[tokio::test]
+async fn the_html_of_an_issue_is_made_safe_for_mail_clients() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Hello",
+            "text": "Hello",
+            "html": r#"<style>p { color: red; }</style><p>Read <a href="/issues">the archive</a></p><script>alert(1)</script>"#,
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_redirect_to(&response, "/admin/newsletters");
+
+    // The app is reached on a random port, not part of its base url in tests
+    let html = stored_html(&app).await;
+    assert!(html.starts_with(r#"<p style="color: red;">Read <a href="http://127.0.0.1"#));
+    assert!(html.ends_with(r#"/issues">the archive</a></p>"#));
+    assert!(!html.contains("script"));
+}
+
+#[tokio::test]
+async fn the_admin_is_warned_when_gmail_would_clip_an_issue() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    app.post_newsletter(&serde_json::json!({
+        "title": "Hello",
+        "text": "Hello",
+        "html": format!("<p>{}</p>", "a".repeat(110 * 1024)),
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    }))
+    .await;
+
+    let html = app.get_newsletters_html().await;
+    assert!(html.contains("The newsletter issue has been published!"));
+    assert!(html.contains("Gmail only shows the first 102 KB of a message."));
+}
+
+#[tokio::test]
+async fn drafts_are_made_safe_when_published() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_html = r#"<p onclick="alert(1)">Hi <img src="cat.png" alt="cat"></p>"#;
+    app.post_draft(&serde_json::json!({
+        "title": "Hello",
+        "text": "Hello",
+        "html": draft_html,
+    }))
+    .await;
+    let draft_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+
+    // Drafts are edited as written
+    assert_eq!(stored_html(&app).await, draft_html);
+
+    let response = app
+        .post_publish_draft(
+            draft_id,
+            &serde_json::json!({ "idempotency_key": uuid::Uuid::new_v4().to_string() }),
+        )
+        .await;
+    assert_redirect_to(&response, "/admin/newsletters");
+
+    let html = stored_html(&app).await;
+    assert!(html.starts_with(r#"<p>Hi <img src="http://127.0.0.1"#));
+    assert!(html.ends_with(r#"/cat.png" alt="cat"></p>"#));
+}
+
+#[tokio::test]
+async fn drafts_are_previewed_and_tested_as_they_are_published() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_draft(&serde_json::json!({
+        "title": "Hello",
+        "text": "Hello",
+        "html": r#"<style>p { color: red; }</style><p>Hi {{ name | reader }}, read <a href="/issues">the archive</a></p><script>alert(1)</script>"#,
+    }))
+    .await;
+    let draft_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+
+    let preview = app.get_draft_preview(draft_id).await.text().await.unwrap();
+    assert!(
+        preview.starts_with(r#"<p style="color: red;">Hi reader, read <a href="http://127.0.0.1"#)
+    );
+    assert!(!preview.contains("script"));
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_draft_test_email(
+        draft_id,
+        &serde_json::json!({ "recipients": "ursula@domain.com" }),
+    )
+    .await;
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+    let html = body["HtmlBody"].as_str().unwrap();
+    assert!(html.starts_with(r#"<p style="color: red;">Hi reader, read <a href="http://127.0.0.1"#));
+    assert!(!html.contains("script"));
+}
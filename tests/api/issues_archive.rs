@@ -0,0 +1,191 @@
+use crate::helpers::{assert_redirect_to, publish_issue, spawn_app, TestApp};
+use sqlx::types::Uuid;
+
+const HTML: &str = "<h2>html content</h2>";
+
+async fn get_slug(app: &TestApp, newsletter_issue_id: Uuid) -> String {
+    sqlx::query!(
+        "SELECT slug FROM newsletter_issue WHERE newsletter_issue_id = $1",
+        newsletter_issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .slug
+    .unwrap()
+}
+
+#[tokio::test]
+async fn archived_issues_are_rendered_by_slug_and_by_id() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_issue_id = publish_issue(&app, "Hello, World!", HTML, true).await;
+
+    let slug = get_slug(&app, newsletter_issue_id).await;
+    assert_eq!(slug, "hello-world");
+
+    let archive = app.get_archive(None).await.text().await.unwrap();
+    assert!(archive.contains(r#"<a href="/issues/hello-world">Hello, World!</a>"#));
+
+    for slug_or_id in [slug, newsletter_issue_id.to_string()] {
+        let response = app.get_archived_issue(&slug_or_id).await;
+        assert_eq!(response.status().as_u16(), 200);
+        let html = response.text().await.unwrap();
+        assert!(html.contains("<title>Hello, World! - Newsletter</title>"));
+        assert!(html.contains("<h2>html content</h2>"));
+    }
+}
+
+#[tokio::test]
+async fn issues_are_hidden_from_the_archive_by_default() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_issue_id = publish_issue(&app, "Private issue", HTML, false).await;
+
+    let archive = app.get_archive(None).await.text().await.unwrap();
+    assert!(!archive.contains("Private issue"));
+    assert_eq!(
+        app.get_archived_issue("private-issue")
+            .await
+            .status()
+            .as_u16(),
+        404
+    );
+    assert_eq!(
+        app.get_archived_issue(&newsletter_issue_id.to_string())
+            .await
+            .status()
+            .as_u16(),
+        404
+    );
+}
+
+#[tokio::test]
+async fn admins_can_show_and_hide_an_issue_in_the_archive() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_issue_id = publish_issue(&app, "Toggled issue", HTML, false).await;
+
+    let response = app
+        .post_issue_archive_visibility(
+            newsletter_issue_id,
+            &serde_json::json!({ "show_in_archive": "on" }),
+        )
+        .await;
+    assert_redirect_to(
+        &response,
+        &format!("/admin/newsletters/{}", newsletter_issue_id),
+    );
+    assert_eq!(
+        app.get_archived_issue("toggled-issue")
+            .await
+            .status()
+            .as_u16(),
+        200
+    );
+
+    // An unchecked checkbox is not sent at all
+    app.post_issue_archive_visibility(newsletter_issue_id, &serde_json::json!({}))
+        .await;
+    assert_eq!(
+        app.get_archived_issue("toggled-issue")
+            .await
+            .status()
+            .as_u16(),
+        404
+    );
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_change_the_archive_visibility() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_issue_archive_visibility(
+            Uuid::new_v4(),
+            &serde_json::json!({ "show_in_archive": "on" }),
+        )
+        .await;
+
+    assert_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn issues_sharing_a_title_get_distinct_slugs() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let first = publish_issue(&app, "Weekly digest", HTML, true).await;
+    let second = publish_issue(&app, "Weekly digest", HTML, true).await;
+
+    let first_slug = get_slug(&app, first).await;
+    let second_slug = get_slug(&app, second).await;
+    assert_eq!(first_slug, "weekly-digest");
+    assert_eq!(
+        second_slug,
+        format!("weekly-digest-{}", &second.to_string()[..8])
+    );
+}
+
+#[tokio::test]
+async fn drafts_are_not_in_the_archive() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_draft(&serde_json::json!({
+        "title": "Draft title",
+        "text": "Draft text",
+        "html": "<p>Draft html</p>",
+    }))
+    .await;
+    // Even if the flag is set on the draft
+    sqlx::query!("UPDATE newsletter_issue SET show_in_archive = true")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let archive = app.get_archive(None).await.text().await.unwrap();
+    assert!(!archive.contains("Draft title"));
+}
+
+#[tokio::test]
+async fn the_archive_is_paginated_from_the_most_recent_issue() {
+    let app = spawn_app().await;
+    for i in 0..25 {
+        sqlx::query!(
+            r#"
+            INSERT INTO newsletter_issue
+                (newsletter_issue_id, title, text, html, published_at, show_in_archive)
+            VALUES ($1, $2, '', '', now() - make_interval(days => $3), true)
+            "#,
+            Uuid::new_v4(),
+            format!("Issue number {:02}", i),
+            i
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+
+    let first_page = app.get_archive(None).await.text().await.unwrap();
+    assert!(first_page.contains("Issue number 00"));
+    assert!(first_page.contains("Issue number 19"));
+    assert!(!first_page.contains("Issue number 20"));
+    assert!(first_page.find("Issue number 00") < first_page.find("Issue number 01"));
+    assert!(first_page.contains(r#"href="/issues?page=2""#));
+
+    let second_page = app.get_archive(Some(2)).await.text().await.unwrap();
+    assert!(!second_page.contains("Issue number 19"));
+    assert!(second_page.contains("Issue number 24"));
+    assert!(second_page.contains(r#"href="/issues?page=1""#));
+    assert!(!second_page.contains(r#"href="/issues?page=3""#));
+}
+
+#[tokio::test]
+async fn a_huge_page_of_the_archive_is_empty() {
+    let app = spawn_app().await;
+
+    let response = app.get_archive(Some(i64::MAX)).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("No issue has been published yet."));
+}
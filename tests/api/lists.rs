@@ -0,0 +1,238 @@
+use crate::helpers::{assert_redirect_to, spawn_app, subscribe_with, ConfirmationLink, TestApp};
+use sqlx::types::Uuid;
+
+// Create a list as the logged in admin and return its id
+async fn create_list(app: &TestApp, name: &str, slug: &str) -> Uuid {
+    let response = app
+        .post_list(&serde_json::json!({ "name": name, "slug": slug }))
+        .await;
+    assert_redirect_to(&response, "/admin/lists");
+
+    sqlx::query!("SELECT list_id FROM lists WHERE slug = $1", slug)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id
+}
+
+// Subscribe to `list` and return the link of the confirmation email
+async fn subscribe(app: &TestApp, email: &str, list: &str) -> ConfirmationLink {
+    subscribe_with(
+        app,
+        &serde_json::json!({ "name": "Ursula", "email": email, "list": list }),
+    )
+    .await
+}
+
+async fn membership_statuses(app: &TestApp, email: &str) -> Vec<(String, String)> {
+    sqlx::query!(
+        r#"
+        SELECT lists.slug, list_memberships.status
+        FROM list_memberships
+        JOIN lists ON lists.list_id = list_memberships.list_id
+        JOIN subscriptions ON subscriptions.id = list_memberships.subscriber_id
+        WHERE subscriptions.email = $1
+        ORDER BY lists.slug
+    "#,
+        email
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.slug, r.status))
+    .collect()
+}
+
+fn statuses(statuses: &[(&str, &str)]) -> Vec<(String, String)> {
+    statuses
+        .iter()
+        .map(|(slug, status)| (slug.to_string(), status.to_string()))
+        .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_lists() {
+    let app = spawn_app().await;
+
+    let response = app.get_lists().await;
+    assert_redirect_to(&response, "/login");
+    let response = app
+        .post_list(&serde_json::json!({ "name": "Releases", "slug": "releases" }))
+        .await;
+    assert_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn lists_can_be_created_with_a_unique_slug() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    create_list(&app, "Release announcements", "releases").await;
+    let html = app.get_lists().await.text().await.unwrap();
+    assert!(html.contains("The Release announcements list has been created."));
+    assert!(html.contains("Release announcements"));
+    assert!(html.contains(r#"<a href="/subscription?list=releases">releases</a>"#));
+
+    app.post_list(&serde_json::json!({ "name": "Again", "slug": "releases" }))
+        .await;
+    let html = app.get_lists().await.text().await.unwrap();
+    assert!(html.contains("releases is already the slug of a list."));
+
+    app.post_list(&serde_json::json!({ "name": "Bad", "slug": "Bad Slug" }))
+        .await;
+    let html = app.get_lists().await.text().await.unwrap();
+    assert!(html.contains("Bad Slug is not a valid list slug"));
+}
+
+#[tokio::test]
+async fn the_subscribe_page_offers_every_list() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_list(&app, "Release announcements", "releases").await;
+
+    let html = app
+        .api_client
+        .get(format!("{}/subscription?list=releases", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains(r#"<option value="newsletter">Newsletter</option>"#));
+    assert!(html.contains(r#"<option value="releases" selected>Release announcements</option>"#));
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_is_rejected() {
+    let app = spawn_app().await;
+
+    let body = "name=Ursula&email=ursula%40example.com&list=nope";
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn memberships_are_pending_until_the_link_is_clicked() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_list(&app, "Release announcements", "releases").await;
+
+    let link = subscribe(&app, "ursula@example.com", "releases").await;
+    assert_eq!(
+        membership_statuses(&app, "ursula@example.com").await,
+        statuses(&[("releases", "pending_confirmation")])
+    );
+
+    reqwest::get(link.html_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(
+        membership_statuses(&app, "ursula@example.com").await,
+        statuses(&[("releases", "confirmed")])
+    );
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_confirm_each_new_list() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_list(&app, "Release announcements", "releases").await;
+    let link = subscribe(&app, "ursula@example.com", "").await;
+    reqwest::get(link.html_link).await.unwrap();
+
+    let link = subscribe(&app, "ursula@example.com", "releases").await;
+    assert_eq!(
+        membership_statuses(&app, "ursula@example.com").await,
+        statuses(&[
+            ("newsletter", "confirmed"),
+            ("releases", "pending_confirmation")
+        ])
+    );
+
+    reqwest::get(link.html_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(
+        membership_statuses(&app, "ursula@example.com").await,
+        statuses(&[("newsletter", "confirmed"), ("releases", "confirmed")])
+    );
+}
+
+#[tokio::test]
+async fn issues_are_only_enqueued_for_the_confirmed_members_of_their_list() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let releases_id = create_list(&app, "Release announcements", "releases").await;
+    let link = subscribe(&app, "digest@example.com", "").await;
+    reqwest::get(link.html_link).await.unwrap();
+    let link = subscribe(&app, "releases@example.com", "releases").await;
+    reqwest::get(link.html_link).await.unwrap();
+    subscribe(&app, "pending@example.com", "releases").await;
+
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Version 2.0",
+            "text": "Version 2.0 is out",
+            "html": "<p>Version 2.0 is out</p>",
+            "list_id": releases_id.to_string(),
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    let queued: Vec<String> = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.subscriber_email)
+        .collect();
+    assert_eq!(queued, vec!["releases@example.com".to_string()]);
+}
+
+#[tokio::test]
+async fn issues_for_an_unknown_list_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Version 2.0",
+            "text": "Version 2.0 is out",
+            "html": "<p>Version 2.0 is out</p>",
+            "list_id": uuid::Uuid::new_v4().to_string(),
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn unsubscribing_leaves_every_list() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_list(&app, "Release announcements", "releases").await;
+    let link = subscribe(&app, "ursula@example.com", "").await;
+    reqwest::get(link.html_link).await.unwrap();
+    let link = subscribe(&app, "ursula@example.com", "releases").await;
+    reqwest::get(link.html_link).await.unwrap();
+    let id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+
+    app.post_subscriber_action(id, "unsubscribe").await;
+
+    assert_eq!(
+        membership_statuses(&app, "ursula@example.com").await,
+        statuses(&[("newsletter", "unsubscribed"), ("releases", "unsubscribed")])
+    );
+}
@@ -1,8 +1,26 @@
 mod admin_dashboard;
+mod admin_subscribers;
 mod change_password;
+mod email_layouts;
+mod engagement_tracking;
+mod feeds;
 mod health_check;
 mod helpers;
+mod issue_html;
+mod issues_archive;
+mod lists;
 mod login;
+mod merge_tags;
 mod newsletter;
+mod newsletter_drafts;
+mod newsletter_markdown;
+mod newsletter_report;
+mod newsletter_schedule;
+mod rss_to_email;
+mod segments;
+mod subscriber_import;
+mod subscribers_export;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_data;
+mod subscriptions_unsubscribe;
@@ -0,0 +1,190 @@
+use crate::helpers::{
+    assert_redirect_to, postmark_batch_accepted, spawn_app, subscribe_with, TestApp,
+};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+// Subscribe as `name` and click the confirmation link
+async fn subscribe(app: &TestApp, name: &str, email: &str) {
+    let link = subscribe_with(app, &serde_json::json!({ "name": name, "email": email })).await;
+    reqwest::get(link.html_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn n_issues(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT count(*) as "n!" FROM newsletter_issue WHERE published_at IS NOT NULL"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n
+}
+
+#[tokio::test]
+async fn merge_tags_are_rendered_for_each_recipient() {
+    let app = spawn_app().await;
+    subscribe(&app, "Ursula & co", "ursula@example.com").await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(postmark_batch_accepted())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Hello",
+            "text": "Hi {{ name }} <{{ email }}>, read it at {{ archive_url | nowhere }}",
+            "html": r#"<p>Hi {{ name }}!</p><a href="{{ unsubscribe_url }}">Leave</a>"#,
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_emails().await;
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    let email_request = requests.last().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(
+        body[0]["TextBody"]
+            .as_str()
+            .unwrap()
+            .lines()
+            .next()
+            .unwrap(),
+        "Hi Ursula & co <ursula@example.com>, read it at nowhere"
+    );
+    let html = body[0]["HtmlBody"].as_str().unwrap();
+    assert!(html.starts_with("<p>Hi Ursula &amp; co!</p>"));
+    let unsubscribe_link = app.get_unsubscribe_link(email_request);
+    assert!(html.contains(&format!(
+        r#"<a href="{}">Leave</a>"#,
+        unsubscribe_link.as_str().replace('&', "&amp;")
+    )));
+}
+
+#[tokio::test]
+async fn issues_with_unknown_merge_tags_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let test_cases = vec![
+        ("Hi {{ first_name }}", "<p>Hi</p>"),
+        ("Hi", "<p>Hi {{ name </p>"),
+    ];
+    for (text, html) in test_cases {
+        let response = app
+            .post_newsletter(&serde_json::json!({
+                "title": "Hello",
+                "text": text,
+                "html": html,
+                "idempotency_key": uuid::Uuid::new_v4().to_string(),
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 400);
+    }
+
+    assert_eq!(n_issues(&app).await, 0);
+}
+
+#[tokio::test]
+async fn drafts_with_unknown_merge_tags_are_not_published() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_draft(&serde_json::json!({
+        "title": "Hello",
+        "text": "Hi {{ first_name }}",
+        "html": "<p>Hi</p>",
+    }))
+    .await;
+    let draft_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_publish_draft(
+            draft_id,
+            &serde_json::json!({ "idempotency_key": uuid::Uuid::new_v4().to_string() }),
+        )
+        .await;
+    let draft_page = format!("/admin/newsletters/drafts/{}", draft_id);
+    assert_redirect_to(&response, &draft_page);
+    let html = app.get_draft(draft_id).await.text().await.unwrap();
+    assert!(html.contains("{{ first_name }} is not a merge tag"));
+    assert_eq!(n_issues(&app).await, 0);
+
+    app.post_draft_test_email(
+        draft_id,
+        &serde_json::json!({ "recipients": "ursula@domain.com" }),
+    )
+    .await;
+    let html = app.get_draft(draft_id).await.text().await.unwrap();
+    assert!(html.contains("No test email has been sent."));
+}
+
+#[tokio::test]
+async fn test_emails_use_the_fallbacks() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_draft(&serde_json::json!({
+        "title": "Hello",
+        "text": "Hi {{ name | reader }} <{{ email }}>",
+        "html": "<p>Hi {{ name | reader }}!</p>",
+    }))
+    .await;
+    let draft_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_draft_test_email(
+        draft_id,
+        &serde_json::json!({ "recipients": "ursula@domain.com" }),
+    )
+    .await;
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+    assert_eq!(body["TextBody"], "Hi reader <ursula@domain.com>");
+    assert_eq!(body["HtmlBody"], "<p>Hi reader!</p>");
+}
+
+#[tokio::test]
+async fn the_archive_uses_the_fallbacks() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Hello",
+            "text": "Hi {{ name | reader }}",
+            "html": "<p>Hi {{ name | reader }}!</p>",
+            "show_in_archive": "on",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_redirect_to(&response, "/admin/newsletters");
+
+    let html = app.get_archived_issue("hello").await.text().await.unwrap();
+    assert!(html.contains("<p>Hi reader!</p>"));
+}
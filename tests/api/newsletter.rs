@@ -1,9 +1,8 @@
 use std::time::Duration;
 
-use crate::helpers::{assert_redirect_to, spawn_app, ConfirmationLink, TestApp};
-use fake::{
-    faker::{internet::en::SafeEmail, name::en::Name},
-    Fake,
+use crate::helpers::{
+    assert_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber,
+    postmark_batch_accepted, postmark_batch_reply, spawn_app,
 };
 use wiremock::{
     matchers::{any, method, path},
@@ -44,9 +43,8 @@ async fn newsletter_is_delivered_to_confirmed_subscribers() {
     create_confirmed_subscriber(&app).await;
     app.test_user.login(&app).await;
 
-    Mock::given(path("/email"))
-        .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+    when_sending_email()
+        .respond_with(postmark_batch_accepted())
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -65,51 +63,6 @@ async fn newsletter_is_delivered_to_confirmed_subscribers() {
     app.dispatch_all_emails().await;
 }
 
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLink {
-    // when working with multiple subscribers
-    // use random credentials
-    let name: String = Name().fake();
-    let email: String = SafeEmail().fake();
-    let body = serde_urlencoded::to_string(&serde_json::json!({
-        "name": name,
-        "email": email
-    }))
-    .unwrap();
-
-    let _mock_guard = Mock::given(path("/email"))
-        .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
-        .named("Create unconfirmed subscriber")
-        .expect(1)
-        .mount_as_scoped(&app.email_server)
-        .await;
-
-    app.post_subscriptions(body.into())
-        .await
-        .error_for_status()
-        .unwrap();
-
-    let email_request = &app
-        .email_server
-        .received_requests()
-        .await
-        .unwrap()
-        .pop()
-        .unwrap();
-
-    app.get_confirmation_url(&email_request).await
-}
-
-async fn create_confirmed_subscriber(app: &TestApp) {
-    let confirmation_link = create_unconfirmed_subscriber(app).await;
-
-    reqwest::get(confirmation_link.html_link)
-        .await
-        .unwrap()
-        .error_for_status()
-        .unwrap();
-}
-
 #[tokio::test]
 async fn publish_newsletter_return_400_for_invalid_body() {
     let app = spawn_app().await;
@@ -152,9 +105,8 @@ async fn newsletter_creation_is_idempotent() {
     app.test_user.login(&app).await;
 
     // // mocking Postmark API for testing
-    Mock::given(path("/email")) // if request comes in /email
-        .and(method("POST")) // with method POST
-        .respond_with(ResponseTemplate::new(200))
+    when_sending_email() // if request comes in /email/batch with method POST
+        .respond_with(postmark_batch_accepted())
         .expect(1) // only expect one request
         // when we retry, then this ensures
         // that the api is idempotent
@@ -186,11 +138,16 @@ async fn concurrent_form_submission_are_handle_gracefully() {
     app.test_user.login(&app).await;
 
     // // mocking Postmark api
-    Mock::given(path("/email"))
-        .and(method("POST"))
+    when_sending_email()
         // setting delay of 2 second for first request to mack the time taken by first request
         // so that we can test the concurrent request being queued
-        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(serde_json::json!([
+                    { "ErrorCode": 0, "Message": "OK", "MessageID": uuid::Uuid::new_v4() }
+                ]))
+                .set_delay(Duration::from_secs(2)),
+        )
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -213,9 +170,10 @@ async fn concurrent_form_submission_are_handle_gracefully() {
     app.dispatch_all_emails().await;
 }
 
-// short-hand for building mock server for email deliveries
+// short-hand for building mock server for email deliveries,
+// issues are sent through Postmark's batch endpoint
 fn when_sending_email() -> MockBuilder {
-    Mock::given(path("/email")).and(method("POST"))
+    Mock::given(path("/email/batch")).and(method("POST"))
 }
 
 #[tokio::test]
@@ -232,35 +190,239 @@ async fn transient_errors_do_not_cause_duplicate_deliveries_on_retry() {
     create_confirmed_subscriber(&app).await;
     app.test_user.login(&app).await;
 
-    // mocking postmark api
+    // mocking postmark api,
+    // both subscribers are in the same batch and one of them is refused
+    let rejected = sqlx::query!("SELECT email FROM subscriptions LIMIT 1")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    let rejected_recipient = rejected.clone();
     when_sending_email()
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(postmark_batch_reply(move |to| to == rejected_recipient))
         .up_to_n_times(1)
         .expect(1)
         .mount(&app.email_server)
         .await;
 
+    let response = app.post_newsletter(&newsletter_request_payload).await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    // // retry
+    // expecting only one hit to postmark api
+    // one subscriber already got the email
+    when_sending_email()
+        .respond_with(postmark_batch_accepted())
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletter(&newsletter_request_payload).await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    app.dispatch_all_emails().await;
+
+    // Only the refused delivery is left, waiting for its retry
+    let queued = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].subscriber_email, rejected);
+}
+
+#[tokio::test]
+async fn transient_errors_are_retried() {
+    let mut app = spawn_app().await;
+    // Retries are due straight away
+    app.issue_delivery.retry_base_delay_millisecond = 0;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
     when_sending_email()
         .respond_with(ResponseTemplate::new(500))
         .up_to_n_times(1)
         .expect(1)
         .mount(&app.email_server)
         .await;
+    when_sending_email()
+        .respond_with(postmark_batch_accepted())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
 
+    let newsletter_request_payload = serde_json::json!({
+        "title": "Newsletter title",
+        "text": "text content",
+        "html": "<h1>html content</h1>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
     let response = app.post_newsletter(&newsletter_request_payload).await;
-    assert_eq!(response.status().as_u16(), 303);
+    assert_redirect_to(&response, "/admin/newsletters");
+
+    app.dispatch_all_emails().await;
+
+    let n_failures = sqlx::query!(r#"SELECT count(*) as "count!" FROM issue_delivery_failures"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_failures, 0);
+}
+
+#[tokio::test]
+async fn failed_retries_are_not_attempted_before_their_time() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
 
-    // // retry
-    // expecting only one hit to postmark api
-    // one subscriber already got the email
     when_sending_email()
-        .respond_with(ResponseTemplate::new(200))
-        .expect(0)
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
         .mount(&app.email_server)
         .await;
 
+    let newsletter_request_payload = serde_json::json!({
+        "title": "Newsletter title",
+        "text": "text content",
+        "html": "<h1>html content</h1>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
     let response = app.post_newsletter(&newsletter_request_payload).await;
-    assert_eq!(response.status().as_u16(), 303);
+    assert_redirect_to(&response, "/admin/newsletters");
 
     app.dispatch_all_emails().await;
+
+    let task = sqlx::query!(
+        r#"SELECT n_retries, execute_after > now() as "in_the_future!" FROM issue_delivery_queue"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(task.n_retries, 1);
+    assert!(task.in_the_future);
+}
+
+#[tokio::test]
+async fn deliveries_are_moved_to_failures_after_max_retries() {
+    let mut app = spawn_app().await;
+    app.issue_delivery.retry_base_delay_millisecond = 0;
+    app.issue_delivery.max_retries = 3;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    when_sending_email()
+        .respond_with(ResponseTemplate::new(500))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_payload = serde_json::json!({
+        "title": "Newsletter title",
+        "text": "text content",
+        "html": "<h1>html content</h1>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletter(&newsletter_request_payload).await;
+    assert_redirect_to(&response, "/admin/newsletters");
+
+    app.dispatch_all_emails().await;
+
+    let n_queued = sqlx::query!(r#"SELECT count(*) as "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+
+    let failure = sqlx::query!("SELECT n_retries, last_error FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(failure.n_retries, 3);
+    assert!(failure.last_error.contains("500"));
+}
+
+#[tokio::test]
+async fn deliveries_are_sent_in_batches() {
+    let mut app = spawn_app().await;
+    app.issue_delivery.batch_size = 2.try_into().unwrap();
+    for _ in 0..3 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+
+    // 3 recipients in batches of 2
+    when_sending_email()
+        .respond_with(postmark_batch_accepted())
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_payload = serde_json::json!({
+        "title": "Newsletter title",
+        "text": "text content",
+        "html": "<h1>html content</h1>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletter(&newsletter_request_payload).await;
+    assert_redirect_to(&response, "/admin/newsletters");
+
+    app.dispatch_all_emails().await;
+
+    let n_sent = sqlx::query!(
+        r#"SELECT count(*) as "count!" FROM issue_delivery_log WHERE outcome = 'sent'"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(n_sent, 3);
+}
+
+#[tokio::test]
+async fn refused_messages_of_a_batch_are_rescheduled_individually() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let rejected = sqlx::query!("SELECT email FROM subscriptions LIMIT 1")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    let rejected_recipient = rejected.clone();
+    when_sending_email()
+        .respond_with(postmark_batch_reply(move |to| to == rejected_recipient))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_payload = serde_json::json!({
+        "title": "Newsletter title",
+        "text": "text content",
+        "html": "<h1>html content</h1>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletter(&newsletter_request_payload).await;
+    assert_redirect_to(&response, "/admin/newsletters");
+
+    app.dispatch_all_emails().await;
+
+    let task = sqlx::query!("SELECT subscriber_email, n_retries FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(task.subscriber_email, rejected);
+    assert_eq!(task.n_retries, 1);
+
+    let log = sqlx::query!("SELECT outcome, error FROM issue_delivery_log ORDER BY outcome")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(log.len(), 2);
+    assert_eq!(log[0].outcome, "failed");
+    assert!(log[0].error.as_deref().unwrap().contains("406"));
+    assert_eq!(log[1].outcome, "sent");
 }
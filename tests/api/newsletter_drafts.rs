@@ -0,0 +1,373 @@
+use crate::helpers::{
+    assert_redirect_to, create_confirmed_subscriber, postmark_batch_accepted, spawn_app, TestApp,
+};
+use sqlx::types::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+// Save a draft and return its id, taken from the redirect to its edit page
+async fn create_draft(app: &TestApp) -> Uuid {
+    let response = app
+        .post_draft(&serde_json::json!({
+            "title": "Draft title",
+            "text": "Draft text",
+            "html": "<h1>Draft html</h1>",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    let location = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    location
+        .strip_prefix("/admin/newsletters/drafts/")
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_drafts() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/admin/newsletters/drafts", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn drafts_are_saved_but_not_delivered() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_issue_id = create_draft(&app).await;
+    app.dispatch_all_emails().await;
+
+    let issue = sqlx::query!(
+        "SELECT published_at, user_id FROM newsletter_issue WHERE newsletter_issue_id = $1",
+        newsletter_issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(issue.published_at.is_none());
+    assert_eq!(issue.user_id, Some(app.test_user.user_id));
+
+    let html = app.get_drafts_html().await;
+    assert!(html.contains("Draft title"));
+    assert!(html.contains(&format!("by {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn drafts_can_be_edited() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_issue_id = create_draft(&app).await;
+
+    let response = app
+        .post_update_draft(
+            newsletter_issue_id,
+            &serde_json::json!({
+                "title": "New <title>",
+                "text": "New text",
+                "html": "<p>New html</p>",
+            }),
+        )
+        .await;
+    assert_redirect_to(
+        &response,
+        &format!("/admin/newsletters/drafts/{}", newsletter_issue_id),
+    );
+
+    let html = app
+        .get_draft(newsletter_issue_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("The draft has been saved."));
+    assert!(html.contains(&format!(
+        r#"value="{}""#,
+        htmlescape::encode_attribute("New <title>")
+    )));
+    assert!(html.contains("&lt;p&gt;New html&lt;/p&gt;"));
+
+    let issue = sqlx::query!(
+        "SELECT updated_at > created_at as \"edited!\" FROM newsletter_issue \
+        WHERE newsletter_issue_id = $1",
+        newsletter_issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(issue.edited);
+}
+
+#[tokio::test]
+async fn drafts_can_be_previewed() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_issue_id = create_draft(&app).await;
+
+    let response = app.get_draft_preview(newsletter_issue_id).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers().get("Content-Security-Policy").unwrap(),
+        "sandbox"
+    );
+    assert_eq!(response.text().await.unwrap(), "<h1>Draft html</h1>");
+}
+
+#[tokio::test]
+async fn drafts_can_be_deleted() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_issue_id = create_draft(&app).await;
+
+    let response = app.post_delete_draft(newsletter_issue_id).await;
+    assert_redirect_to(&response, "/admin/newsletters/drafts");
+
+    assert!(app
+        .get_drafts_html()
+        .await
+        .contains("The draft has been deleted."));
+    assert_eq!(
+        app.get_draft(newsletter_issue_id).await.status().as_u16(),
+        404
+    );
+}
+
+#[tokio::test]
+async fn published_drafts_are_delivered_to_confirmed_subscribers() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let newsletter_issue_id = create_draft(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(postmark_batch_accepted())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_publish_draft(
+            newsletter_issue_id,
+            &serde_json::json!({ "idempotency_key": uuid::Uuid::new_v4().to_string() }),
+        )
+        .await;
+    assert_redirect_to(&response, "/admin/newsletters");
+    assert!(app
+        .get_newsletters_html()
+        .await
+        .contains("The newsletter issue has been published!"));
+
+    app.dispatch_all_emails().await;
+
+    // Not a draft anymore
+    assert_eq!(
+        app.get_draft(newsletter_issue_id).await.status().as_u16(),
+        404
+    );
+}
+
+#[tokio::test]
+async fn drafts_with_a_missing_part_are_not_published() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let response = app
+        .post_draft(&serde_json::json!({
+            "title": "Draft title",
+            "text": "Draft text",
+            "html": "",
+        }))
+        .await;
+    let draft_page = response.headers()["Location"].to_str().unwrap().to_owned();
+    let newsletter_issue_id: Uuid = draft_page
+        .strip_prefix("/admin/newsletters/drafts/")
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    let response = app
+        .post_publish_draft(
+            newsletter_issue_id,
+            &serde_json::json!({ "idempotency_key": uuid::Uuid::new_v4().to_string() }),
+        )
+        .await;
+    assert_redirect_to(&response, &draft_page);
+    let html = app
+        .get_draft(newsletter_issue_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("Write the issue in Markdown, or fill both its text and HTML parts."));
+
+    let n_queued = sqlx::query!(r#"SELECT count(*) as "n!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_queued, 0);
+}
+
+#[tokio::test]
+async fn publishing_a_draft_is_idempotent() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let newsletter_issue_id = create_draft(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(postmark_batch_accepted())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let body = serde_json::json!({ "idempotency_key": uuid::Uuid::new_v4().to_string() });
+    let response = app.post_publish_draft(newsletter_issue_id, &body).await;
+    assert_redirect_to(&response, "/admin/newsletters");
+    let response = app.post_publish_draft(newsletter_issue_id, &body).await;
+    assert_redirect_to(&response, "/admin/newsletters");
+
+    app.dispatch_all_emails().await;
+}
+
+#[tokio::test]
+async fn published_issues_can_not_be_edited_as_drafts() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_issue_id = create_draft(&app).await;
+
+    app.post_publish_draft(
+        newsletter_issue_id,
+        &serde_json::json!({ "idempotency_key": uuid::Uuid::new_v4().to_string() }),
+    )
+    .await;
+
+    let response = app
+        .post_update_draft(
+            newsletter_issue_id,
+            &serde_json::json!({
+                "title": "New title",
+                "text": "New text",
+                "html": "<p>New html</p>",
+            }),
+        )
+        .await;
+    assert_redirect_to(&response, "/admin/newsletters/drafts");
+    assert!(app
+        .get_drafts_html()
+        .await
+        .contains("The draft does not exist anymore."));
+
+    let title = sqlx::query!("SELECT title FROM newsletter_issue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .title;
+    assert_eq!(title, "Draft title");
+}
+
+#[tokio::test]
+async fn test_emails_are_sent_without_touching_the_delivery_queue() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let newsletter_issue_id = create_draft(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_draft_test_email(
+            newsletter_issue_id,
+            &serde_json::json!({ "recipients": "ursula@domain.com,\r\nle_guin@domain.com" }),
+        )
+        .await;
+    assert_redirect_to(
+        &response,
+        &format!("/admin/newsletters/drafts/{}", newsletter_issue_id),
+    );
+
+    let html = app
+        .get_draft(newsletter_issue_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("The test email has been sent to ursula@domain.com, le_guin@domain.com."));
+
+    // The first request is the subscriber's confirmation email
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests[1].body).unwrap();
+    assert_eq!(body["Subject"], "[TEST] Draft title");
+    assert_eq!(body["To"], "ursula@domain.com");
+
+    let n_queued = sqlx::query!(r#"SELECT count(*) as "n!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_queued, 0);
+    assert_eq!(
+        app.get_draft(newsletter_issue_id).await.status().as_u16(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn no_test_email_is_sent_if_an_address_is_invalid() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_issue_id = create_draft(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_draft_test_email(
+            newsletter_issue_id,
+            &serde_json::json!({ "recipients": "ursula@domain.com, <b>not-an-email" }),
+        )
+        .await;
+    assert_redirect_to(
+        &response,
+        &format!("/admin/newsletters/drafts/{}", newsletter_issue_id),
+    );
+
+    let html = app
+        .get_draft(newsletter_issue_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("Invalid email address: &lt;b&gt;not-an-email."));
+}
@@ -0,0 +1,137 @@
+use crate::helpers::{
+    assert_redirect_to, create_confirmed_subscriber, postmark_batch_accepted, spawn_app, TestApp,
+};
+use sqlx::types::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+struct StoredIssue {
+    markdown: Option<String>,
+    text: String,
+    html: String,
+}
+
+async fn stored_issue(app: &TestApp) -> StoredIssue {
+    sqlx::query_as!(
+        StoredIssue,
+        "SELECT markdown, text, html FROM newsletter_issue"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+async fn draft_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+#[tokio::test]
+async fn issues_written_in_markdown_are_sent_with_both_parts() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(postmark_batch_accepted())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let markdown = "# Rust 2.0\n\nRead [the announcement](https://example.com/rust-2).";
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Rust 2.0",
+            "markdown": markdown,
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_redirect_to(&response, "/admin/newsletters");
+
+    let issue = stored_issue(&app).await;
+    assert_eq!(issue.markdown.as_deref(), Some(markdown));
+    // Styled by the stylesheet, inlined
+    assert!(issue.html.contains(r#"<h1 style="line-height: 1.25;"#));
+
+    app.dispatch_all_emails().await;
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests.last().unwrap().body).unwrap();
+    assert!(body[0]["TextBody"].as_str().unwrap().starts_with(
+        "Rust 2.0\n========\n\nRead the announcement [1].\n\n[1] https://example.com/rust-2"
+    ));
+    assert!(body[0]["HtmlBody"].as_str().unwrap().contains(
+        r#"<a href="https://example.com/rust-2" style="color: #1a5fb4;">the announcement</a>"#
+    ));
+}
+
+#[tokio::test]
+async fn parts_filled_in_override_the_markdown() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    app.post_newsletter(&serde_json::json!({
+        "title": "Rust 2.0",
+        "markdown": "Rust 2.0 is *out*",
+        "text": "RUST 2.0 IS OUT",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    }))
+    .await;
+
+    let issue = stored_issue(&app).await;
+    assert_eq!(issue.text, "RUST 2.0 IS OUT");
+    assert!(issue.html.contains("<p>Rust 2.0 is <em>out</em></p>"));
+}
+
+#[tokio::test]
+async fn issues_without_markdown_need_both_parts() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Rust 2.0",
+            "html": "<p>Rust 2.0 is out</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn generated_parts_of_a_draft_follow_its_markdown() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    app.post_draft(&serde_json::json!({
+        "title": "Rust 2.0",
+        "markdown": "Rust 2.0 is *near*",
+        "html": "<p>Custom html</p>",
+    }))
+    .await;
+    let draft_id = draft_id(&app).await;
+
+    // Only the part typed in by the admin is shown in the form
+    let html = app.get_draft(draft_id).await.text().await.unwrap();
+    assert!(html.contains(r#"<textarea name="markdown">Rust 2.0 is *near*</textarea>"#));
+    assert!(html.contains(r#"name="text"></textarea>"#));
+    assert!(html.contains(r#"name="html">&lt;p&gt;Custom html&lt;/p&gt;</textarea>"#));
+
+    app.post_update_draft(
+        draft_id,
+        &serde_json::json!({
+            "title": "Rust 2.0",
+            "markdown": "Rust 2.0 is *out*",
+            "html": "<p>Custom html</p>",
+        }),
+    )
+    .await;
+
+    let issue = stored_issue(&app).await;
+    assert_eq!(issue.text, "Rust 2.0 is out");
+    assert_eq!(issue.html, "<p>Custom html</p>");
+}
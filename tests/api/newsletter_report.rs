@@ -0,0 +1,127 @@
+use crate::helpers::{assert_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+use sqlx::types::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish_newsletter(app: &TestApp) -> Uuid {
+    let newsletter_request_payload = serde_json::json!({
+        "title": "Newsletter title",
+        "text": "text content",
+        "html": "<h1>html content</h1>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletter(&newsletter_request_payload).await;
+    assert_redirect_to(&response, "/admin/newsletters");
+
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_delivery_report() {
+    let app = spawn_app().await;
+
+    let response = app.get_newsletter_report(Uuid::new_v4()).await;
+
+    assert_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn delivery_report_of_an_unknown_issue_is_404() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_newsletter_report(Uuid::new_v4()).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn successful_deliveries_are_logged_with_the_provider_message_id() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!([{
+                "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817",
+                "ErrorCode": 0,
+                "Message": "OK"
+            }])),
+        )
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_issue_id = publish_newsletter(&app).await;
+
+    let html = app
+        .get_newsletter_report(newsletter_issue_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("Sent: 0"));
+    assert!(html.contains("Pending: 1"));
+
+    app.dispatch_all_emails().await;
+
+    let log = sqlx::query!("SELECT outcome, provider_message_id FROM issue_delivery_log")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(log.outcome, "sent");
+    assert_eq!(
+        log.provider_message_id.as_deref(),
+        Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817")
+    );
+
+    let html = app
+        .get_newsletter_report(newsletter_issue_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("Sent: 1"));
+    assert!(html.contains("Pending: 0"));
+}
+
+#[tokio::test]
+async fn delivery_report_lists_failed_recipients() {
+    let mut app = spawn_app().await;
+    app.issue_delivery.retry_base_delay_millisecond = 0;
+    app.issue_delivery.max_retries = 2;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_issue_id = publish_newsletter(&app).await;
+    app.dispatch_all_emails().await;
+
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+
+    let html = app
+        .get_newsletter_report(newsletter_issue_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("Failed attempts: 2"));
+    assert!(html.contains("Failed recipients: 1"));
+    assert!(html.contains(&email));
+}
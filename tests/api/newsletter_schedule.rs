@@ -0,0 +1,247 @@
+use crate::helpers::{
+    assert_redirect_to, create_confirmed_subscriber, postmark_batch_accepted, spawn_app, TestApp,
+};
+use chrono::{TimeZone, Utc};
+use sqlx::types::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn schedule_newsletter(app: &TestApp, scheduled_at: &str, timezone: &str) -> Uuid {
+    let newsletter_request_payload = serde_json::json!({
+        "title": "Newsletter title",
+        "text": "text content",
+        "html": "<h1>html content</h1>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "scheduled_at": scheduled_at,
+        "timezone": timezone,
+    });
+    let response = app.post_newsletter(&newsletter_request_payload).await;
+    assert_redirect_to(&response, "/admin/newsletters");
+
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+// Pretend that the time of the issue has come
+async fn make_issue_due(app: &TestApp, newsletter_issue_id: Uuid) {
+    sqlx::query!(
+        "UPDATE newsletter_issue SET scheduled_at = now() - interval '1 minute' \
+        WHERE newsletter_issue_id = $1",
+        newsletter_issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+async fn n_queued_deliveries(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT count(*) as "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_not_delivered_before_their_time() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    schedule_newsletter(&app, "2099-01-01T09:00", "UTC").await;
+    assert!(app
+        .get_newsletters_html()
+        .await
+        .contains("The newsletter issue has been scheduled for"));
+
+    assert_eq!(app.publish_scheduled_issues().await, 0);
+    app.dispatch_all_emails().await;
+    assert_eq!(n_queued_deliveries(&app).await, 0);
+
+    let issue = sqlx::query!("SELECT published_at, scheduled_at FROM newsletter_issue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(issue.published_at.is_none());
+    assert!(issue.scheduled_at.is_some());
+}
+
+#[tokio::test]
+async fn the_scheduled_time_is_stored_in_utc() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    schedule_newsletter(&app, "2099-07-01T09:00", "America/New_York").await;
+
+    let issue = sqlx::query!("SELECT scheduled_at FROM newsletter_issue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        issue.scheduled_at,
+        Some(Utc.with_ymd_and_hms(2099, 7, 1, 13, 0, 0).unwrap())
+    );
+}
+
+#[tokio::test]
+async fn invalid_schedules_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let test_cases = vec![
+        ("2099-01-01T09:00", "Not/A_Timezone", "unknown timezone"),
+        ("next tuesday", "UTC", "invalid date"),
+        ("2001-01-01T09:00", "UTC", "time in the past"),
+    ];
+
+    for (scheduled_at, timezone, message) in test_cases {
+        let response = app
+            .post_newsletter(&serde_json::json!({
+                "title": "Newsletter title",
+                "text": "text content",
+                "html": "<h1>html content</h1>",
+                "idempotency_key": uuid::Uuid::new_v4().to_string(),
+                "scheduled_at": scheduled_at,
+                "timezone": timezone,
+            }))
+            .await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API does not fail with 400 Bad Request for a {}.",
+            message
+        );
+    }
+}
+
+#[tokio::test]
+async fn due_issues_are_published_by_the_scheduler() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(postmark_batch_accepted())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_issue_id = schedule_newsletter(&app, "2099-01-01T09:00", "UTC").await;
+    make_issue_due(&app, newsletter_issue_id).await;
+
+    assert_eq!(app.publish_scheduled_issues().await, 1);
+    assert_eq!(n_queued_deliveries(&app).await, 1);
+    // Already published, the next tick does nothing
+    assert_eq!(app.publish_scheduled_issues().await, 0);
+
+    app.dispatch_all_emails().await;
+
+    let issue = sqlx::query!("SELECT published_at FROM newsletter_issue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(issue.published_at.is_some());
+}
+
+#[tokio::test]
+async fn scheduled_issues_can_be_rescheduled() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let newsletter_issue_id = schedule_newsletter(&app, "2099-01-01T09:00", "UTC").await;
+
+    let response = app
+        .post_reschedule_issue(
+            newsletter_issue_id,
+            &serde_json::json!({
+                "scheduled_at": "2099-02-01T10:30",
+                "timezone": "Europe/Paris",
+            }),
+        )
+        .await;
+    assert_redirect_to(&response, "/admin/newsletters");
+    assert!(app
+        .get_newsletters_html()
+        .await
+        .contains("The newsletter issue has been rescheduled for"));
+
+    let issue = sqlx::query!("SELECT scheduled_at FROM newsletter_issue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        issue.scheduled_at,
+        Some(Utc.with_ymd_and_hms(2099, 2, 1, 9, 30, 0).unwrap())
+    );
+}
+
+#[tokio::test]
+async fn scheduled_issues_can_be_cancelled() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let newsletter_issue_id = schedule_newsletter(&app, "2099-01-01T09:00", "UTC").await;
+
+    let response = app.post_cancel_issue(newsletter_issue_id).await;
+    assert_redirect_to(&response, "/admin/newsletters");
+
+    let n_issues = sqlx::query!(r#"SELECT count(*) as "count!" FROM newsletter_issue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+    assert_eq!(app.publish_scheduled_issues().await, 0);
+}
+
+#[tokio::test]
+async fn published_issues_can_not_be_rescheduled_or_cancelled() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let newsletter_issue_id = schedule_newsletter(&app, "2099-01-01T09:00", "UTC").await;
+    make_issue_due(&app, newsletter_issue_id).await;
+    app.publish_scheduled_issues().await;
+
+    let response = app.post_cancel_issue(newsletter_issue_id).await;
+    assert_redirect_to(&response, "/admin/newsletters");
+    assert!(app
+        .get_newsletters_html()
+        .await
+        .contains("The newsletter issue is not scheduled anymore."));
+
+    let response = app
+        .post_reschedule_issue(
+            newsletter_issue_id,
+            &serde_json::json!({
+                "scheduled_at": "2099-02-01T10:30",
+                "timezone": "UTC",
+            }),
+        )
+        .await;
+    assert_redirect_to(&response, "/admin/newsletters");
+    assert!(app
+        .get_newsletters_html()
+        .await
+        .contains("The newsletter issue is not scheduled anymore."));
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_cancel_a_scheduled_issue() {
+    let app = spawn_app().await;
+
+    let response = app.post_cancel_issue(Uuid::new_v4()).await;
+
+    assert_redirect_to(&response, "/login");
+}
@@ -0,0 +1,257 @@
+use crate::helpers::{create_confirmed_subscriber, postmark_batch_accepted, spawn_app, TestApp};
+use newsletter::configuration::{RssToEmailMode, RssToEmailSettings};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+struct Blog {
+    server: MockServer,
+}
+
+impl Blog {
+    async fn start() -> Self {
+        Self {
+            server: MockServer::start().await,
+        }
+    }
+
+    fn settings(&self, feed: &str, mode: RssToEmailMode) -> RssToEmailSettings {
+        RssToEmailSettings {
+            feed_url: format!("{}/{}", self.server.uri(), feed),
+            poll_interval_second: 600,
+            mode,
+            show_in_archive: false,
+        }
+    }
+
+    // Only the latest content of the feed is served
+    async fn serve(&self, feed: &str, body: String) {
+        self.server.reset().await;
+        Mock::given(path(format!("/{}", feed)))
+            .and(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "application/xml"))
+            .mount(&self.server)
+            .await;
+    }
+}
+
+// Most recent posts first, as blogs do
+fn rss(posts: &[(&str, &str)]) -> String {
+    let mut items = String::new();
+    for (guid, title) in posts.iter().rev() {
+        items.push_str(&format!(
+            r#"<item>
+                <title>{title}</title>
+                <link>https://blog.example.com/{guid}</link>
+                <guid>{guid}</guid>
+                <description>&lt;p&gt;The content of {title}&lt;/p&gt;</description>
+            </item>"#
+        ));
+    }
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+        <rss version="2.0"><channel><title>Blog</title>{items}</channel></rss>"#
+    )
+}
+
+async fn issue_titles(app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT title FROM newsletter_issue ORDER BY created_at")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.title)
+        .collect()
+}
+
+#[tokio::test]
+async fn posts_already_in_the_feed_are_skipped_on_the_first_poll() {
+    let app = spawn_app().await;
+    let blog = Blog::start().await;
+    let settings = blog.settings("feed.xml", RssToEmailMode::Publish);
+    blog.serve("feed.xml", rss(&[("post-1", "First post")]))
+        .await;
+
+    assert_eq!(app.poll_feed(&settings).await.unwrap(), 0);
+    assert_eq!(app.poll_feed(&settings).await.unwrap(), 0);
+
+    assert!(issue_titles(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn new_posts_become_drafts() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let blog = Blog::start().await;
+    let settings = blog.settings("feed.xml", RssToEmailMode::Draft);
+    blog.serve("feed.xml", rss(&[("post-1", "First post")]))
+        .await;
+    app.poll_feed(&settings).await.unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    blog.serve(
+        "feed.xml",
+        rss(&[
+            ("post-1", "First post"),
+            ("post-2", "Second post"),
+            ("post-3", "Third post"),
+        ]),
+    )
+    .await;
+    assert_eq!(app.poll_feed(&settings).await.unwrap(), 2);
+    // Each post is only turned into an issue once
+    assert_eq!(app.poll_feed(&settings).await.unwrap(), 0);
+    app.dispatch_all_emails().await;
+
+    // In the order they were published
+    assert_eq!(issue_titles(&app).await, vec!["Second post", "Third post"]);
+    let issue = sqlx::query!(
+        "SELECT html, text, published_at FROM newsletter_issue WHERE title = 'Second post'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(issue.published_at.is_none());
+    assert!(issue.html.contains("<p>The content of Second post</p>"));
+    assert!(issue
+        .html
+        .contains(r#"href="https://blog.example.com/post-2""#));
+    assert!(issue.text.contains("The content of Second post"));
+    assert!(issue
+        .text
+        .contains("Read it on the blog: https://blog.example.com/post-2"));
+
+    app.test_user.login(&app).await;
+    assert!(app.get_drafts_html().await.contains("Third post"));
+}
+
+#[tokio::test]
+async fn new_posts_are_delivered_straight_away_in_publish_mode() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let blog = Blog::start().await;
+    let settings = blog.settings("feed.xml", RssToEmailMode::Publish);
+    blog.serve("feed.xml", rss(&[])).await;
+    app.poll_feed(&settings).await.unwrap();
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(postmark_batch_accepted())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    blog.serve("feed.xml", rss(&[("post-1", "First post")]))
+        .await;
+    assert_eq!(app.poll_feed(&settings).await.unwrap(), 1);
+    app.dispatch_all_emails().await;
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests.last().unwrap().body).unwrap();
+    assert_eq!(body[0]["Subject"], "First post");
+}
+
+#[tokio::test]
+async fn atom_feeds_are_supported() {
+    let app = spawn_app().await;
+    let blog = Blog::start().await;
+    let settings = blog.settings("atom.xml", RssToEmailMode::Draft);
+    let atom = |entries: &str| {
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+            <feed xmlns="http://www.w3.org/2005/Atom">
+                <title>Blog</title>
+                <id>urn:uuid:60a76c80-d399-11d9-b93C-0003939e0af6</id>
+                <updated>2026-10-18T12:00:00Z</updated>
+                {entries}
+            </feed>"#
+        )
+    };
+    blog.serve("atom.xml", atom("")).await;
+    app.poll_feed(&settings).await.unwrap();
+
+    blog.serve(
+        "atom.xml",
+        atom(
+            r#"<entry>
+                <title>Atom post</title>
+                <link href="https://blog.example.com/atom-post"/>
+                <id>urn:uuid:1225c695-cfb8-4ebb-aaaa-80da344efa6a</id>
+                <updated>2026-10-18T12:00:00Z</updated>
+                <content type="html">&lt;p&gt;Atom content&lt;/p&gt;</content>
+            </entry>"#,
+        ),
+    )
+    .await;
+    assert_eq!(app.poll_feed(&settings).await.unwrap(), 1);
+
+    let issue = sqlx::query!("SELECT title, html FROM newsletter_issue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.title, "Atom post");
+    assert!(issue.html.contains("<p>Atom content</p>"));
+}
+
+#[tokio::test]
+async fn relative_links_of_posts_are_resolved_against_the_feed() {
+    let app = spawn_app().await;
+    let blog = Blog::start().await;
+    let settings = blog.settings("feed.xml", RssToEmailMode::Draft);
+    blog.serve("feed.xml", rss(&[])).await;
+    app.poll_feed(&settings).await.unwrap();
+
+    blog.serve(
+        "feed.xml",
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+        <rss version="2.0"><channel><title>Blog</title>
+            <item>
+                <title>Relative post</title>
+                <link>/posts/relative</link>
+                <guid>relative</guid>
+                <description>&lt;img src="cat.png"&gt;</description>
+            </item>
+        </channel></rss>"#
+            .into(),
+    )
+    .await;
+    assert_eq!(app.poll_feed(&settings).await.unwrap(), 1);
+
+    let issue = sqlx::query!("SELECT html, text FROM newsletter_issue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let post_url = format!("{}/posts/relative", blog.server.uri());
+    assert!(issue.html.contains(&format!(r#"href="{}""#, post_url)));
+    assert!(issue
+        .html
+        .contains(&format!(r#"src="{}/posts/cat.png""#, blog.server.uri())));
+    assert!(issue
+        .text
+        .contains(&format!("Read it on the blog: {}", post_url)));
+}
+
+#[tokio::test]
+async fn an_unreachable_feed_is_an_error() {
+    let app = spawn_app().await;
+    let blog = Blog::start().await;
+    let settings = blog.settings("feed.xml", RssToEmailMode::Draft);
+    Mock::given(path("/feed.xml"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&blog.server)
+        .await;
+
+    assert!(app.poll_feed(&settings).await.is_err());
+
+    // The feed is not considered polled yet
+    let n_feeds = sqlx::query!(r#"SELECT count(*) as "n!" FROM rss_feeds"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_feeds, 0);
+}
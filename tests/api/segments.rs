@@ -0,0 +1,318 @@
+use crate::helpers::{assert_redirect_to, spawn_app, subscribe_with, TestApp};
+use sqlx::types::Uuid;
+
+// Subscribe with `tags`, click the confirmation link and return the subscriber id
+async fn subscribe(app: &TestApp, email: &str, tags: &str) -> Uuid {
+    let link = subscribe_with(
+        app,
+        &serde_json::json!({ "name": "Ursula", "email": email, "tags": tags }),
+    )
+    .await;
+    reqwest::get(link.html_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+async fn tags_of(app: &TestApp, subscriber_id: Uuid) -> Vec<String> {
+    sqlx::query!(
+        "SELECT tag FROM subscriber_tags WHERE subscriber_id = $1 ORDER BY tag",
+        subscriber_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.tag)
+    .collect()
+}
+
+// Create a segment as the logged in admin and return its id
+async fn create_segment(app: &TestApp, segment: serde_json::Value) -> Uuid {
+    let response = app.post_segment(&segment).await;
+    assert_redirect_to(&response, "/admin/segments");
+
+    sqlx::query!(
+        "SELECT segment_id FROM segments WHERE name = $1",
+        segment["name"].as_str().unwrap()
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .segment_id
+}
+
+async fn queued_emails(app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue ORDER BY subscriber_email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.subscriber_email)
+        .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_segments() {
+    let app = spawn_app().await;
+
+    let response = app.get_segments().await;
+    assert_redirect_to(&response, "/login");
+    let response = app
+        .post_segment(&serde_json::json!({ "name": "Rust fans", "include_tags": "rust" }))
+        .await;
+    assert_redirect_to(&response, "/login");
+    let response = app.post_subscriber_tags(Uuid::new_v4(), "rust").await;
+    assert_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_subscribe_form_tags_new_subscribers() {
+    let app = spawn_app().await;
+
+    let id = subscribe(&app, "ursula@example.com", "Rust, beta,rust").await;
+
+    assert_eq!(tags_of(&app, id).await, vec!["beta", "rust"]);
+}
+
+#[tokio::test]
+async fn invalid_tags_are_rejected_by_the_subscribe_form() {
+    let app = spawn_app().await;
+
+    let body = "name=Ursula&email=ursula%40example.com&tags=not%20valid";
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn the_subscribe_page_carries_the_tags_of_its_url() {
+    let app = spawn_app().await;
+
+    let html = app
+        .api_client
+        .get(format!("{}/subscription?tags=rust", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert!(html.contains(r#"<input type="hidden" name="tags" value="rust">"#));
+}
+
+#[tokio::test]
+async fn the_admin_can_replace_the_tags_of_a_subscriber() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let id = subscribe(&app, "ursula@example.com", "rust,beta").await;
+
+    let response = app.post_subscriber_tags(id, "go, rust").await;
+    assert_redirect_to(&response, &format!("/admin/subscribers/{}", id));
+
+    assert_eq!(tags_of(&app, id).await, vec!["go", "rust"]);
+    let html = app.get_subscriber(id).await.text().await.unwrap();
+    assert!(html.contains("The tags have been saved."));
+    assert!(html.contains("<tr><th>Tags</th><td>go, rust</td></tr>"));
+
+    app.post_subscriber_tags(id, "c++").await;
+    let html = app.get_subscriber(id).await.text().await.unwrap();
+    assert!(html.contains("c++ is not a valid tag"));
+    assert_eq!(tags_of(&app, id).await, vec!["go", "rust"]);
+}
+
+#[tokio::test]
+async fn segments_can_be_created_with_a_unique_name() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    create_segment(
+        &app,
+        serde_json::json!({
+            "name": "Rust fans",
+            "include_tags": "rust",
+            "exclude_tags": "beta",
+            "status": "confirmed",
+            "subscribed_from": "2026-01-01",
+        }),
+    )
+    .await;
+    let html = app.get_segments().await.text().await.unwrap();
+    assert!(html.contains("The Rust fans segment has been created."));
+    assert!(html.contains("tagged rust, not tagged beta, confirmed, subscribed from 2026-01-01"));
+
+    app.post_segment(&serde_json::json!({ "name": "Rust fans" }))
+        .await;
+    let html = app.get_segments().await.text().await.unwrap();
+    assert!(html.contains("Rust fans is already the name of a segment."));
+
+    app.post_segment(&serde_json::json!({ "name": "VIP", "status": "vip" }))
+        .await;
+    let html = app.get_segments().await.text().await.unwrap();
+    assert!(html.contains("vip is not a subscriber status"));
+}
+
+#[tokio::test]
+async fn issues_sent_to_a_segment_are_only_enqueued_for_its_members() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    subscribe(&app, "rust@example.com", "rust").await;
+    subscribe(&app, "beta@example.com", "rust,beta").await;
+    subscribe(&app, "go@example.com", "go").await;
+    let segment_id = create_segment(
+        &app,
+        serde_json::json!({ "name": "Rust fans", "include_tags": "rust", "exclude_tags": "beta" }),
+    )
+    .await;
+
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Rust 2.0",
+            "text": "Rust 2.0 is out",
+            "html": "<p>Rust 2.0 is out</p>",
+            "segment_id": segment_id.to_string(),
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    assert_eq!(queued_emails(&app).await, vec!["rust@example.com"]);
+}
+
+#[tokio::test]
+async fn segments_filter_on_the_subscription_date() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let old_id = subscribe(&app, "old@example.com", "").await;
+    subscribe(&app, "new@example.com", "").await;
+    sqlx::query!(
+        "UPDATE subscriptions SET subscription_at = '2020-06-01' WHERE id = $1",
+        old_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let segment_id = create_segment(
+        &app,
+        serde_json::json!({ "name": "Newcomers", "subscribed_from": "2021-01-01" }),
+    )
+    .await;
+
+    app.post_newsletter(&serde_json::json!({
+        "title": "Welcome",
+        "text": "Welcome",
+        "html": "<p>Welcome</p>",
+        "segment_id": segment_id.to_string(),
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    }))
+    .await;
+
+    assert_eq!(queued_emails(&app).await, vec!["new@example.com"]);
+}
+
+#[tokio::test]
+async fn issues_for_an_unknown_segment_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Rust 2.0",
+            "text": "Rust 2.0 is out",
+            "html": "<p>Rust 2.0 is out</p>",
+            "segment_id": uuid::Uuid::new_v4().to_string(),
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn the_recipients_of_an_issue_can_be_counted_before_sending_it() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    subscribe(&app, "rust@example.com", "rust").await;
+    subscribe(&app, "go@example.com", "go").await;
+    let segment_id = create_segment(
+        &app,
+        serde_json::json!({ "name": "Rust fans", "include_tags": "rust" }),
+    )
+    .await;
+
+    let response = app.get_issue_recipients("segment_id=").await;
+    assert_redirect_to(&response, "/admin/newsletters");
+    let html = app.get_newsletters_html().await;
+    assert!(html.contains("An issue sent now would reach 2 subscriber(s)."));
+
+    app.get_issue_recipients(&format!("segment_id={}", segment_id))
+        .await;
+    let html = app.get_newsletters_html().await;
+    assert!(html.contains("An issue sent now would reach 1 subscriber(s)."));
+    assert!(html.contains(&format!(
+        r#"<option value="{}">Rust fans</option>"#,
+        segment_id
+    )));
+
+    // Nothing has been sent
+    assert!(queued_emails(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn drafts_keep_their_segment_until_published() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    subscribe(&app, "rust@example.com", "rust").await;
+    subscribe(&app, "go@example.com", "go").await;
+    let segment_id = create_segment(
+        &app,
+        serde_json::json!({ "name": "Rust fans", "include_tags": "rust" }),
+    )
+    .await;
+
+    app.post_draft(&serde_json::json!({
+        "title": "Rust 2.0",
+        "text": "Rust 2.0 is out",
+        "html": "<p>Rust 2.0 is out</p>",
+        "segment_id": segment_id.to_string(),
+    }))
+    .await;
+    let draft_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+
+    // Updates without a `segment_id` leave it as is
+    app.post_update_draft(
+        draft_id,
+        &serde_json::json!({
+            "title": "Rust 2.0 is out",
+            "text": "Rust 2.0 is out",
+            "html": "<p>Rust 2.0 is out</p>",
+        }),
+    )
+    .await;
+    let html = app.get_draft(draft_id).await.text().await.unwrap();
+    assert!(html.contains(&format!(
+        r#"<option value="{}" selected>Rust fans</option>"#,
+        segment_id
+    )));
+    assert!(html.contains("Publishing now would send this draft to 1 subscriber(s)."));
+
+    app.post_publish_draft(
+        draft_id,
+        &serde_json::json!({ "idempotency_key": uuid::Uuid::new_v4().to_string() }),
+    )
+    .await;
+
+    assert_eq!(queued_emails(&app).await, vec!["rust@example.com"]);
+}
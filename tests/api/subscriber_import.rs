@@ -0,0 +1,255 @@
+use crate::helpers::{
+    assert_redirect_to, create_unconfirmed_subscriber, postmark_batch_accepted, spawn_app, TestApp,
+};
+use newsletter::subscriber_import::ImportMode;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn statuses(app: &TestApp) -> Vec<(String, String, String)> {
+    sqlx::query!("SELECT email, name, status FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.email, r.name, r.status))
+        .collect()
+}
+
+fn location(response: &reqwest::Response) -> String {
+    response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_owned()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_import_subscribers() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_subscriber_import("confirmed", "email,name\nursula@example.com,Ursula\n")
+        .await;
+
+    assert_redirect_to(&response, "/login");
+    assert!(statuses(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn valid_rows_are_imported_and_invalid_ones_reported() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let csv = "\
+Name,Email,Source
+Ursula,ursula@example.com,old provider
+Octavia,not-an-email,old provider
+<b>,le.guin@example.com,old provider
+Ursula again,ursula@example.com,old provider
+Octavia,octavia@example.com,old provider
+";
+    let response = app.post_subscriber_import("confirmed", csv).await;
+    assert_eq!(response.status().as_u16(), 303);
+    let location = location(&response);
+    assert!(location.starts_with("/admin/subscribers/imports/"));
+
+    let html = app
+        .api_client
+        .get(format!("{}{}", &app.address, location))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("2 of the 5 rows have been imported."));
+    assert!(html.contains("3 rows need attention"));
+
+    assert_eq!(
+        statuses(&app).await,
+        vec![
+            (
+                "octavia@example.com".into(),
+                "Octavia".into(),
+                "confirmed".into()
+            ),
+            (
+                "ursula@example.com".into(),
+                "Ursula".into(),
+                "confirmed".into()
+            ),
+        ]
+    );
+
+    let response = app.get_subscriber_import_errors(&location).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "text/csv; charset=utf-8"
+    );
+    let report = response.text().await.unwrap();
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0], "line,email,error");
+    assert!(lines[1].starts_with("3,not-an-email,"));
+    assert!(lines[2].starts_with("4,le.guin@example.com,"));
+    assert_eq!(lines[3], "5,ursula@example.com,Duplicate of an earlier row");
+}
+
+#[tokio::test]
+async fn pending_imports_send_confirmation_emails() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(postmark_batch_accepted())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let csv = "email,name\nursula@example.com,Ursula\noctavia@example.com,Octavia\n";
+    let response = app.post_subscriber_import("pending", csv).await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    let statuses = statuses(&app).await;
+    assert!(statuses.iter().all(|(_, _, s)| s == "pending_confirmation"));
+
+    let request = &app.email_server.received_requests().await.unwrap()[0];
+    let messages: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0]["To"], "ursula@example.com");
+
+    // The links of the batch work like the ones sent by the form
+    let text = messages[1]["TextBody"].as_str().unwrap();
+    let link = linkify::LinkFinder::new()
+        .links(text)
+        .next()
+        .unwrap()
+        .as_str()
+        .to_owned();
+    let mut link = reqwest::Url::parse(&link).unwrap();
+    link.set_port(Some(app.port)).unwrap();
+    let response = reqwest::get(link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let saved =
+        sqlx::query!("SELECT status FROM subscriptions WHERE email = 'octavia@example.com'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn importing_never_resubscribes_someone_who_unsubscribed() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let email = sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed' RETURNING email")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+
+    let report = app
+        .import_subscribers(
+            ImportMode::Confirmed,
+            &format!("email,name\n{},Someone\n", email),
+        )
+        .await;
+
+    assert_eq!(report.n_imported, 0);
+    assert_eq!(report.n_failed, 1);
+    let saved = statuses(&app).await;
+    assert_eq!(saved[0].2, "unsubscribed");
+}
+
+#[tokio::test]
+async fn importing_as_confirmed_confirms_known_pending_subscribers() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+
+    let report = app
+        .import_subscribers(
+            ImportMode::Confirmed,
+            &format!("email,name\n{},New Name\n", email),
+        )
+        .await;
+
+    assert_eq!(report.n_imported, 1);
+    assert_eq!(
+        statuses(&app).await,
+        vec![(email, "New Name".into(), "confirmed".into())]
+    );
+}
+
+#[tokio::test]
+async fn large_files_are_imported_in_batches() {
+    let app = spawn_app().await;
+    let mut csv = String::from("email,name\n");
+    for i in 0..1234 {
+        csv.push_str(&format!("reader{}@example.com,Reader {}\n", i, i));
+    }
+
+    let report = app.import_subscribers(ImportMode::Confirmed, &csv).await;
+
+    assert_eq!(report.n_rows, 1234);
+    assert_eq!(report.n_imported, 1234);
+    assert!(report.errors.is_empty());
+    let n_subscribers = sqlx::query!(
+        r#"SELECT count(*) as "count!" FROM subscriptions WHERE status = 'confirmed'"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(n_subscribers, 1234);
+}
+
+#[tokio::test]
+async fn rows_repeated_in_a_later_batch_are_reported_as_duplicates() {
+    let app = spawn_app().await;
+    let mut csv = String::from("email,name\n");
+    for i in 0..600 {
+        csv.push_str(&format!("reader{}@example.com,Reader {}\n", i, i));
+    }
+    csv.push_str("reader0@example.com,Renamed\n");
+
+    let report = app.import_subscribers(ImportMode::Confirmed, &csv).await;
+
+    assert_eq!(report.n_imported, 600);
+    assert_eq!(report.n_failed, 1);
+    assert_eq!(report.errors[0].line, 602);
+    assert_eq!(report.errors[0].error, "Duplicate of an earlier row");
+    let name = sqlx::query!("SELECT name FROM subscriptions WHERE email = 'reader0@example.com'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .name;
+    assert_eq!(name, "Reader 0");
+}
+
+#[tokio::test]
+async fn a_file_without_an_email_column_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_subscriber_import("confirmed", "name,mail\nUrsula,ursula@example.com\n")
+        .await;
+    assert_redirect_to(&response, "/admin/subscribers");
+
+    let html = app.get_subscribers("").await.text().await.unwrap();
+    assert!(html.contains("The file has no `email` column."));
+    assert!(statuses(&app).await.is_empty());
+}
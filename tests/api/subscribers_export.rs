@@ -0,0 +1,120 @@
+use crate::helpers::{assert_redirect_to, spawn_app, TestApp};
+
+async fn insert_subscribers(app: &TestApp, n: i32, status: &str) {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscription_at, status)
+        SELECT
+            gen_random_uuid(),
+            $2 || i || '@example.com',
+            'Reader ' || i,
+            now() - make_interval(secs => i),
+            $2
+        FROM generate_series(1, $1) AS i
+    "#,
+        n,
+        status
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_export_the_subscribers() {
+    let app = spawn_app().await;
+
+    let response = app.get_subscribers_export("format=csv").await;
+
+    assert_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_csv() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscription_at, status)
+        VALUES (gen_random_uuid(), 'ursula@example.com', 'Le Guin, Ursula', now(), 'confirmed')
+    "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    insert_subscribers(&app, 2, "unsubscribed").await;
+
+    let response = app.get_subscribers_export("format=csv").await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "text/csv; charset=utf-8"
+    );
+    assert!(response
+        .headers()
+        .get("Content-Disposition")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .contains("subscribers.csv"));
+    let csv = response.text().await.unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0], "id,email,name,status,subscription_at");
+    // Oldest first
+    assert!(lines[1].contains("unsubscribed2@example.com"));
+    assert!(lines[3].contains(r#"ursula@example.com,"Le Guin, Ursula",confirmed,"#));
+
+    let csv = app
+        .get_subscribers_export("format=csv&status=confirmed")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(csv.lines().count(), 2);
+}
+
+#[tokio::test]
+async fn large_lists_are_exported_as_a_single_json_array() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    // More than one batch
+    insert_subscribers(&app, 2345, "confirmed").await;
+    insert_subscribers(&app, 10, "pending_confirmation").await;
+
+    let response = app
+        .get_subscribers_export("format=json&status=confirmed")
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let subscribers: Vec<serde_json::Value> = response.json().await.unwrap();
+
+    assert_eq!(subscribers.len(), 2345);
+    assert_eq!(subscribers[0]["email"], "confirmed2345@example.com");
+    assert_eq!(subscribers[2344]["email"], "confirmed1@example.com");
+    assert!(subscribers.iter().all(|s| s["status"] == "confirmed"));
+    let ids: std::collections::HashSet<_> =
+        subscribers.iter().map(|s| s["id"].to_string()).collect();
+    assert_eq!(ids.len(), 2345);
+}
+
+#[tokio::test]
+async fn an_empty_export_is_an_empty_json_array() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_subscribers_export("format=json").await;
+    let subscribers: Vec<serde_json::Value> = response.json().await.unwrap();
+
+    assert!(subscribers.is_empty());
+}
+
+#[tokio::test]
+async fn unknown_formats_and_statuses_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    for query in ["format=xml", "format=csv&status=vip"] {
+        let response = app.get_subscribers_export(query).await;
+        assert_eq!(response.status().as_u16(), 400, "{}", query);
+    }
+}
@@ -19,6 +19,8 @@ async fn subscribe_redirect_for_valid_form_data() {
 
     assert_eq!(303, response.status().as_u16());
     assert_redirect_to(&response, "/subscription");
+    // Sent in the background
+    test_app.email_requests(1).await;
 }
 
 #[tokio::test]
@@ -38,6 +40,8 @@ async fn subscribe_persist_the_new_subscriber() {
 
     assert_eq!(303, response.status().as_u16());
     assert_redirect_to(&response, "/subscription");
+    // Sent in the background
+    test_app.email_requests(1).await;
 
     let saved = sqlx::query!("SELECT email, name, status from subscriptions",)
         .fetch_one(&test_app.db_pool)
@@ -107,7 +111,7 @@ async fn subscribe_sends_a_confirmation_email_when_valid_data() {
 
     test_app.post_subscriptions(body.into()).await;
 
-    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let email_request = &test_app.email_requests(1).await[0];
     let confirmation_links = test_app.get_confirmation_url(email_request).await;
     assert_eq!(confirmation_links.html_link, confirmation_links.text_link);
 }
@@ -126,3 +130,156 @@ async fn subscribe_fails_if_there_if_fatal_db_error() {
 
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn failing_to_send_the_confirmation_email_gets_the_same_answer() {
+    let test_app = spawn_app().await;
+    let body = "name=Kunal%20Singh&email=kunal%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app.post_subscriptions(body.into()).await;
+
+    // Same answer as for a confirmed subscriber, who gets no email
+    assert_redirect_to(&response, "/subscription");
+    test_app.email_requests(1).await;
+}
+
+#[tokio::test]
+async fn subscribing_again_while_pending_sends_a_new_confirmation_email() {
+    let test_app = spawn_app().await;
+    let body = "name=Kunal%20Singh&email=kunal%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app.post_subscriptions(body.into()).await;
+    test_app.email_requests(1).await;
+    let response = test_app.post_subscriptions(body.into()).await;
+    assert_redirect_to(&response, "/subscription");
+
+    let email_requests = test_app.email_requests(2).await;
+    let first_link = test_app.get_confirmation_url(&email_requests[0]).await;
+    let second_link = test_app.get_confirmation_url(&email_requests[1]).await;
+    assert_ne!(first_link.html_link, second_link.html_link);
+
+    // Only the latest link confirms the subscription
+    let response = reqwest::get(first_link.html_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    let response = reqwest::get(second_link.html_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].status, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribing_again_while_pending_keeps_the_name() {
+    let test_app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app
+        .post_subscriptions("name=Kunal%20Singh&email=kunal%40gmail.com".into())
+        .await;
+    test_app.email_requests(1).await;
+    let response = test_app
+        .post_subscriptions("name=Someone%20Else&email=kunal%40gmail.com".into())
+        .await;
+    assert_redirect_to(&response, "/subscription");
+    test_app.email_requests(2).await;
+
+    let saved = sqlx::query!("SELECT name, status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "Kunal Singh");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn subscribing_again_when_confirmed_succeeds_without_any_email() {
+    let test_app = spawn_app().await;
+    let body = "name=Kunal%20Singh&email=kunal%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app.post_subscriptions(body.into()).await;
+    let email_request = &test_app.email_requests(1).await[0];
+    let confirmation_link = test_app.get_confirmation_url(email_request).await;
+    reqwest::get(confirmation_link.html_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Same answer as for a new subscriber
+    let response = test_app
+        .post_subscriptions("name=Someone%20Else&email=kunal%40gmail.com".into())
+        .await;
+    assert_redirect_to(&response, "/subscription");
+
+    let saved = sqlx::query!("SELECT name, status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "Kunal Singh");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribing_again_after_unsubscribing_requires_a_new_confirmation() {
+    let test_app = spawn_app().await;
+    let body = "name=Kunal%20Singh&email=kunal%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app.post_subscriptions(body.into()).await;
+    test_app.email_requests(1).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    let response = test_app.post_subscriptions(body.into()).await;
+    assert_redirect_to(&response, "/subscription");
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+
+    let email_request = &test_app.email_requests(2).await[1];
+    let confirmation_link = test_app.get_confirmation_url(email_request).await;
+    let response = reqwest::get(confirmation_link.html_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -1,4 +1,4 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{assert_redirect_to, create_unconfirmed_subscriber, spawn_app};
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
 
@@ -27,7 +27,7 @@ pub async fn the_link_returned_by_subscribe_will_give_200_when_hit() {
 
     test_app.post_subscriptions(body.into()).await;
 
-    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let email_request = &test_app.email_requests(1).await[0];
     let confirmation_link = test_app.get_confirmation_url(email_request).await;
 
     let res = reqwest::get(confirmation_link.text_link).await.unwrap();
@@ -51,7 +51,7 @@ pub async fn clicking_confirmation_link_confirms_a_subscriber() {
 
     test_app.post_subscriptions(body.into()).await;
 
-    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let email_request = &test_app.email_requests(1).await[0];
     let confirmation_link = test_app.get_confirmation_url(email_request).await;
 
     reqwest::get(confirmation_link.html_link)
@@ -69,3 +69,155 @@ pub async fn clicking_confirmation_link_confirms_a_subscriber() {
     assert_eq!(saved.name, "A B");
     assert_eq!(saved.status, "confirmed");
 }
+
+async fn age_confirmation_tokens(test_app: &crate::helpers::TestApp, hours: i32) {
+    sqlx::query!(
+        "UPDATE subscription_tokens SET created_at = now() - make_interval(hours => $1)",
+        hours
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+}
+
+fn subscription_token(link: &reqwest::Url) -> String {
+    link.query_pairs()
+        .find(|(k, _)| k == "subscription_token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+#[tokio::test]
+async fn an_expired_link_offers_to_resend_a_confirmation_email() {
+    let test_app = spawn_app().await;
+    let confirmation_link = create_unconfirmed_subscriber(&test_app).await;
+    age_confirmation_tokens(&test_app, 49).await;
+
+    let response = reqwest::get(confirmation_link.html_link.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 410);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("This confirmation link has expired."));
+    assert!(html.contains(r#"action="/subscription/resend""#));
+    assert!(html.contains(&subscription_token(&confirmation_link.html_link)));
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn resending_a_confirmation_email_gives_a_new_working_link() {
+    let test_app = spawn_app().await;
+    let expired_link = create_unconfirmed_subscriber(&test_app).await;
+    age_confirmation_tokens(&test_app, 49).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_resend_confirmation(&subscription_token(&expired_link.html_link))
+        .await;
+    assert_redirect_to(&response, "/subscription");
+
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let new_link = test_app.get_confirmation_url(&email_request).await;
+    assert_ne!(new_link.html_link, expired_link.html_link);
+
+    // The expired link is gone for good
+    let response = reqwest::get(expired_link.html_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    let response = reqwest::get(new_link.html_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn resending_with_an_unknown_token_is_rejected() {
+    let test_app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app.post_resend_confirmation("not-a-token").await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_confirmation_link_can_only_be_used_once() {
+    let test_app = spawn_app().await;
+    let confirmation_link = create_unconfirmed_subscriber(&test_app).await;
+
+    let response = reqwest::get(confirmation_link.html_link.clone())
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = reqwest::get(confirmation_link.html_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 410);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("This confirmation link has already been used."));
+}
+
+#[tokio::test]
+async fn stale_pending_subscribers_are_deleted() {
+    let test_app = spawn_app().await;
+    create_unconfirmed_subscriber(&test_app).await;
+    // Signed up long ago, but asked for a new link recently
+    create_unconfirmed_subscriber(&test_app).await;
+    sqlx::query!("UPDATE subscriptions SET subscription_at = now() - interval '60 days'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!(
+        r#"
+        UPDATE subscription_tokens SET created_at = now() - interval '60 days'
+        WHERE subscriber_id = (SELECT id FROM subscriptions ORDER BY email LIMIT 1)
+    "#
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+    // Recent signup
+    create_unconfirmed_subscriber(&test_app).await;
+
+    assert_eq!(test_app.delete_stale_pending_subscribers().await, 1);
+
+    let n_subscribers = sqlx::query!(r#"SELECT count(*) as "count!" FROM subscriptions"#)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    let n_tokens = sqlx::query!(r#"SELECT count(*) as "count!" FROM subscription_tokens"#)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_subscribers, 2);
+    assert_eq!(n_tokens, 2);
+}
@@ -0,0 +1,251 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+use chrono::Utc;
+use newsletter::domain::DataRequestToken;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn subscriber_email(app: &TestApp) -> String {
+    sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email
+}
+
+// Ask for the data of the only subscriber and return the link of the email
+async fn request_data_link(app: &TestApp) -> reqwest::Url {
+    let email = subscriber_email(app).await;
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    let n_requests = app.email_server.received_requests().await.unwrap().len();
+    let response = app.post_data_request(&email).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The email is sent in the background
+    let email_request = app.email_requests(n_requests + 1).await.pop().unwrap();
+    app.get_confirmation_url(&email_request).await.text_link
+}
+
+fn with_path(link: &reqwest::Url, path: &str) -> reqwest::Url {
+    let mut link = link.clone();
+    link.set_path(path);
+    link
+}
+
+#[tokio::test]
+async fn subscribers_receive_a_link_to_manage_their_data() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let link = request_data_link(&app).await;
+    assert_eq!(link.path(), "/subscription/data/manage");
+
+    let response = reqwest::get(link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("/subscription/data/export?"));
+    assert!(html.contains("Erase my data"));
+}
+
+#[tokio::test]
+async fn unknown_emails_get_the_same_answer_without_an_email() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_data_request("nobody@example.com").await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("If this address is subscribed"));
+
+    let response = app.post_data_request("not-an-email").await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn failing_to_send_the_link_gets_the_same_answer() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_data_request(&subscriber_email(&app).await).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("If this address is subscribed"));
+}
+
+#[tokio::test]
+async fn the_export_contains_the_subscription_and_its_tokens() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    let link = request_data_link(&app).await;
+
+    let response = reqwest::get(with_path(&link, "/subscription/data/export"))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Disposition"],
+        "attachment; filename=\"my-data.json\""
+    );
+
+    let data: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(data["subscription"]["email"], email.as_str());
+    assert_eq!(data["subscription"]["status"], "confirmed");
+    let tokens = data["confirmation_tokens"].as_array().unwrap();
+    assert_eq!(tokens.len(), 1);
+    assert!(tokens[0]["consumed_at"].is_string());
+    assert_eq!(data["deliveries"], serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn erasing_the_data_deletes_the_subscriber_and_their_deliveries() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let link = request_data_link(&app).await;
+
+    // Queued, not delivered yet
+    app.test_user.login(&app).await;
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    let response = app
+        .api_client
+        .post(with_path(&link, "/subscription/data/erase"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("has been erased"));
+
+    let counts = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT count(*) FROM subscriptions) as "subscriptions!",
+            (SELECT count(*) FROM subscription_tokens) as "tokens!",
+            (SELECT count(*) FROM issue_delivery_queue) as "queued!"
+    "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(counts.subscriptions, 0);
+    assert_eq!(counts.tokens, 0);
+    assert_eq!(counts.queued, 0);
+
+    // The link does not lead to anything anymore
+    let response = reqwest::get(with_path(&link, "/subscription/data/export"))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn erasing_the_data_removes_the_email_from_the_import_reports() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    let link = request_data_link(&app).await;
+
+    // Both repeated rows end up in the error report
+    app.test_user.login(&app).await;
+    let csv = format!(
+        "email,name\n{email},Ursula\n{email},Ursula\nother@example.com,Other\nother@example.com,Other\n"
+    );
+    let response = app.post_subscriber_import("confirmed", &csv).await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    let response = app
+        .api_client
+        .post(with_path(&link, "/subscription/data/erase"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let error_report = sqlx::query!("SELECT error_report FROM subscriber_imports")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .error_report;
+    assert!(!error_report.contains(&email));
+    assert!(error_report.contains("3,,Duplicate of an earlier row"));
+    assert!(error_report.contains("5,other@example.com,Duplicate of an earlier row"));
+}
+
+#[tokio::test]
+async fn tampered_or_expired_links_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let link = request_data_link(&app).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    let subscriber_id = uuid::Uuid::from_bytes(*subscriber_id.as_bytes());
+
+    // Expiring later than what was signed
+    let mut tampered = link.clone();
+    let query: String = link
+        .query_pairs()
+        .map(|(k, v)| match k.as_ref() {
+            "expires_at" => format!("{}={}", k, v.parse::<i64>().unwrap() + 3600),
+            _ => format!("{}={}", k, v),
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+    tampered.set_query(Some(&query));
+
+    let expires_at = Utc::now().timestamp() - 60;
+    let token = DataRequestToken::generate(subscriber_id, expires_at, &app.hmac_secret.0);
+    let mut expired = link.clone();
+    expired.set_query(Some(&format!(
+        "subscriber_id={}&expires_at={}&token={}",
+        subscriber_id,
+        expires_at,
+        token.as_ref()
+    )));
+
+    for link in [tampered, expired] {
+        for path in ["/subscription/data/manage", "/subscription/data/export"] {
+            let response = reqwest::get(with_path(&link, path)).await.unwrap();
+            assert_eq!(response.status().as_u16(), 401);
+        }
+        let response = app
+            .api_client
+            .post(with_path(&link, "/subscription/data/erase"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 401);
+    }
+    // Still there
+    subscriber_email(&app).await;
+}
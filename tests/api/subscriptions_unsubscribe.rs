@@ -0,0 +1,168 @@
+use crate::helpers::{create_confirmed_subscriber, postmark_batch_accepted, spawn_app, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+async fn publish_newsletter(app: &TestApp) {
+    let newsletter_request_payload = serde_json::json!({
+        "title": "Title of Newsletter",
+        "text": "Newsletter plain body",
+        "html": "<h1>Newsletter html body</h1>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+
+    let response = app.post_newsletter(&newsletter_request_payload).await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    app.dispatch_all_emails().await;
+}
+
+// Deliver one issue to a single confirmed subscriber and return
+// the unsubscribe link found in the email
+async fn deliver_issue_and_get_unsubscribe_link(app: &TestApp) -> reqwest::Url {
+    create_confirmed_subscriber(app).await;
+    app.test_user.login(app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(postmark_batch_accepted())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(app).await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+
+    app.get_unsubscribe_link(&email_request)
+}
+
+#[tokio::test]
+async fn delivered_issues_contain_an_unsubscribe_link() {
+    let app = spawn_app().await;
+
+    let link = deliver_issue_and_get_unsubscribe_link(&app).await;
+
+    let response = reqwest::get(link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("Unsubscribe"));
+}
+
+#[tokio::test]
+async fn posting_to_the_unsubscribe_link_unsubscribes_the_subscriber() {
+    let app = spawn_app().await;
+    let link = deliver_issue_and_get_unsubscribe_link(&app).await;
+
+    let response = app.api_client.post(link).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn newsletter_is_not_delivered_to_unsubscribed_subscribers() {
+    let app = spawn_app().await;
+    let link = deliver_issue_and_get_unsubscribe_link(&app).await;
+
+    app.api_client
+        .post(link)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // The mock mounted before still expects exactly one request,
+    // the one made for the first issue.
+    publish_newsletter(&app).await;
+}
+
+#[tokio::test]
+async fn unsubscribe_with_a_tampered_token_is_rejected() {
+    let app = spawn_app().await;
+    let mut link = deliver_issue_and_get_unsubscribe_link(&app).await;
+
+    let subscriber_id = link
+        .query_pairs()
+        .find(|(k, _)| k == "subscriber_id")
+        .unwrap()
+        .1
+        .to_string();
+    link.set_query(Some(&format!(
+        "subscriber_id={}&token={}",
+        subscriber_id,
+        "0".repeat(64)
+    )));
+
+    let response = app.api_client.post(link).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn delivered_issues_carry_one_click_unsubscribe_headers() {
+    let app = spawn_app().await;
+    let link = deliver_issue_and_get_unsubscribe_link(&app).await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let header = |name: &str| {
+        body[0]["Headers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|h| h["Name"] == name)
+            .map(|h| h["Value"].as_str().unwrap().to_owned())
+            .unwrap()
+    };
+
+    let list_unsubscribe = header("List-Unsubscribe");
+    assert!(list_unsubscribe.contains("<mailto:"));
+    assert!(list_unsubscribe.contains(&format!("<{}>", link)));
+    assert_eq!(
+        header("List-Unsubscribe-Post"),
+        "List-Unsubscribe=One-Click"
+    );
+}
+
+#[tokio::test]
+async fn one_click_unsubscribe_works_without_a_session() {
+    let app = spawn_app().await;
+    let link = deliver_issue_and_get_unsubscribe_link(&app).await;
+
+    // What a mail client sends on behalf of the user, RFC 8058
+    let response = reqwest::Client::new()
+        .post(link)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "unsubscribed");
+}